@@ -0,0 +1,235 @@
+use crate::open_index_table::OpenIndexTable;
+use crate::sharded_index_table::shard_of;
+use std::vec::Vec;
+use tokio::sync::RwLock;
+
+// A shard on a cache line of its own.
+#[repr(align(64))]
+struct Shard(RwLock<OpenIndexTable>);
+
+// Table for async tasks, sharded like SyncIndexTable on tokio's RwLock. A
+// task waiting for a shard lock yields to the executor rather than blocking
+// its thread, and no lock is held across any other await: every operation
+// takes the lock, does its work and lets go. Iteration goes through
+// scan_chunks, which copies a chunk of entries per lock acquisition.
+//
+// The lock is fair, so a waiting writer goes ahead of readers arriving after
+// it, and it needs no tokio runtime, so the table works on any executor.
+pub struct AsyncIndexTable {
+  shards: Vec<Shard>,
+  shift: u32,
+}
+
+impl AsyncIndexTable {
+  // Creates a table of shards shards. Panics unless shards is a power of
+  // two.
+  pub fn new(shards: usize) -> AsyncIndexTable {
+    assert!(
+      shards.is_power_of_two(),
+      "shard count must be a power of two"
+    );
+    AsyncIndexTable {
+      shards: (0..shards)
+        .map(|_| Shard(RwLock::new(OpenIndexTable::new())))
+        .collect(),
+      shift: 64 - shards.trailing_zeros(),
+    }
+  }
+
+  pub fn shard_count(&self) -> usize {
+    self.shards.len()
+  }
+
+  fn shard(&self, key: u64) -> &RwLock<OpenIndexTable> {
+    &self.shards[shard_of(key, self.shift)].0
+  }
+
+  // Sum of the shard lengths, each read under its own lock, so with writers
+  // running the total need not match any single moment.
+  pub async fn len(&self) -> u64 {
+    let mut len = 0;
+    for shard in &self.shards {
+      len += shard.0.read().await.len();
+    }
+    len
+  }
+
+  pub async fn is_empty(&self) -> bool {
+    self.len().await == 0
+  }
+
+  pub async fn get(&self, key: u64) -> Option<u64> {
+    self.shard(key).read().await.get(key)
+  }
+
+  pub async fn insert(&self, key: u64, v: u64) {
+    self.shard(key).write().await.insert(key, v);
+  }
+
+  pub async fn delete(&self, key: u64) -> Option<u64> {
+    self.shard(key).write().await.delete(key)
+  }
+
+  // Walks the entries in chunks of about chunk_size, each copied out under
+  // one acquisition of a shard lock, so a consumer taking its time over a
+  // chunk holds up no writer. Entries present for the whole scan come out at
+  // least once, and exactly once unless their shard grows meanwhile; entries
+  // written during the scan may or may not show up. Panics if chunk_size is
+  // zero.
+  pub fn scan_chunks(&self, chunk_size: usize) -> ScanChunks<'_> {
+    assert!(chunk_size > 0, "chunk size must be non-zero");
+    ScanChunks {
+      table: self,
+      shard: 0,
+      cursor: 0,
+      chunk_size,
+    }
+  }
+}
+
+impl Default for AsyncIndexTable {
+  fn default() -> Self {
+    Self::new(16)
+  }
+}
+
+// Chunked walk of an AsyncIndexTable, see scan_chunks. Chunks keep the
+// entries of a home slot together, so they can run a little over
+// chunk_size, and never span two shards.
+pub struct ScanChunks<'a> {
+  table: &'a AsyncIndexTable,
+  shard: usize,
+  cursor: u64,
+  chunk_size: usize,
+}
+
+impl ScanChunks<'_> {
+  // The next chunk of entries, or None once the walk is done.
+  #[allow(clippy::should_implement_trait)]
+  pub async fn next(&mut self) -> Option<Vec<(u64, u64)>> {
+    while let Some(shard) = self.table.shards.get(self.shard) {
+      let mut chunk = Vec::new();
+      self.cursor = shard
+        .0
+        .read()
+        .await
+        .scan(self.cursor, self.chunk_size, &mut chunk);
+      if self.cursor == 0 {
+        self.shard += 1;
+      }
+      if !chunk.is_empty() {
+        return Some(chunk);
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_async_table_single_task() {
+  for shards in [1, 4] {
+    let table = AsyncIndexTable::new(shards);
+    assert!(table.is_empty().await);
+    for key in 0..100 {
+      table.insert(key, key + 1).await;
+    }
+    assert_eq!(table.delete(7).await, Some(8));
+    assert_eq!(table.delete(7).await, None);
+    assert_eq!(table.get(7).await, None);
+    assert_eq!(table.get(8).await, Some(9));
+    assert_eq!(table.len().await, 99);
+    let mut scan = table.scan_chunks(10);
+    let mut entries = Vec::new();
+    while let Some(chunk) = scan.next().await {
+      assert!(!chunk.is_empty());
+      entries.extend(chunk);
+    }
+    entries.sort();
+    assert!(entries
+      .into_iter()
+      .eq((0..100).filter(|key| *key != 7).map(|key| (key, key + 1))));
+  }
+}
+
+#[tokio::test]
+async fn test_async_table_waits_for_shard_lock() {
+  let table = Arc::new(AsyncIndexTable::new(1));
+  let mut held = table.shards[0].0.write().await;
+  let get = tokio::spawn({
+    let table = table.clone();
+    async move { table.get(1).await }
+  });
+  // The task runs up to the lock and yields instead of blocking the only
+  // thread of the runtime.
+  tokio::task::yield_now().await;
+  assert!(!get.is_finished());
+  held.insert(1, 2);
+  drop(held);
+  assert_eq!(get.await.unwrap(), Some(2));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_async_table_concurrent_tasks() {
+  let table = Arc::new(AsyncIndexTable::new(4));
+  let tasks: Vec<_> = (0..16u64)
+    .map(|task| {
+      let table = table.clone();
+      tokio::spawn(async move {
+        let keys = task * 1000..(task + 1) * 1000;
+        for key in keys.clone() {
+          table.insert(key, key + 1).await;
+        }
+        for key in keys.clone().step_by(2) {
+          assert_eq!(table.delete(key).await, Some(key + 1));
+        }
+        for key in keys {
+          let expected = (key % 2 == 1).then_some(key + 1);
+          assert_eq!(table.get(key).await, expected);
+        }
+      })
+    })
+    .collect();
+  for task in tasks {
+    task.await.unwrap();
+  }
+  assert_eq!(table.len().await, 8000);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_async_table_writers_progress_during_scan() {
+  let table = Arc::new(AsyncIndexTable::new(4));
+  for key in 0..10_000 {
+    table.insert(key, key).await;
+  }
+  let mut scan = table.scan_chunks(100);
+  let mut seen = scan.next().await.unwrap();
+  // The writers get through while the scan holds on to its first chunk.
+  let writers: Vec<_> = (0..4u64)
+    .map(|writer| {
+      let table = table.clone();
+      tokio::spawn(async move {
+        for key in 0..5000 {
+          table.insert(100_000 + writer * 5000 + key, key).await;
+        }
+      })
+    })
+    .collect();
+  for writer in writers {
+    writer.await.unwrap();
+  }
+  let mut chunks = 1;
+  while let Some(chunk) = scan.next().await {
+    chunks += 1;
+    seen.extend(chunk);
+  }
+  assert!(chunks > 4);
+  // Shards grew under the scan, which may repeat entries but misses none.
+  let mut keys: Vec<u64> = seen.into_iter().map(|(key, _)| key).collect();
+  keys.sort();
+  keys.dedup();
+  assert!((0..10_000).all(|key| keys.binary_search(&key).is_ok()));
+  assert_eq!(table.len().await, 30_000);
+}
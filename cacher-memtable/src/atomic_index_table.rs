@@ -0,0 +1,151 @@
+use crate::open_index_table::{heap_capacity_for, scramble};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+const EMPTY: u8 = 0;
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+
+// Fixed size table that can be shared between threads. Keys are never moved
+// or removed once inserted, so a key's slot is stable and its value can be
+// updated lock free.
+pub struct AtomicIndexTable {
+  states: Vec<AtomicU8>,
+  keys: Vec<AtomicU64>,
+  values: Vec<AtomicU64>,
+  mask: u64,
+  cap: u64,
+  size: AtomicU64,
+}
+
+impl AtomicIndexTable {
+  // Creates a table holding up to capacity keys.
+  pub fn new(capacity: u64) -> AtomicIndexTable {
+    let slots = heap_capacity_for(capacity as usize) as u64 >> 1;
+    AtomicIndexTable {
+      states: (0..slots).map(|_| AtomicU8::new(EMPTY)).collect(),
+      keys: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      values: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      mask: slots - 1,
+      cap: capacity,
+      size: AtomicU64::new(0),
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.size.load(Ordering::Acquire)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> u64 {
+    self.cap
+  }
+
+  // Returns the slot holding key. A slot that is being claimed by another
+  // insert is waited on, since it may be claimed for key.
+  fn find(&self, key: u64) -> Option<usize> {
+    let mut index = scramble(key) & self.mask;
+    loop {
+      let state = loop {
+        match self.states[index as usize].load(Ordering::Acquire) {
+          WRITING => core::hint::spin_loop(),
+          state => break state,
+        }
+      };
+      if state == EMPTY {
+        return None;
+      }
+      if self.keys[index as usize].load(Ordering::Relaxed) == key {
+        return Some(index as usize);
+      }
+      index = (index + 1) & self.mask;
+    }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    self
+      .find(key)
+      .map(|index| self.values[index].load(Ordering::Acquire))
+  }
+
+  // Stores v for key, returning false if key is new and the table is full.
+  pub fn insert(&self, key: u64, v: u64) -> bool {
+    let mut index = scramble(key) & self.mask;
+    loop {
+      let state = &self.states[index as usize];
+      match state.compare_exchange(EMPTY, WRITING, Ordering::Acquire, Ordering::Acquire) {
+        Ok(_) => {
+          if self.size.fetch_add(1, Ordering::AcqRel) >= self.cap {
+            self.size.fetch_sub(1, Ordering::AcqRel);
+            state.store(EMPTY, Ordering::Release);
+            return false;
+          }
+          self.keys[index as usize].store(key, Ordering::Relaxed);
+          self.values[index as usize].store(v, Ordering::Relaxed);
+          state.store(READY, Ordering::Release);
+          return true;
+        }
+        Err(WRITING) => core::hint::spin_loop(),
+        Err(_) => {
+          if self.keys[index as usize].load(Ordering::Relaxed) == key {
+            self.values[index as usize].store(v, Ordering::Release);
+            return true;
+          }
+          index = (index + 1) & self.mask;
+        }
+      }
+    }
+  }
+
+  // Replaces the value of key with new if it currently equals expected,
+  // otherwise returns the current value.
+  //
+  // Panics if key is not in the table.
+  pub fn compare_and_swap(&self, key: u64, expected: u64, new: u64) -> Result<(), u64> {
+    let index = self.find(key).expect("key is not in the table");
+    self.values[index]
+      .compare_exchange(expected, new, Ordering::AcqRel, Ordering::Acquire)
+      .map(|_| ())
+  }
+}
+
+#[test]
+fn test_atomic_table_insert_get() {
+  let table = AtomicIndexTable::new(100);
+  assert!(table.is_empty());
+  for i in 0..100 {
+    assert!(table.insert(i, i * 2));
+  }
+  assert!(!table.insert(100, 1));
+  assert!(table.insert(5, 11));
+  assert_eq!(table.len(), 100);
+  for i in 0..100 {
+    assert_eq!(table.get(i), Some(if i == 5 { 11 } else { i * 2 }));
+  }
+  assert_eq!(table.get(100), None);
+  assert_eq!(table.compare_and_swap(0, 1, 2), Err(0));
+  assert_eq!(table.compare_and_swap(0, 0, 2), Ok(()));
+  assert_eq!(table.get(0), Some(2));
+}
+
+#[test]
+fn test_atomic_table_concurrent_cas_increments() {
+  let table = AtomicIndexTable::new(16);
+  table.insert(7, 0);
+  std::thread::scope(|scope| {
+    for _ in 0..8 {
+      scope.spawn(|| {
+        for _ in 0..10_000 {
+          let mut current = table.get(7).unwrap();
+          while let Err(actual) = table.compare_and_swap(7, current, current + 1) {
+            current = actual;
+          }
+        }
+      });
+    }
+  });
+  assert_eq!(table.get(7), Some(80_000));
+}
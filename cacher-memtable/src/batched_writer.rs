@@ -0,0 +1,219 @@
+use crate::sync_index_table::SyncIndexTable;
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::vec::Vec;
+#[cfg(test)]
+use test::Bencher;
+
+enum Op {
+  Insert(u64, u64),
+  Delete(u64),
+  // Answered once every operation queued before it is applied.
+  Flush(Sender<()>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteError {
+  // The queue is full; only returned by the try_ methods.
+  Full,
+  // The consumer thread is gone, having panicked.
+  Closed,
+}
+
+// Write combining front end for a SyncIndexTable. Producers queue inserts
+// and deletes on a bounded channel, and a consumer thread applies whatever
+// has piled up in one write_batch, paying for the shard locks once per
+// batch instead of once per write. Writes from one producer are applied in
+// the order they were queued. Reads go straight to the table and see a
+// write only once it is applied; flush waits for that.
+pub struct BatchedWriter {
+  sender: Option<SyncSender<Op>>,
+  table: Arc<SyncIndexTable>,
+  consumer: Option<JoinHandle<()>>,
+}
+
+impl BatchedWriter {
+  // Starts the consumer thread for table, queueing up to capacity writes.
+  // Batches hold at most capacity writes too. Panics if capacity is zero.
+  pub fn new(table: Arc<SyncIndexTable>, capacity: usize) -> BatchedWriter {
+    assert!(capacity > 0, "capacity must be non-zero");
+    let (sender, receiver) = mpsc::sync_channel(capacity);
+    let consumer = {
+      let table = table.clone();
+      std::thread::spawn(move || apply_batches(&table, &receiver, capacity))
+    };
+    BatchedWriter {
+      sender: Some(sender),
+      table,
+      consumer: Some(consumer),
+    }
+  }
+
+  pub fn table(&self) -> &SyncIndexTable {
+    &self.table
+  }
+
+  fn send(&self, op: Op) -> Result<(), WriteError> {
+    let sender = self.sender.as_ref().unwrap();
+    sender.send(op).map_err(|_| WriteError::Closed)
+  }
+
+  fn try_send(&self, op: Op) -> Result<(), WriteError> {
+    match self.sender.as_ref().unwrap().try_send(op) {
+      Ok(()) => Ok(()),
+      Err(TrySendError::Full(_)) => Err(WriteError::Full),
+      Err(TrySendError::Disconnected(_)) => Err(WriteError::Closed),
+    }
+  }
+
+  // Queues an insert, blocking while the queue is full.
+  pub fn insert(&self, key: u64, v: u64) -> Result<(), WriteError> {
+    self.send(Op::Insert(key, v))
+  }
+
+  // Queues a delete, blocking while the queue is full.
+  pub fn delete(&self, key: u64) -> Result<(), WriteError> {
+    self.send(Op::Delete(key))
+  }
+
+  pub fn try_insert(&self, key: u64, v: u64) -> Result<(), WriteError> {
+    self.try_send(Op::Insert(key, v))
+  }
+
+  pub fn try_delete(&self, key: u64) -> Result<(), WriteError> {
+    self.try_send(Op::Delete(key))
+  }
+
+  // Returns once every write this thread queued before the call is applied
+  // to the table, so reads after it see them, along with whatever other
+  // threads had queued by then.
+  pub fn flush(&self) -> Result<(), WriteError> {
+    let (done, applied) = mpsc::channel();
+    self.send(Op::Flush(done))?;
+    applied.recv().map_err(|_| WriteError::Closed)
+  }
+}
+
+// Applies queued writes until every sender is gone, batching whatever is
+// queued by the time the previous batch is done.
+fn apply_batches(table: &SyncIndexTable, receiver: &Receiver<Op>, max_batch: usize) {
+  let mut ops = Vec::with_capacity(max_batch);
+  let mut flushes = Vec::new();
+  while let Ok(op) = receiver.recv() {
+    ops.push(op);
+    ops.extend(receiver.try_iter().take(max_batch - 1));
+    table.write_batch(|batch| {
+      for op in ops.drain(..) {
+        match op {
+          Op::Insert(key, v) => batch.insert(key, v),
+          Op::Delete(key) => {
+            batch.delete(key);
+          }
+          Op::Flush(done) => flushes.push(done),
+        }
+      }
+    });
+    // Only now that the batch is unlocked can readers see it.
+    for done in flushes.drain(..) {
+      let _ = done.send(());
+    }
+  }
+}
+
+impl Drop for BatchedWriter {
+  // Applies the writes still queued and stops the consumer thread.
+  fn drop(&mut self) {
+    drop(self.sender.take());
+    if let Some(consumer) = self.consumer.take() {
+      let _ = consumer.join();
+    }
+  }
+}
+
+#[test]
+fn test_batched_writer_applies_in_order() {
+  let writer = BatchedWriter::new(Arc::new(SyncIndexTable::new(4)), 64);
+  std::thread::scope(|scope| {
+    for producer in 0..4u64 {
+      let writer = &writer;
+      scope.spawn(move || {
+        let keys = producer * 100..(producer + 1) * 100;
+        for round in 0..50 {
+          for key in keys.clone() {
+            if round % 7 == 6 {
+              writer.delete(key).unwrap();
+            } else {
+              writer.insert(key, round).unwrap();
+            }
+          }
+        }
+        // Read your writes: the last round wrote every key.
+        writer.flush().unwrap();
+        assert!(keys.clone().all(|key| writer.table().get(key) == Some(49)));
+        for key in keys.clone().step_by(2) {
+          writer.delete(key).unwrap();
+        }
+        writer.flush().unwrap();
+        assert!(keys.step_by(2).all(|key| writer.table().get(key).is_none()));
+      });
+    }
+  });
+  assert_eq!(writer.table().len(), 200);
+}
+
+#[test]
+fn test_batched_writer_backpressure() {
+  let table = Arc::new(SyncIndexTable::new(4));
+  let writer = BatchedWriter::new(table.clone(), 8);
+  // With every shard locked the consumer is stuck on its first batch, so
+  // the queue fills up behind it.
+  let queued = table.write_batch(|_| {
+    (0..100)
+      .take_while(|key| writer.try_insert(*key, *key) != Err(WriteError::Full))
+      .count()
+  });
+  assert!((8..=16).contains(&queued));
+  writer.flush().unwrap();
+  assert_eq!(table.len(), queued as u64);
+  // Dropping the writer applies what is still queued.
+  for key in 100..200 {
+    writer.insert(key, key).unwrap();
+  }
+  drop(writer);
+  assert_eq!(table.len(), queued as u64 + 100);
+}
+
+// Producer threads each writing 4096 keys, through a BatchedWriter or with a
+// shard lock per insert.
+#[cfg(test)]
+fn bench_producers(b: &mut Bencher, insert: &(dyn Fn(u64) + Sync), flush: &(dyn Fn() + Sync)) {
+  b.iter(|| {
+    std::thread::scope(|scope| {
+      for producer in 0..8u64 {
+        scope.spawn(move || {
+          for key in producer << 12..(producer + 1) << 12 {
+            insert(key);
+          }
+        });
+      }
+    });
+    flush();
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_batched_writer_producers(b: &mut Bencher) {
+  let writer = BatchedWriter::new(Arc::new(SyncIndexTable::new(16)), 1024);
+  bench_producers(b, &|key| writer.insert(key, key).unwrap(), &|| {
+    writer.flush().unwrap()
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_per_op_lock_producers(b: &mut Bencher) {
+  let table = SyncIndexTable::new(16);
+  bench_producers(b, &|key| table.insert(key, key), &|| {});
+}
@@ -0,0 +1,163 @@
+use crate::open_index_table::{scramble, OpenIndexTable};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+// Bloom filter with a small counter per cell instead of a bit so keys can be
+// removed again. Counters saturate and are then never decremented, which keeps
+// the filter free of false negatives.
+pub struct CountingBloomFilter {
+  counters: Vec<u8>,
+  seeds: Vec<u64>,
+  mask: u64,
+}
+
+impl CountingBloomFilter {
+  // cells is rounded up to the next power of two.
+  pub fn new(cells: u64, hashes: u64) -> CountingBloomFilter {
+    assert!(cells > 0 && hashes > 0, "cells and hashes must be non zero");
+    let cells = cells.next_power_of_two();
+    CountingBloomFilter {
+      counters: vec![0; cells as usize],
+      seeds: (1..=hashes).map(|i| scramble(i) | 1).collect(),
+      mask: cells - 1,
+    }
+  }
+
+  // Sized for roughly a 1% false positive rate at expected_items keys.
+  pub fn with_expected_items(expected_items: u64) -> CountingBloomFilter {
+    CountingBloomFilter::new(expected_items.max(1) * 10, 7)
+  }
+
+  fn index(&self, seed: u64, key: u64) -> usize {
+    let hash = scramble(key ^ seed);
+    ((hash ^ (hash >> 32)) & self.mask) as usize
+  }
+
+  pub fn insert(&mut self, key: u64) {
+    for i in 0..self.seeds.len() {
+      let index = self.index(self.seeds[i], key);
+      self.counters[index] = self.counters[index].saturating_add(1);
+    }
+  }
+
+  // Must only be called for keys that were inserted before.
+  pub fn remove(&mut self, key: u64) {
+    for i in 0..self.seeds.len() {
+      let index = self.index(self.seeds[i], key);
+      if self.counters[index] != u8::MAX {
+        self.counters[index] -= 1;
+      }
+    }
+  }
+
+  pub fn may_contain(&self, key: u64) -> bool {
+    self
+      .seeds
+      .iter()
+      .all(|seed| self.counters[self.index(*seed, key)] > 0)
+  }
+
+  pub fn clear(&mut self) {
+    self.counters.fill(0);
+  }
+}
+
+// OpenIndexTable gated by a counting bloom filter so lookups of absent keys
+// usually return without walking a probe chain.
+pub struct BloomIndexTable {
+  table: OpenIndexTable,
+  filter: CountingBloomFilter,
+  skipped: Cell<u64>,
+}
+
+impl BloomIndexTable {
+  pub fn new(expected_items: u64) -> BloomIndexTable {
+    BloomIndexTable {
+      table: OpenIndexTable::new(),
+      filter: CountingBloomFilter::with_expected_items(expected_items),
+      skipped: Cell::new(0),
+    }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    if !self.filter.may_contain(key) {
+      self.skipped.set(self.skipped.get() + 1);
+      return None;
+    }
+    self.table.get(key)
+  }
+
+  pub fn insert(&mut self, key: u64, v: u64) {
+    if self.table.insert(key, v).is_none() {
+      self.filter.insert(key);
+    }
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    let deleted = self.table.delete(key);
+    if deleted.is_some() {
+      self.filter.remove(key);
+    }
+    deleted
+  }
+
+  // Number of lookups answered by the filter alone.
+  pub fn skipped_probes(&self) -> u64 {
+    self.skipped.get()
+  }
+}
+
+#[test]
+fn test_counting_bloom_filter() {
+  let mut filter = CountingBloomFilter::with_expected_items(100);
+  for key in 0..100 {
+    filter.insert(key);
+  }
+  for key in 0..100 {
+    assert!(filter.may_contain(key));
+  }
+  for key in 0..50 {
+    filter.remove(key);
+  }
+  for key in 50..100 {
+    assert!(filter.may_contain(key));
+  }
+  let false_positives = (0..50).filter(|key| filter.may_contain(*key)).count();
+  assert!(false_positives < 5, "{} false positives", false_positives);
+  filter.clear();
+  assert!(!filter.may_contain(75));
+}
+
+#[test]
+fn test_bloom_table_skips_absent_keys() {
+  let mut table = BloomIndexTable::new(1000);
+  for key in 1..=1000 {
+    table.insert(key, key * 2);
+  }
+  for key in 1..=1000 {
+    assert_eq!(table.get(key), Some(key * 2));
+  }
+  assert_eq!(table.skipped_probes(), 0);
+  let misses = 100_000;
+  for key in 1_000_000..1_000_000 + misses {
+    assert_eq!(table.get(key), None);
+  }
+  assert!(
+    table.skipped_probes() > misses * 95 / 100,
+    "only {} skipped",
+    table.skipped_probes()
+  );
+}
+
+#[test]
+fn test_bloom_table_delete() {
+  let mut table = BloomIndexTable::new(100);
+  table.insert(5, 50);
+  table.insert(5, 51);
+  table.insert(6, 60);
+  assert_eq!(table.delete(5), Some(51));
+  assert_eq!(table.delete(5), None);
+  assert_eq!(table.get(5), None);
+  assert_eq!(table.get(6), Some(60));
+}
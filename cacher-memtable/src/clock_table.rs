@@ -0,0 +1,206 @@
+use crate::open_index_table::OpenIndexTable;
+use alloc::vec::Vec;
+
+// Bounded cache approximating LRU with the clock (second chance) algorithm.
+// Entries sit in a ring of capacity slots, each with a reference bit that
+// get sets. To make room, a hand sweeps the ring clearing set bits and
+// evicts the first entry whose bit was already clear, so an entry read
+// since the hand last passed survives one more turn. The table maps keys to
+// their slot in the ring.
+pub struct ClockTable {
+  table: OpenIndexTable,
+  keys: Vec<u64>,
+  values: Vec<u64>,
+  referenced: Vec<u64>,
+  free_slots: Vec<u64>,
+  hand: u64,
+  capacity: u64,
+}
+
+impl ClockTable {
+  pub fn new(capacity: u64) -> ClockTable {
+    assert!(capacity > 0, "capacity must be non zero");
+    ClockTable {
+      table: OpenIndexTable::new(),
+      keys: Vec::new(),
+      values: Vec::new(),
+      referenced: Vec::new(),
+      free_slots: Vec::new(),
+      hand: 0,
+      capacity,
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.keys.len() as u64 - self.free_slots.len() as u64
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> u64 {
+    self.capacity
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.table.get(key).is_some()
+  }
+
+  // Reads the value of key without setting its reference bit.
+  pub fn peek(&self, key: u64) -> Option<u64> {
+    let slot = self.table.get(key)?;
+    Some(self.values[slot as usize])
+  }
+
+  fn is_referenced(&self, slot: u64) -> bool {
+    self.referenced[(slot / 64) as usize] & (1 << (slot % 64)) != 0
+  }
+
+  fn set_referenced(&mut self, slot: u64, referenced: bool) {
+    let word = &mut self.referenced[(slot / 64) as usize];
+    if referenced {
+      *word |= 1 << (slot % 64);
+    } else {
+      *word &= !(1 << (slot % 64));
+    }
+  }
+
+  pub fn get(&mut self, key: u64) -> Option<u64> {
+    let slot = self.table.get(key)?;
+    self.set_referenced(slot, true);
+    Some(self.values[slot as usize])
+  }
+
+  // Inserts or updates key, returning the entry evicted to make room. A new
+  // entry starts unreferenced, so it is the first to go unless read before
+  // the hand comes around.
+  pub fn insert(&mut self, key: u64, value: u64) -> Option<(u64, u64)> {
+    if let Some(slot) = self.table.get(key) {
+      self.values[slot as usize] = value;
+      self.set_referenced(slot, true);
+      return None;
+    }
+    let (slot, evicted) = self.free_slot();
+    self.keys[slot as usize] = key;
+    self.values[slot as usize] = value;
+    self.set_referenced(slot, false);
+    self.table.insert(key, slot);
+    evicted
+  }
+
+  // Returns the value of key, first inserting default if it is missing and
+  // evicting an entry if the cache is full. Either way key counts as
+  // referenced.
+  pub fn get_or_insert(&mut self, key: u64, default: u64) -> u64 {
+    if let Some(value) = self.get(key) {
+      return value;
+    }
+    self.insert(key, default);
+    let slot = self.table.get(key).unwrap();
+    self.set_referenced(slot, true);
+    default
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    let slot = self.table.delete(key)?;
+    self.set_referenced(slot, false);
+    self.free_slots.push(slot);
+    Some(self.values[slot as usize])
+  }
+
+  // Finds a slot for a new entry: a deleted one, a fresh one while the ring
+  // is below capacity, or the next victim of the hand.
+  fn free_slot(&mut self) -> (u64, Option<(u64, u64)>) {
+    if let Some(slot) = self.free_slots.pop() {
+      return (slot, None);
+    }
+    let slot = self.keys.len() as u64;
+    if slot < self.capacity {
+      self.keys.push(0);
+      self.values.push(0);
+      if slot.is_multiple_of(64) {
+        self.referenced.push(0);
+      }
+      return (slot, None);
+    }
+    let victim = self.sweep();
+    let (key, value) = (self.keys[victim as usize], self.values[victim as usize]);
+    self.table.delete(key);
+    (victim, Some((key, value)))
+  }
+
+  // Advances the hand to the first unreferenced entry, clearing the bits it
+  // passes. Ends within one turn plus a slot, since a full turn clears every
+  // bit. Only called with the ring full, so every slot holds an entry.
+  fn sweep(&mut self) -> u64 {
+    loop {
+      let slot = self.hand;
+      self.hand = (self.hand + 1) % self.capacity;
+      if !self.is_referenced(slot) {
+        return slot;
+      }
+      self.set_referenced(slot, false);
+    }
+  }
+}
+
+#[test]
+fn test_clock_table() {
+  let mut table = ClockTable::new(3);
+  assert!(table.is_empty());
+  assert_eq!(table.insert(1, 10), None);
+  assert_eq!(table.insert(2, 20), None);
+  assert_eq!(table.insert(2, 21), None);
+  assert_eq!(table.get(2), Some(21));
+  assert_eq!(table.peek(1), Some(10));
+  assert_eq!(table.delete(1), Some(10));
+  assert_eq!(table.delete(1), None);
+  // The deleted slot is reused before anything is evicted.
+  assert_eq!(table.insert(3, 30), None);
+  assert_eq!(table.insert(4, 40), None);
+  assert_eq!(table.len(), 3);
+  assert_eq!(table.get_or_insert(3, 0), 30);
+  assert_eq!(table.get_or_insert(5, 50), 50);
+  assert_eq!(table.len(), 3);
+  assert!(table.contains_key(5));
+  assert_eq!(table.peek(4), None);
+}
+
+#[test]
+fn test_clock_referenced_entries_survive_eviction() {
+  let mut table = ClockTable::new(100);
+  for key in 0..100 {
+    table.insert(key, key);
+  }
+  // Touch every even key, then push a full round of new keys through.
+  for key in (0..100).step_by(2) {
+    assert_eq!(table.get(key), Some(key));
+  }
+  let mut evicted = Vec::new();
+  for key in 100..150 {
+    evicted.push(table.insert(key, key).unwrap().0);
+  }
+  evicted.sort();
+  assert!(evicted.iter().copied().eq((1..100).step_by(2)));
+  assert!((0..100).step_by(2).all(|key| table.peek(key) == Some(key)));
+  // The hand cleared the survivors' bits on the way, so without another
+  // read they go next, before the entries that were just inserted and read.
+  for key in 100..150 {
+    table.get(key);
+  }
+  let evicted: Vec<u64> = (150..200)
+    .map(|key| table.insert(key, key).unwrap().0)
+    .collect();
+  assert!(evicted.iter().all(|key| *key < 100 && key % 2 == 0));
+  assert!((100..150).all(|key| table.contains_key(key)));
+}
+
+#[test]
+fn test_clock_get_or_insert_marks_referenced() {
+  let mut table = ClockTable::new(2);
+  table.get_or_insert(1, 1);
+  table.insert(2, 2);
+  assert_eq!(table.insert(3, 3), Some((2, 2)));
+  assert!(table.contains_key(1));
+}
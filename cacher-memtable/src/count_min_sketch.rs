@@ -0,0 +1,135 @@
+use crate::open_index_table::scramble;
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub struct CountMinSketch {
+  counters: Vec<u64>,
+  seeds: Vec<u64>,
+  width: u64,
+  width_mask: u64,
+  depth: u64,
+}
+
+impl CountMinSketch {
+  // width is rounded up to the next power of two so rows can be indexed by mask.
+  pub fn new(width: u64, depth: u64) -> CountMinSketch {
+    assert!(width > 0 && depth > 0, "width and depth must be non zero");
+    let width = width.next_power_of_two();
+    CountMinSketch {
+      counters: vec![0; (width * depth) as usize],
+      seeds: (1..=depth).map(|row| scramble(row) | 1).collect(),
+      width,
+      width_mask: width - 1,
+      depth,
+    }
+  }
+
+  pub fn width(&self) -> u64 {
+    self.width
+  }
+
+  pub fn depth(&self) -> u64 {
+    self.depth
+  }
+
+  fn index(&self, row: u64, key: u64) -> usize {
+    let hash = scramble(key ^ self.seeds[row as usize]);
+    (row * self.width + ((hash ^ (hash >> 32)) & self.width_mask)) as usize
+  }
+
+  pub fn add(&mut self, key: u64) {
+    for row in 0..self.depth {
+      let index = self.index(row, key);
+      self.counters[index] = self.counters[index].saturating_add(1);
+    }
+  }
+
+  pub fn estimate(&self, key: u64) -> u64 {
+    let mut min = u64::MAX;
+    for row in 0..self.depth {
+      min = min.min(self.counters[self.index(row, key)]);
+    }
+    min
+  }
+
+  // Ages the sketch so old popularity decays relative to recent accesses.
+  pub fn halve(&mut self) {
+    for counter in self.counters.iter_mut() {
+      *counter >>= 1;
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.counters.fill(0);
+  }
+}
+
+#[test]
+fn test_sketch_never_underreports() {
+  let mut sketch = CountMinSketch::new(64, 4);
+  let mut counts = std::collections::HashMap::new();
+  for i in 0..10_000u64 {
+    let key = (i * 7919) % 1000;
+    sketch.add(key);
+    *counts.entry(key).or_insert(0) += 1;
+  }
+  for (key, count) in counts {
+    assert!(sketch.estimate(key) >= count);
+  }
+}
+
+#[test]
+fn test_sketch_skewed_error_bound() {
+  let width = 1024;
+  let mut sketch = CountMinSketch::new(width, 4);
+  let mut counts = std::collections::HashMap::new();
+  let mut total = 0;
+  // Key k is added roughly n / k times, giving a Zipf-like distribution.
+  for key in 1..=5_000u64 {
+    let n = 10_000 / key + 1;
+    for _ in 0..n {
+      sketch.add(key);
+    }
+    counts.insert(key, n);
+    total += n;
+  }
+  // With width w the overestimate is at most e / w * total with high probability.
+  let bound = (std::f64::consts::E / width as f64 * total as f64) as u64;
+  let mut over_bound = 0;
+  for (key, count) in &counts {
+    let estimate = sketch.estimate(*key);
+    assert!(estimate >= *count);
+    if estimate - count > bound {
+      over_bound += 1;
+    }
+  }
+  assert!(
+    over_bound * 100 <= counts.len(),
+    "{} estimates over bound",
+    over_bound
+  );
+}
+
+#[test]
+fn test_sketch_clear() {
+  let mut sketch = CountMinSketch::new(100, 3);
+  assert_eq!(sketch.width(), 128);
+  assert_eq!(sketch.depth(), 3);
+  sketch.add(42);
+  sketch.add(42);
+  assert_eq!(sketch.estimate(42), 2);
+  sketch.clear();
+  assert_eq!(sketch.estimate(42), 0);
+}
+
+#[test]
+fn test_sketch_halve() {
+  let mut sketch = CountMinSketch::new(64, 4);
+  for _ in 0..9 {
+    sketch.add(7);
+  }
+  sketch.halve();
+  assert_eq!(sketch.estimate(7), 4);
+  sketch.halve();
+  assert_eq!(sketch.estimate(7), 2);
+}
@@ -0,0 +1,132 @@
+use crate::open_index_table::{Iter, OpenIndexTable};
+
+// Map from keys to counts. Keys with a zero count are not stored, so count
+// returns 0 for them and iter_counts skips them.
+pub struct CounterTable {
+  table: OpenIndexTable,
+  total: u64,
+}
+
+impl Default for CounterTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl CounterTable {
+  pub fn new() -> CounterTable {
+    CounterTable {
+      table: OpenIndexTable::new(),
+      total: 0,
+    }
+  }
+
+  // Number of keys with a non zero count.
+  pub fn len(&self) -> u64 {
+    self.table.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Sum of all counts.
+  pub fn total(&self) -> u64 {
+    self.total
+  }
+
+  pub fn count(&self, key: u64) -> u64 {
+    self.table.get_or(key, 0)
+  }
+
+  // Adds n to the count of key, returning the new count.
+  pub fn add(&mut self, key: u64, n: u64) -> u64 {
+    let previous = self
+      .table
+      .fetch_update(key, |count| match count.unwrap_or(0) + n {
+        0 => None,
+        count => Some(count),
+      })
+      .unwrap_or(0);
+    self.total += n;
+    previous + n
+  }
+
+  // Subtracts n from the count of key, saturating at zero, and returns the
+  // new count. The key is removed once its count reaches zero.
+  pub fn sub(&mut self, key: u64, n: u64) -> u64 {
+    let previous = self
+      .table
+      .fetch_update(key, |count| match count?.saturating_sub(n) {
+        0 => None,
+        count => Some(count),
+      })
+      .unwrap_or(0);
+    let removed = previous.min(n);
+    self.total -= removed;
+    previous - removed
+  }
+
+  pub fn iter_counts(&self) -> Iter<'_> {
+    self.table.iter()
+  }
+}
+
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use std::collections::HashMap;
+
+#[test]
+fn test_counter_table_add_sub() {
+  let mut counter = CounterTable::new();
+  assert_eq!(counter.count(1), 0);
+  assert_eq!(counter.add(1, 3), 3);
+  assert_eq!(counter.add(1, 2), 5);
+  assert_eq!(counter.add(0, 1), 1);
+  assert_eq!(counter.add(2, 0), 0);
+  assert_eq!(counter.len(), 2);
+  assert_eq!(counter.total(), 6);
+  assert_eq!(counter.sub(1, 4), 1);
+  assert_eq!(counter.sub(1, 4), 0);
+  assert_eq!(counter.sub(7, 4), 0);
+  assert_eq!(counter.count(1), 0);
+  assert_eq!(counter.len(), 1);
+  assert_eq!(counter.total(), 1);
+  assert_eq!(counter.iter_counts().collect::<Vec<_>>(), [(0, 1)]);
+}
+
+#[test]
+fn test_counter_table_matches_hash_map() {
+  let mut state = 0x9E37_79B9_7F4A_7C15u64;
+  let mut random = move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+  };
+  for round in 0..20 {
+    let mut counter = CounterTable::new();
+    let mut model: HashMap<u64, u64> = HashMap::new();
+    let keys = 1 + round * 50;
+    for _ in 0..5_000 {
+      let key = random() % keys;
+      let n = random() % 8;
+      if random() % 2 == 0 {
+        let count = model.entry(key).or_insert(0);
+        *count += n;
+        assert_eq!(counter.add(key, n), *count);
+      } else {
+        let count = model.get(&key).copied().unwrap_or(0).saturating_sub(n);
+        model.insert(key, count);
+        assert_eq!(counter.sub(key, n), count);
+      }
+      model.retain(|_, count| *count != 0);
+    }
+    assert_eq!(counter.len(), model.len() as u64);
+    assert_eq!(counter.total(), model.values().sum::<u64>());
+    for (key, count) in counter.iter_counts() {
+      assert_eq!(model.get(&key), Some(&count));
+    }
+  }
+}
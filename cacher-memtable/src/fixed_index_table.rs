@@ -0,0 +1,181 @@
+use crate::open_index_table::scramble;
+use crate::probing::LinearProbe;
+
+// Returned by FixedIndexTable::insert when a new key would push the table past
+// its fill limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Full;
+
+// OpenIndexTable with N slots stored inline, for targets without an
+// allocator. It never grows: once 87.5% of the slots are taken, inserting a
+// new key fails. The slots are laid out like the [u64; 2 * N] array of the
+// growable table; they are spelled as pairs only because array lengths cannot
+// be computed from N.
+pub struct FixedIndexTable<const N: usize> {
+  slots: [[u64; 2]; N],
+  occupied: [bool; N],
+  size: u64,
+}
+
+impl<const N: usize> FixedIndexTable<N> {
+  const POWER_OF_TWO: () = assert!(N.is_power_of_two(), "N must be a power of two");
+  // Keeps at least one slot free so every probe ends.
+  const LIMIT: u64 = (N - N.div_ceil(8)) as u64;
+
+  pub const fn new() -> FixedIndexTable<N> {
+    #[allow(clippy::let_unit_value)]
+    let () = Self::POWER_OF_TWO;
+    FixedIndexTable {
+      slots: [[0; 2]; N],
+      occupied: [false; N],
+      size: 0,
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  // Number of entries the table holds before insert reports Full.
+  pub fn capacity(&self) -> u64 {
+    Self::LIMIT
+  }
+
+  fn index(&self, k: u64) -> u64 {
+    (scramble(k) & (N as u64 - 1)) << 1
+  }
+
+  fn find(&self, key: u64) -> (u64, bool) {
+    self.probe(self.index(key), |index| {
+      self.slots[(index >> 1) as usize][0] == key
+    })
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => Some(self.slots[(index >> 1) as usize][1]),
+      _ => None,
+    }
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.find(key).1
+  }
+
+  // Inserts or updates key. Updating always succeeds, a new key fails with
+  // Full once the table is at its fill limit.
+  pub fn insert(&mut self, key: u64, v: u64) -> Result<(), Full> {
+    let (index, found) = self
+      .try_probe(self.index(key), |index| {
+        self.slots[(index >> 1) as usize][0] == key
+      })
+      .ok_or(Full)?;
+    let slot = (index >> 1) as usize;
+    if !found {
+      if self.size == Self::LIMIT {
+        return Err(Full);
+      }
+      self.size += 1;
+      self.slots[slot][0] = key;
+      self.occupied[slot] = true;
+    }
+    self.slots[slot][1] = v;
+    Ok(())
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => {
+        let v = self.slots[(index >> 1) as usize][1];
+        self.size -= 1;
+        self.unshift(index);
+        Some(v)
+      }
+      _ => None,
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.occupied = [false; N];
+    self.size = 0;
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+    self
+      .slots
+      .iter()
+      .zip(&self.occupied)
+      .filter(|(_, occupied)| **occupied)
+      .map(|(slot, _)| (slot[0], slot[1]))
+  }
+}
+
+impl<const N: usize> LinearProbe for FixedIndexTable<N> {
+  fn data_mask(&self) -> u64 {
+    (2 * N - 1) as u64
+  }
+
+  fn slot_occupied(&self, index: u64) -> bool {
+    self.occupied[(index >> 1) as usize]
+  }
+
+  fn home_of(&self, index: u64) -> u64 {
+    self.index(self.slots[(index >> 1) as usize][0])
+  }
+
+  fn vacate(&mut self, index: u64) {
+    self.occupied[(index >> 1) as usize] = false;
+  }
+
+  fn move_slot(&mut self, from: u64, to: u64) {
+    self.slots[(to >> 1) as usize] = self.slots[(from >> 1) as usize];
+  }
+}
+
+impl<const N: usize> Default for FixedIndexTable<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+index_table_tests!(FixedIndexTable::<8192>::new(), |k: u64| k);
+
+#[test]
+fn test_fixed_table_full() {
+  let mut table = FixedIndexTable::<16>::new();
+  assert_eq!(table.capacity(), 14);
+  for key in 0..14 {
+    assert_eq!(table.insert(key, key), Ok(()));
+  }
+  assert_eq!(table.insert(14, 14), Err(Full));
+  assert_eq!(table.get(14), None);
+  // Existing keys can still be updated, and deleting makes room again.
+  assert_eq!(table.insert(3, 30), Ok(()));
+  assert_eq!(table.get(3), Some(30));
+  assert_eq!(table.delete(0), Some(0));
+  assert_eq!(table.insert(14, 14), Ok(()));
+  assert_eq!(table.len(), 14);
+  assert!((1..=14).all(|key| table.contains_key(key)));
+  table.clear();
+  assert!(table.is_empty());
+  assert_eq!(table.iter().count(), 0);
+  assert_eq!(table.get(5), None);
+}
+
+#[test]
+fn test_fixed_table_every_slot_taken() {
+  // Only reachable through broken size accounting, simulated here: probing
+  // for a new key finds no free slot to stop at.
+  let mut table = FixedIndexTable::<16>::new();
+  for (slot, pair) in table.slots.iter_mut().enumerate() {
+    *pair = [slot as u64 + 100, 0];
+  }
+  table.occupied = [true; 16];
+  assert_eq!(table.insert(1, 1), Err(Full));
+  assert_eq!(table.insert(105, 1), Ok(()));
+}
@@ -0,0 +1,248 @@
+use crate::hasher::Hasher64;
+use crate::open_index_table::{scramble, OpenIndexTable};
+use crate::slot_array::SlotAllocator;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Average number of keys per bucket. Larger buckets need fewer pilots but
+// take longer to place.
+const BUCKET_SIZE: usize = 4;
+// Pilots tried for a bucket before the build starts over with a new seed.
+const MAX_PILOT: u32 = 1 << 16;
+
+// Read only table built once from a fixed set of entries. Keys are placed
+// with a perfect hash: every key hashes into a bucket, and each bucket stores
+// a pilot chosen at build time so its keys land in distinct slots. A lookup
+// reads the pilot and then exactly one slot, and the slots are about 98%
+// full instead of the 87.5% a growable table allows.
+//
+// Slots left empty repeat the first entry, so they never match a key that
+// hashes to them; the occupancy bitset is only needed to walk the entries.
+pub struct FrozenIndexTable {
+  data: Vec<u64>,
+  occupancy: Vec<u64>,
+  pilots: Vec<u32>,
+  seed: u64,
+  slots: u64,
+  size: u64,
+}
+
+// Maps hash uniformly onto 0..n.
+fn reduce(hash: u64, n: u64) -> u64 {
+  ((hash as u128 * n as u128) >> 64) as u64
+}
+
+// Picks the slot from the low half of the hash, which the bucket, taken from
+// the high half, says nothing about.
+fn slot_for(hash: u64, pilot: u32, slots: u64) -> u64 {
+  reduce(hash.rotate_left(32) ^ scramble(pilot as u64 + 1), slots)
+}
+
+impl FrozenIndexTable {
+  pub fn len(&self) -> u64 {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  fn hash(&self, key: u64) -> u64 {
+    scramble(key ^ self.seed)
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    if self.size == 0 {
+      return None;
+    }
+    let hash = self.hash(key);
+    let pilot = self.pilots[reduce(hash, self.pilots.len() as u64) as usize];
+    let index = (slot_for(hash, pilot, self.slots) << 1) as usize;
+    (self.data[index] == key).then(|| self.data[index + 1])
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.get(key).is_some()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+    (0..self.slots as usize)
+      .filter(|slot| self.occupancy[slot >> 6] & (1 << (slot & 63)) != 0)
+      .map(|slot| (self.data[slot << 1], self.data[(slot << 1) + 1]))
+  }
+
+  // Builds the table from entries with distinct keys.
+  fn build(entries: &[(u64, u64)]) -> FrozenIndexTable {
+    let mut seed = 0;
+    loop {
+      if let Some(table) = FrozenIndexTable::try_build(entries, scramble(seed)) {
+        return table;
+      }
+      seed += 1;
+    }
+  }
+
+  fn try_build(entries: &[(u64, u64)], seed: u64) -> Option<FrozenIndexTable> {
+    let size = entries.len();
+    let slots = (size + size / 50 + 1) as u64;
+    let buckets = size.div_ceil(BUCKET_SIZE).max(1);
+    let mut table = FrozenIndexTable {
+      data: Vec::new(),
+      occupancy: vec![0; (slots as usize).div_ceil(64)],
+      pilots: vec![0; buckets],
+      seed,
+      slots,
+      size: size as u64,
+    };
+    let hashes: Vec<u64> = entries.iter().map(|(key, _)| table.hash(*key)).collect();
+    // Groups entry indices by bucket, like a counting sort.
+    let mut starts = vec![0; buckets + 1];
+    for hash in &hashes {
+      starts[reduce(*hash, buckets as u64) as usize + 1] += 1;
+    }
+    for bucket in 0..buckets {
+      starts[bucket + 1] += starts[bucket];
+    }
+    let mut members = vec![0; size];
+    let mut next = starts.clone();
+    for (entry, hash) in hashes.iter().enumerate() {
+      let bucket = reduce(*hash, buckets as u64) as usize;
+      members[next[bucket]] = entry;
+      next[bucket] += 1;
+    }
+    // Large buckets are the hardest to place, so they go first.
+    let mut order: Vec<usize> = (0..buckets).collect();
+    order.sort_unstable_by_key(|bucket| core::cmp::Reverse(starts[bucket + 1] - starts[*bucket]));
+    let mut placed = vec![u64::MAX; slots as usize];
+    let mut positions = Vec::new();
+    for bucket in order {
+      let bucket_members = &members[starts[bucket]..starts[bucket + 1]];
+      if bucket_members.is_empty() {
+        break;
+      }
+      let pilot = (0..MAX_PILOT).find(|pilot| {
+        positions.clear();
+        for entry in bucket_members {
+          let slot = slot_for(hashes[*entry], *pilot, slots) as usize;
+          if placed[slot] != u64::MAX || positions.contains(&slot) {
+            return false;
+          }
+          positions.push(slot);
+        }
+        true
+      })?;
+      table.pilots[bucket] = pilot;
+      for (entry, slot) in bucket_members.iter().zip(&positions) {
+        placed[*slot] = *entry as u64;
+      }
+    }
+    let filler = entries.first().copied().unwrap_or((0, 0));
+    table.data = Vec::with_capacity(slots as usize * 2);
+    for (slot, entry) in placed.iter().enumerate() {
+      let (key, v) = match entry {
+        &u64::MAX => filler,
+        entry => {
+          table.occupancy[slot >> 6] |= 1 << (slot & 63);
+          entries[*entry as usize]
+        }
+      };
+      table.data.push(key);
+      table.data.push(v);
+    }
+    Some(table)
+  }
+}
+
+impl FromIterator<(u64, u64)> for FrozenIndexTable {
+  // Later entries win over earlier ones with the same key.
+  fn from_iter<I: IntoIterator<Item = (u64, u64)>>(iter: I) -> FrozenIndexTable {
+    let mut table = OpenIndexTable::new();
+    for (key, v) in iter {
+      table.insert(key, v);
+    }
+    table.freeze()
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  // Rebuilds the entries into a read only table that answers lookups with a
+  // single slot read. Entries stored through the pre-hashed API become plain
+  // keys.
+  pub fn freeze(self) -> FrozenIndexTable {
+    let entries: Vec<(u64, u64)> = self.iter().collect();
+    FrozenIndexTable::build(&entries)
+  }
+}
+
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use test::Bencher;
+
+#[test]
+fn test_freeze_preserves_entries() {
+  let mut table = OpenIndexTable::new();
+  table.insert(0, 7);
+  for key in 1..10_000u64 {
+    table.insert(key.wrapping_mul(0x9E37_79B9_7F4A_7C15), key);
+  }
+  let expected: HashMap<u64, u64> = table.iter().collect();
+  let frozen = table.freeze();
+  assert_eq!(frozen.len(), 10_000);
+  assert_eq!(frozen.get(0), Some(7));
+  for (key, v) in &expected {
+    assert_eq!(frozen.get(*key), Some(*v));
+  }
+  for key in 1..10_000u64 {
+    assert!(!frozen.contains_key(key.wrapping_mul(0x9E37_79B9_7F4A_7C15) + 1));
+  }
+  assert_eq!(frozen.iter().collect::<HashMap<_, _>>(), expected);
+}
+
+#[test]
+fn test_frozen_from_iter() {
+  let frozen: FrozenIndexTable = [(1, 10), (2, 20), (1, 11), (0, 0)].into_iter().collect();
+  assert_eq!(frozen.len(), 3);
+  assert_eq!(frozen.get(1), Some(11));
+  assert_eq!(frozen.get(0), Some(0));
+  assert_eq!(frozen.get(3), None);
+  let empty: FrozenIndexTable = core::iter::empty().collect();
+  assert!(empty.is_empty());
+  assert_eq!(empty.get(0), None);
+  assert_eq!(empty.iter().count(), 0);
+  // A single entry also fills the empty slots, which must not match key 0.
+  let single: FrozenIndexTable = [(5, 50)].into_iter().collect();
+  assert_eq!(single.get(5), Some(50));
+  assert_eq!(single.get(0), None);
+}
+
+// A growable table with 2^21 slots is at its fill limit with this many
+// entries, so both tables use close to the same memory.
+#[cfg(test)]
+const BENCH_ENTRIES: u64 = (1 << 21) / 16 * 14;
+
+#[cfg(test)]
+fn bench_get(b: &mut Bencher, get: impl Fn(u64) -> bool) {
+  let mut i: u64 = 0;
+  b.iter(|| {
+    i = (i + 0x9E37_79B9) % BENCH_ENTRIES;
+    assert!(get(i));
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_frozen_get(b: &mut Bencher) {
+  let frozen: FrozenIndexTable = (0..BENCH_ENTRIES).map(|key| (key, key)).collect();
+  bench_get(b, |key| frozen.get(key) == Some(key));
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_unfrozen_get(b: &mut Bencher) {
+  let mut table = OpenIndexTable::with_capacity(BENCH_ENTRIES as usize);
+  for key in 0..BENCH_ENTRIES {
+    table.insert(key, key);
+  }
+  bench_get(b, |key| table.get(key) == Some(key));
+}
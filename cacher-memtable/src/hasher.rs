@@ -0,0 +1,102 @@
+use crate::open_index_table::scramble;
+
+// Maps keys to the hashes tables pick slots from.
+pub trait Hasher64: Clone {
+  fn hash(&self, key: u64) -> u64;
+}
+
+// The default hasher: fast, but fixed, so keys can be chosen to collide.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scramble;
+
+impl Hasher64 for Scramble {
+  fn hash(&self, key: u64) -> u64 {
+    scramble(key)
+  }
+}
+
+// SipHash-2-4 of the key's little endian bytes under a secret 128-bit key.
+// Without the secret, which keys collide cannot be predicted, so untrusted
+// keys cannot be chosen to build long probe chains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipHash64 {
+  k0: u64,
+  k1: u64,
+}
+
+#[inline]
+fn sip_round(v: &mut [u64; 4]) {
+  v[0] = v[0].wrapping_add(v[1]);
+  v[1] = v[1].rotate_left(13);
+  v[1] ^= v[0];
+  v[0] = v[0].rotate_left(32);
+  v[2] = v[2].wrapping_add(v[3]);
+  v[3] = v[3].rotate_left(16);
+  v[3] ^= v[2];
+  v[0] = v[0].wrapping_add(v[3]);
+  v[3] = v[3].rotate_left(21);
+  v[3] ^= v[0];
+  v[2] = v[2].wrapping_add(v[1]);
+  v[1] = v[1].rotate_left(17);
+  v[1] ^= v[2];
+  v[2] = v[2].rotate_left(32);
+}
+
+impl SipHash64 {
+  pub fn new(k0: u64, k1: u64) -> SipHash64 {
+    SipHash64 { k0, k1 }
+  }
+
+  // Uses a key drawn from the randomness std seeds its HashMaps with.
+  #[cfg(feature = "std")]
+  pub fn random() -> SipHash64 {
+    use core::hash::{BuildHasher, Hasher};
+    let state = std::collections::hash_map::RandomState::new();
+    let mut k0 = state.build_hasher();
+    k0.write_u8(0);
+    let mut k1 = state.build_hasher();
+    k1.write_u8(1);
+    SipHash64::new(k0.finish(), k1.finish())
+  }
+}
+
+impl Hasher64 for SipHash64 {
+  fn hash(&self, key: u64) -> u64 {
+    let mut v = [
+      self.k0 ^ 0x736f_6d65_7073_6575,
+      self.k1 ^ 0x646f_7261_6e64_6f6d,
+      self.k0 ^ 0x6c79_6765_6e65_7261,
+      self.k1 ^ 0x7465_6462_7974_6573,
+    ];
+    // One full 8 byte block, then the final block holding only the length.
+    for m in [key, 8 << 56] {
+      v[3] ^= m;
+      sip_round(&mut v);
+      sip_round(&mut v);
+      v[0] ^= m;
+    }
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+      sip_round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+  }
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_sip_hash_matches_std() {
+  use core::hash::Hasher;
+  for (k0, k1) in [
+    (0, 0),
+    (1, 2),
+    (0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908),
+  ] {
+    let hasher = SipHash64::new(k0, k1);
+    for key in [0, 1, 42, u64::MAX, 1 << 40] {
+      let mut reference = core::hash::SipHasher::new_with_keys(k0, k1);
+      reference.write(&key.to_le_bytes());
+      assert_eq!(hasher.hash(key), reference.finish());
+    }
+  }
+}
@@ -0,0 +1,210 @@
+use crate::open_index_table::{OpenIndexTable, Walk};
+#[cfg(test)]
+use alloc::vec::Vec;
+
+// Entries moved per operation while a resize is in progress.
+const DEFAULT_STEP: usize = 4;
+
+// Table that grows without stopping the world. Once the current table is
+// full, a table of twice the capacity takes its place and the old one is
+// drained into it a few entries per insert, get or delete, so no single call
+// pays for rehashing everything. While entries are being moved, lookups check
+// both tables; a key is only ever in one of them.
+pub struct IncrementalTable {
+  table: OpenIndexTable,
+  old: Option<(OpenIndexTable, Walk)>,
+  step: usize,
+}
+
+impl Default for IncrementalTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl IncrementalTable {
+  pub fn new() -> IncrementalTable {
+    IncrementalTable::with_capacity(0)
+  }
+
+  // Creates a table holding n entries before its first resize.
+  pub fn with_capacity(n: usize) -> IncrementalTable {
+    IncrementalTable {
+      table: OpenIndexTable::with_capacity(n).with_manual_resize(),
+      old: None,
+      step: DEFAULT_STEP,
+    }
+  }
+
+  // Moves entries entries per operation during a resize instead of 4. More
+  // finishes resizes sooner at a higher cost per call. Panics if entries is
+  // 0.
+  pub fn with_migration_step(mut self, entries: usize) -> IncrementalTable {
+    assert!(entries > 0, "migration step must be non zero");
+    self.step = entries;
+    self
+  }
+
+  pub fn len(&self) -> u64 {
+    self.table.len() + self.old.as_ref().map_or(0, |(old, _)| old.len())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Whether entries are still being moved out of the previous table.
+  pub fn is_migrating(&self) -> bool {
+    self.old.is_some()
+  }
+
+  // Returns the value of key, moving a few entries along first if a resize
+  // is in progress.
+  pub fn get(&mut self, key: u64) -> Option<u64> {
+    self.migrate(self.step);
+    self.peek(key)
+  }
+
+  // Looks key up like get, without moving any entries.
+  pub fn peek(&self, key: u64) -> Option<u64> {
+    match (self.table.get(key), &self.old) {
+      (None, Some((old, _))) => old.get(key),
+      (found, _) => found,
+    }
+  }
+
+  pub fn insert(&mut self, key: u64, v: u64) {
+    self.migrate(self.step);
+    if let Some((old, _)) = &mut self.old {
+      if old.delete(key).is_some() {
+        self.table.insert(key, v);
+        return;
+      }
+    }
+    if self.table.needs_resize() && self.table.get(key).is_none() {
+      self.start_resize();
+    }
+    self.table.insert(key, v);
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    self.migrate(self.step);
+    match (self.table.delete(key), &mut self.old) {
+      (None, Some((old, _))) => old.delete(key),
+      (deleted, _) => deleted,
+    }
+  }
+
+  // Walks the entries of both tables, in no particular order.
+  pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+    let old = self.old.as_ref().map(|(old, _)| old.iter());
+    self.table.iter().chain(old.into_iter().flatten())
+  }
+
+  // Moves up to n entries from the previous table into the current one.
+  fn migrate(&mut self, n: usize) {
+    let Some((old, walk)) = &mut self.old else {
+      return;
+    };
+    for _ in 0..n {
+      match old.take_next(walk) {
+        Some((hash, v, true)) => self.table.insert_hashed(hash, v),
+        Some((key, v, false)) => {
+          self.table.insert(key, v);
+        }
+        None => break,
+      }
+    }
+    if old.is_empty() {
+      self.old = None;
+    }
+  }
+
+  // Swaps in an empty table twice the size of the current one. The current
+  // one holds its fill limit of entries, and every later insert moves at
+  // least one of them, so they are all moved by the time the new table is
+  // full. A still unfinished resize is completed first all the same.
+  fn start_resize(&mut self) {
+    self.migrate(usize::MAX);
+    let capacity = self.table.len() as usize * 2;
+    let table = OpenIndexTable::with_capacity(capacity).with_manual_resize();
+    let old = core::mem::replace(&mut self.table, table);
+    let walk = old.walk();
+    self.old = Some((old, walk));
+  }
+}
+
+#[test]
+fn test_incremental_resize_spreads_migration() {
+  let mut table = IncrementalTable::new();
+  let mut key = 0;
+  while !table.is_migrating() {
+    table.insert(key, key + 1);
+    key += 1;
+  }
+  // Grown out of the inline table; run up to a larger resize.
+  while table.is_migrating() {
+    table.insert(key, key + 1);
+    key += 1;
+  }
+  while !table.is_migrating() {
+    table.insert(key, key + 1);
+    key += 1;
+  }
+  let moving = key;
+  let mut operations = 0;
+  while table.is_migrating() {
+    // Every key resolves in the middle of the migration, through either
+    // table.
+    assert!((0..key).all(|key| table.peek(key) == Some(key + 1)));
+    assert_eq!(table.len(), key);
+    match operations % 3 {
+      0 => {
+        table.insert(key, key + 1);
+        key += 1;
+      }
+      1 => assert_eq!(table.get(key / 2), Some(key / 2 + 1)),
+      _ => {
+        // Overwrites of keys not yet moved land in the current table.
+        let old_key = key - 1 - (operations % 7);
+        table.insert(old_key, old_key + 1);
+      }
+    }
+    operations += 1;
+  }
+  assert!(operations >= moving / DEFAULT_STEP as u64);
+  assert_eq!(table.len(), key);
+  assert!((0..key).all(|key| table.peek(key) == Some(key + 1)));
+  assert_eq!(table.peek(key), None);
+}
+
+#[test]
+fn test_incremental_delete_during_migration() {
+  let mut table = IncrementalTable::with_capacity(1000).with_migration_step(1);
+  let mut key = 0;
+  while !table.is_migrating() {
+    table.insert(key, key);
+    key += 1;
+  }
+  let inserted = key;
+  // Two moves per key, so well before the end of the migration.
+  for key in (0..inserted).step_by(8) {
+    assert_eq!(table.delete(key), Some(key));
+    assert_eq!(table.delete(key), None);
+  }
+  assert!(table.is_migrating());
+  let kept = |key: &u64| !key.is_multiple_of(8);
+  assert_eq!(table.len(), (0..inserted).filter(kept).count() as u64);
+  let mut entries: Vec<(u64, u64)> = table.iter().collect();
+  entries.sort();
+  assert!(entries
+    .iter()
+    .copied()
+    .eq((0..inserted).filter(kept).map(|key| (key, key))));
+  while table.is_migrating() {
+    table.get(0);
+  }
+  for key in 0..inserted {
+    assert_eq!(table.peek(key), kept(&key).then_some(key));
+  }
+}
@@ -0,0 +1,352 @@
+use core::borrow::Borrow;
+use core::hash::{BuildHasher, Hash};
+use std::collections::hash_map::RandomState;
+use std::vec;
+use std::vec::Vec;
+
+// Hashes are stored with the top bit set so a zero entry always marks a free slot.
+const FREE_HASH: u64 = 0;
+const HASH_TAG: u64 = 1 << 63;
+
+pub struct IndexMap<K, V, S = RandomState> {
+  hashes: Vec<u64>,
+  entries: Vec<Option<(K, V)>>,
+  mask: u64,
+  cap: u64,
+  size: u64,
+  hasher: S,
+}
+
+impl<K: Hash + Eq, V> IndexMap<K, V, RandomState> {
+  pub fn new() -> IndexMap<K, V, RandomState> {
+    IndexMap::with_hasher(RandomState::new())
+  }
+}
+
+impl<K: Hash + Eq, V> Default for IndexMap<K, V, RandomState> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<K: Hash + Eq, V, S: BuildHasher> IndexMap<K, V, S> {
+  pub fn with_hasher(hasher: S) -> IndexMap<K, V, S> {
+    IndexMap::with_slots(32, hasher)
+  }
+
+  fn with_slots(slots: u64, hasher: S) -> IndexMap<K, V, S> {
+    IndexMap {
+      hashes: vec![FREE_HASH; slots as usize],
+      entries: (0..slots).map(|_| None).collect(),
+      mask: slots - 1,
+      cap: (slots / 16) * 14, // 87.5% fill
+      size: 0,
+      hasher,
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+    self.hasher.hash_one(key) | HASH_TAG
+  }
+
+  fn index(&self, hash: u64) -> u64 {
+    hash & self.mask
+  }
+
+  fn next(&self, index: u64) -> u64 {
+    (index + 1) & self.mask
+  }
+
+  fn find<Q>(&self, key: &Q) -> Option<u64>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    let hash = self.hash(key);
+    let mut index = self.index(hash);
+    loop {
+      let assigned_hash = self.hashes[index as usize];
+      if assigned_hash == FREE_HASH {
+        return None;
+      }
+      if assigned_hash == hash {
+        if let Some((assigned_key, _)) = &self.entries[index as usize] {
+          if assigned_key.borrow() == key {
+            return Some(index);
+          }
+        }
+      }
+      index = self.next(index);
+    }
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    let index = self.find(key)?;
+    self.entries[index as usize].as_ref().map(|(_, v)| v)
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    self.find(key).is_some()
+  }
+
+  pub fn insert(&mut self, key: K, v: V) -> Option<V> {
+    let hash = self.hash(&key);
+    let mut index = self.index(hash);
+    loop {
+      let assigned_hash = self.hashes[index as usize];
+      if assigned_hash == FREE_HASH {
+        self.hashes[index as usize] = hash;
+        self.entries[index as usize] = Some((key, v));
+        self.size += 1;
+        break;
+      }
+      if assigned_hash == hash {
+        if let Some((assigned_key, assigned_value)) = &mut self.entries[index as usize] {
+          if *assigned_key == key {
+            return Some(core::mem::replace(assigned_value, v));
+          }
+        }
+      }
+      index = self.next(index);
+    }
+    self.expand();
+    None
+  }
+
+  pub fn delete<Q>(&mut self, key: &Q) -> Option<V>
+  where
+    K: Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+  {
+    let index = self.find(key)?;
+    self.hashes[index as usize] = FREE_HASH;
+    let (_, v) = self.entries[index as usize].take()?;
+    self.size -= 1;
+    self.unshift(index);
+    Some(v)
+  }
+
+  // Moves entries following the freed slot back so no probe chain is broken.
+  fn unshift(&mut self, free: u64) {
+    let mut free = free;
+    let mut current = self.next(free);
+    loop {
+      let hash = self.hashes[current as usize];
+      if hash == FREE_HASH {
+        return;
+      }
+      let home = self.index(hash);
+      if (current.wrapping_sub(home) & self.mask) >= (current.wrapping_sub(free) & self.mask) {
+        self.hashes[free as usize] = hash;
+        self.hashes[current as usize] = FREE_HASH;
+        self.entries[free as usize] = self.entries[current as usize].take();
+        free = current;
+      }
+      current = self.next(current);
+    }
+  }
+
+  fn expand(&mut self) {
+    if self.size <= self.cap {
+      return;
+    }
+
+    let slots = (self.mask + 1) * 2;
+    let mut hashes = vec![FREE_HASH; slots as usize];
+    let mut entries: Vec<Option<(K, V)>> = (0..slots).map(|_| None).collect();
+    let mask = slots - 1;
+    for (hash, entry) in self.hashes.iter().zip(self.entries.iter_mut()) {
+      if *hash == FREE_HASH {
+        continue;
+      }
+      let mut index = hash & mask;
+      while hashes[index as usize] != FREE_HASH {
+        index = (index + 1) & mask;
+      }
+      hashes[index as usize] = *hash;
+      entries[index as usize] = entry.take();
+    }
+    self.hashes = hashes;
+    self.entries = entries;
+    self.mask = mask;
+    self.cap = (slots / 16) * 14;
+  }
+}
+
+#[cfg(test)]
+use core::hash::{BuildHasherDefault, Hasher};
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use std::format;
+#[cfg(test)]
+use std::string::{String, ToString};
+#[cfg(test)]
+use test::Bencher;
+
+#[cfg(test)]
+#[derive(Default)]
+struct CollidingHasher;
+
+#[cfg(test)]
+impl Hasher for CollidingHasher {
+  fn finish(&self) -> u64 {
+    7
+  }
+
+  fn write(&mut self, _: &[u8]) {}
+}
+
+#[cfg(test)]
+type CollidingMap<V> = IndexMap<String, V, BuildHasherDefault<CollidingHasher>>;
+
+#[test]
+fn test_index_map_string_keys() {
+  let mut map = IndexMap::new();
+  assert_eq!(map.insert("one".to_string(), 1), None);
+  assert_eq!(map.insert("two".to_string(), 2), None);
+  assert_eq!(map.insert("one".to_string(), 3), Some(1));
+  assert_eq!(map.len(), 2);
+  assert_eq!(map.get("one"), Some(&3));
+  assert_eq!(map.get("two"), Some(&2));
+  assert_eq!(map.get("three"), None);
+  assert_eq!(map.delete("one"), Some(3));
+  assert_eq!(map.delete("one"), None);
+  assert_eq!(map.len(), 1);
+  assert!(!map.contains_key("one"));
+  assert!(map.contains_key("two"));
+}
+
+#[test]
+fn test_index_map_grow() {
+  let mut map = IndexMap::new();
+  for i in 0..10_000u64 {
+    map.insert(i.to_string(), i);
+  }
+  assert_eq!(map.len(), 10_000);
+  for i in 0..10_000u64 {
+    assert_eq!(map.get(&i.to_string()), Some(&i));
+  }
+  for i in (0..10_000u64).step_by(2) {
+    assert_eq!(map.delete(&i.to_string()), Some(i));
+  }
+  for i in 0..10_000u64 {
+    let expected = if i % 2 == 0 { None } else { Some(&i) };
+    assert_eq!(map.get(&i.to_string()), expected);
+  }
+}
+
+#[test]
+fn test_index_map_all_keys_colliding() {
+  let mut map: CollidingMap<u64> = IndexMap::with_hasher(BuildHasherDefault::default());
+  for i in 0..100u64 {
+    assert_eq!(map.insert(i.to_string(), i), None);
+  }
+  for i in 0..100u64 {
+    assert_eq!(map.get(&i.to_string()), Some(&i));
+  }
+  // Remove from the head, the middle and the tail of the single chain.
+  for i in [0u64, 50, 99] {
+    assert_eq!(map.delete(&i.to_string()), Some(i));
+  }
+  for i in 0..100u64 {
+    let expected = if [0, 50, 99].contains(&i) {
+      None
+    } else {
+      Some(&i)
+    };
+    assert_eq!(map.get(&i.to_string()), expected);
+  }
+  assert_eq!(map.len(), 97);
+}
+
+#[test]
+fn test_index_map_colliding_chain_wraps() {
+  // Home slot 7 of 32 forces the chain to wrap around the end of the array.
+  let mut map: CollidingMap<u64> = IndexMap::with_hasher(BuildHasherDefault::default());
+  for i in 0..28u64 {
+    map.insert(i.to_string(), i);
+  }
+  for i in 0..28u64 {
+    assert_eq!(map.delete(&i.to_string()), Some(i));
+    for j in i + 1..28 {
+      assert_eq!(map.get(&j.to_string()), Some(&j));
+    }
+  }
+  assert!(map.is_empty());
+}
+
+#[cfg(test)]
+fn string_keys(n: usize) -> Vec<String> {
+  (0..n).map(|i| format!("key-{}", i)).collect()
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_std_map_insert_string(b: &mut Bencher) {
+  let keys = string_keys(1 << 16);
+  let mut map = HashMap::new();
+  let mut i = 0;
+  b.iter(|| {
+    map.insert(keys[i].clone(), i);
+    i = (i + 1) & (keys.len() - 1);
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_index_map_insert_string(b: &mut Bencher) {
+  let keys = string_keys(1 << 16);
+  let mut map = IndexMap::new();
+  let mut i = 0;
+  b.iter(|| {
+    map.insert(keys[i].clone(), i);
+    i = (i + 1) & (keys.len() - 1);
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_std_map_get_string(b: &mut Bencher) {
+  let keys = string_keys(1 << 16);
+  let mut map = HashMap::new();
+  for (i, key) in keys.iter().enumerate() {
+    map.insert(key.clone(), i);
+  }
+  let mut i = 0;
+  b.iter(|| {
+    test::black_box(map.get(&keys[i]));
+    i = (i + 1) & (keys.len() - 1);
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_index_map_get_string(b: &mut Bencher) {
+  let keys = string_keys(1 << 16);
+  let mut map = IndexMap::new();
+  for (i, key) in keys.iter().enumerate() {
+    map.insert(key.clone(), i);
+  }
+  let mut i = 0;
+  b.iter(|| {
+    test::black_box(map.get(&keys[i]));
+    i = (i + 1) & (keys.len() - 1);
+  });
+}
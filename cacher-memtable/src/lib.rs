@@ -1,2 +1,87 @@
-#![feature(test)]
-pub mod open_index_table;
\ No newline at end of file
+#![no_std]
+#![cfg_attr(test, feature(test))]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate std;
+#[cfg(test)]
+extern crate test;
+
+#[cfg(test)]
+#[macro_use]
+mod table_tests;
+
+#[cfg(feature = "tokio")]
+pub mod async_index_table;
+#[cfg(target_has_atomic = "64")]
+pub mod atomic_index_table;
+#[cfg(feature = "std")]
+pub mod batched_writer;
+pub mod bloom_filter;
+pub mod clock_table;
+pub mod count_min_sketch;
+pub mod counter_table;
+pub mod fixed_index_table;
+pub mod frozen_index_table;
+pub mod hasher;
+pub mod incremental_table;
+#[cfg(feature = "std")]
+pub mod index_map;
+pub mod linked_index_table;
+#[cfg(feature = "async")]
+pub mod loading_table;
+pub mod lru_table;
+pub mod mapped_index_table;
+pub mod non_zero_index_table;
+#[cfg(feature = "std")]
+pub mod op_counters;
+pub mod open_index_multi_table;
+pub mod open_index_table;
+pub mod open_index_table_builder;
+mod probing;
+#[cfg(feature = "raw")]
+pub mod raw;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub mod rcu_index_table;
+pub mod sampled_eviction_table;
+pub mod segmented_lru_table;
+#[cfg(feature = "std")]
+pub mod sharded_index_table;
+pub mod slot_array;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+pub mod spmc_index_table;
+#[cfg(all(feature = "std", target_has_atomic = "64"))]
+mod sync;
+#[cfg(feature = "std")]
+pub mod sync_index_table;
+pub mod tiny_lfu_table;
+#[cfg(feature = "std")]
+pub mod ttl_table;
+
+// Smoke test for the no_std wiring: the library must build for a bare metal
+// target with only alloc available. The toolchain running the tests needs the
+// target installed.
+#[test]
+fn test_builds_without_std() {
+  const TARGET: &str = "thumbv7em-none-eabihf";
+  let libdir = std::process::Command::new("rustc")
+    .args(["--print", "target-libdir", "--target", TARGET])
+    .output()
+    .unwrap();
+  let libdir = std::str::from_utf8(&libdir.stdout).unwrap().trim();
+  assert!(
+    !libdir.is_empty() && std::path::Path::new(libdir).exists(),
+    "the {TARGET} target is not installed, run `rustup target add {TARGET}`"
+  );
+  let status = std::process::Command::new(env!("CARGO"))
+    .args(["build", "--no-default-features", "--target", TARGET])
+    .arg("--manifest-path")
+    .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+    .env(
+      "CARGO_TARGET_DIR",
+      std::env::temp_dir().join("cacher-memtable-no-std"),
+    )
+    .status()
+    .unwrap();
+  assert!(status.success());
+}
@@ -0,0 +1,332 @@
+use crate::open_index_table::scramble;
+use crate::probing::LinearProbe;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Marks the ends of the list.
+const NIL: u64 = u64::MAX;
+const MIN_SLOTS: u64 = 16;
+
+// Table remembering the order keys were first inserted in, like Java's
+// LinkedHashMap. Every slot carries the indices of the slots holding the
+// entries inserted just before and after it, threading a doubly linked list
+// through the table from the oldest entry (front) to the newest (back).
+// Overwriting a key keeps its place. A delete shifts entries back into the
+// freed slot, and every entry moved that way has its neighbours' links
+// pointed at its new slot.
+pub struct LinkedIndexTable {
+  keys: Vec<u64>,
+  values: Vec<u64>,
+  prev: Vec<u64>,
+  next: Vec<u64>,
+  occupied: Vec<u64>,
+  head: u64,
+  tail: u64,
+  size: u64,
+}
+
+impl LinearProbe for LinkedIndexTable {
+  fn data_mask(&self) -> u64 {
+    self.slots() * 2 - 1
+  }
+
+  fn slot_occupied(&self, index: u64) -> bool {
+    let slot = index >> 1;
+    self.occupied[(slot >> 6) as usize] & (1 << (slot & 63)) != 0
+  }
+
+  fn home_of(&self, index: u64) -> u64 {
+    self.home(self.keys[(index >> 1) as usize])
+  }
+
+  fn vacate(&mut self, index: u64) {
+    let slot = index >> 1;
+    self.occupied[(slot >> 6) as usize] &= !(1 << (slot & 63));
+  }
+
+  fn move_slot(&mut self, from: u64, to: u64) {
+    let (from_slot, to_slot) = ((from >> 1) as usize, (to >> 1) as usize);
+    let (prev, next) = (self.prev[from_slot], self.next[from_slot]);
+    self.occupy(to, self.keys[from_slot], self.values[from_slot]);
+    self.prev[to_slot] = prev;
+    self.next[to_slot] = next;
+    // The neighbours are other entries, so their slots are current.
+    self.set_next(prev, to);
+    self.set_prev(next, to);
+  }
+}
+
+impl LinkedIndexTable {
+  pub fn new() -> LinkedIndexTable {
+    LinkedIndexTable::with_slots(MIN_SLOTS)
+  }
+
+  fn with_slots(slots: u64) -> LinkedIndexTable {
+    LinkedIndexTable {
+      keys: vec![0; slots as usize],
+      values: vec![0; slots as usize],
+      prev: vec![NIL; slots as usize],
+      next: vec![NIL; slots as usize],
+      occupied: vec![0; slots.div_ceil(64) as usize],
+      head: NIL,
+      tail: NIL,
+      size: 0,
+    }
+  }
+
+  fn slots(&self) -> u64 {
+    self.keys.len() as u64
+  }
+
+  fn home(&self, key: u64) -> u64 {
+    (scramble(key) & (self.slots() - 1)) << 1
+  }
+
+  fn occupy(&mut self, index: u64, key: u64, v: u64) {
+    let slot = index >> 1;
+    self.keys[slot as usize] = key;
+    self.values[slot as usize] = v;
+    self.occupied[(slot >> 6) as usize] |= 1 << (slot & 63);
+  }
+
+  fn find(&self, key: u64) -> (u64, bool) {
+    self.probe(self.home(key), |index| {
+      self.keys[(index >> 1) as usize] == key
+    })
+  }
+
+  // Points the list at index from the entry before it, or the front.
+  fn set_next(&mut self, prev: u64, index: u64) {
+    match prev {
+      NIL => self.head = index,
+      prev => self.next[(prev >> 1) as usize] = index,
+    }
+  }
+
+  fn set_prev(&mut self, next: u64, index: u64) {
+    match next {
+      NIL => self.tail = index,
+      next => self.prev[(next >> 1) as usize] = index,
+    }
+  }
+
+  fn entry_at(&self, index: u64) -> (u64, u64) {
+    let slot = (index >> 1) as usize;
+    (self.keys[slot], self.values[slot])
+  }
+
+  pub fn len(&self) -> u64 {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.find(key).1
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => Some(self.values[(index >> 1) as usize]),
+      _ => None,
+    }
+  }
+
+  // Inserts key at the back, or updates its value in place if it is already
+  // present, returning the value it replaced.
+  pub fn insert(&mut self, key: u64, v: u64) -> Option<u64> {
+    if let (index, true) = self.find(key) {
+      let old = self.values[(index >> 1) as usize];
+      self.values[(index >> 1) as usize] = v;
+      return Some(old);
+    }
+    // Grows at 87.5% fill, like OpenIndexTable.
+    if (self.size + 1) * 8 > self.slots() * 7 {
+      self.grow();
+    }
+    let (index, _) = self.find(key);
+    self.occupy(index, key, v);
+    self.push_back(index);
+    self.size += 1;
+    None
+  }
+
+  fn push_back(&mut self, index: u64) {
+    let slot = (index >> 1) as usize;
+    self.prev[slot] = self.tail;
+    self.next[slot] = NIL;
+    self.set_next(self.tail, index);
+    self.tail = index;
+  }
+
+  // Rehashes into twice the slots, walking the list so the new one comes
+  // out in the same order.
+  fn grow(&mut self) {
+    let mut grown = LinkedIndexTable::with_slots(self.slots() * 2);
+    for (key, v) in self.iter_ordered() {
+      let (index, _) = grown.find(key);
+      grown.occupy(index, key, v);
+      grown.push_back(index);
+    }
+    grown.size = self.size;
+    *self = grown;
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => Some(self.remove_at(index).1),
+      _ => None,
+    }
+  }
+
+  // Unlinks the entry at index, then frees its slot, relinking the entries
+  // the backward shift moves.
+  fn remove_at(&mut self, index: u64) -> (u64, u64) {
+    let entry = self.entry_at(index);
+    let slot = (index >> 1) as usize;
+    let (prev, next) = (self.prev[slot], self.next[slot]);
+    self.set_next(prev, next);
+    self.set_prev(next, prev);
+    self.unshift(index);
+    self.size -= 1;
+    entry
+  }
+
+  // The oldest entry.
+  pub fn front(&self) -> Option<(u64, u64)> {
+    (self.head != NIL).then(|| self.entry_at(self.head))
+  }
+
+  // The newest entry.
+  pub fn back(&self) -> Option<(u64, u64)> {
+    (self.tail != NIL).then(|| self.entry_at(self.tail))
+  }
+
+  // Removes and returns the oldest entry, for FIFO eviction.
+  pub fn pop_front(&mut self) -> Option<(u64, u64)> {
+    (self.head != NIL).then(|| self.remove_at(self.head))
+  }
+
+  // Walks the entries from the oldest to the newest.
+  pub fn iter_ordered(&self) -> IterOrdered<'_> {
+    IterOrdered {
+      table: self,
+      index: self.head,
+    }
+  }
+
+  #[cfg(test)]
+  fn check_links(&self) {
+    let mut count = 0;
+    let mut prev = NIL;
+    let mut index = self.head;
+    while index != NIL {
+      assert!(self.slot_occupied(index));
+      assert_eq!(self.prev[(index >> 1) as usize], prev);
+      assert_eq!(self.find(self.keys[(index >> 1) as usize]), (index, true));
+      (prev, index) = (index, self.next[(index >> 1) as usize]);
+      count += 1;
+    }
+    assert_eq!(prev, self.tail);
+    assert_eq!(count, self.size);
+    let occupied: u32 = self.occupied.iter().map(|word| word.count_ones()).sum();
+    assert_eq!(occupied as u64, self.size);
+  }
+}
+
+impl Default for LinkedIndexTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+// Entries of a LinkedIndexTable from the oldest to the newest.
+pub struct IterOrdered<'a> {
+  table: &'a LinkedIndexTable,
+  index: u64,
+}
+
+impl Iterator for IterOrdered<'_> {
+  type Item = (u64, u64);
+
+  fn next(&mut self) -> Option<(u64, u64)> {
+    if self.index == NIL {
+      return None;
+    }
+    let entry = self.table.entry_at(self.index);
+    self.index = self.table.next[(self.index >> 1) as usize];
+    Some(entry)
+  }
+}
+
+#[test]
+fn test_linked_table_front_back() {
+  let mut table = LinkedIndexTable::new();
+  assert_eq!(table.front(), None);
+  assert_eq!(table.pop_front(), None);
+  for key in [5, 0, 9] {
+    assert_eq!(table.insert(key, key * 10), None);
+  }
+  // Overwriting keeps the key's place.
+  assert_eq!(table.insert(5, 51), Some(50));
+  assert_eq!(table.front(), Some((5, 51)));
+  assert_eq!(table.back(), Some((9, 90)));
+  assert_eq!(table.pop_front(), Some((5, 51)));
+  assert_eq!(table.get(5), None);
+  assert_eq!(table.delete(9), Some(90));
+  assert_eq!(table.delete(9), None);
+  assert_eq!(table.front(), table.back());
+  assert!(table.iter_ordered().eq([(0, 0)]));
+  table.check_links();
+}
+
+#[test]
+fn test_linked_table_order_under_churn() {
+  let mut state = 0x2545_f491_4f6c_dd1d_u64;
+  let mut next = move |n: u64| {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state % n
+  };
+  let mut table = LinkedIndexTable::new();
+  // Keys in insertion order, mirroring the table.
+  let mut order: Vec<u64> = Vec::new();
+  let mut grown = 0;
+  for round in 0..20_000 {
+    let key = next(3000);
+    match next(4) {
+      0 => {
+        let expected = order.iter().position(|k| *k == key).map(|position| {
+          order.remove(position);
+          key + 1
+        });
+        assert_eq!(table.delete(key), expected);
+      }
+      1 => {
+        if let Some((key, _)) = table.pop_front() {
+          assert_eq!(order.remove(0), key);
+        }
+      }
+      _ => {
+        let slots = table.slots();
+        if table.insert(key, key + 1).is_none() {
+          order.push(key);
+        }
+        grown += (table.slots() > slots) as u32;
+      }
+    }
+    if round % 1000 == 0 {
+      table.check_links();
+    }
+  }
+  assert!(grown >= 5);
+  table.check_links();
+  assert!(table
+    .iter_ordered()
+    .eq(order.iter().map(|key| (*key, key + 1))));
+  assert_eq!(table.front().map(|(key, _)| key), order.first().copied());
+  assert_eq!(table.back().map(|(key, _)| key), order.last().copied());
+}
@@ -0,0 +1,229 @@
+use crate::open_index_table::OpenIndexTable;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+#[derive(Default)]
+struct LoadState {
+  value: Option<u64>,
+  abandoned: bool,
+  wakers: Vec<Waker>,
+}
+
+#[derive(Default)]
+struct Load {
+  state: Mutex<LoadState>,
+}
+
+impl Load {
+  fn finish(&self, value: Option<u64>) {
+    let mut state = self.state.lock().unwrap();
+    state.value = value;
+    state.abandoned = value.is_none();
+    for waker in state.wakers.drain(..) {
+      waker.wake();
+    }
+  }
+}
+
+// Resolves to the loaded value, or None if the loading caller was dropped
+// before it finished.
+struct LoadWait {
+  load: Arc<Load>,
+}
+
+impl Future for LoadWait {
+  type Output = Option<u64>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<u64>> {
+    let mut state = self.load.state.lock().unwrap();
+    if state.value.is_some() || state.abandoned {
+      return Poll::Ready(state.value);
+    }
+    state.wakers.push(cx.waker().clone());
+    Poll::Pending
+  }
+}
+
+// Clears an in-flight load if its leader is dropped mid-load so waiters can
+// retry with their own loader.
+struct LoadGuard<'a> {
+  table: &'a LoadingTable,
+  key: u64,
+  load: Arc<Load>,
+  done: bool,
+}
+
+impl Drop for LoadGuard<'_> {
+  fn drop(&mut self) {
+    if !self.done {
+      self.table.loads.lock().unwrap().remove(&self.key);
+      self.load.finish(None);
+    }
+  }
+}
+
+// Thread safe table for cache-aside loading. Concurrent misses on the same
+// key share a single in-flight load.
+pub struct LoadingTable {
+  table: Mutex<OpenIndexTable>,
+  loads: Mutex<HashMap<u64, Arc<Load>>>,
+}
+
+impl LoadingTable {
+  pub fn new() -> LoadingTable {
+    LoadingTable {
+      table: Mutex::new(OpenIndexTable::new()),
+      loads: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    self.table.lock().unwrap().get(key)
+  }
+
+  pub fn insert(&self, key: u64, v: u64) {
+    self.table.lock().unwrap().insert(key, v);
+  }
+
+  pub fn delete(&self, key: u64) -> Option<u64> {
+    self.table.lock().unwrap().delete(key)
+  }
+
+  // Returns the cached value for key, or awaits loader on a miss and caches
+  // its result. If another caller is already loading key, this waits for
+  // that load instead and loader is never polled.
+  pub async fn get_or_load<F: Future<Output = u64>>(&self, key: u64, loader: F) -> u64 {
+    let mut loader = Some(loader);
+    loop {
+      let (load, leader) = {
+        let mut loads = self.loads.lock().unwrap();
+        if let Some(v) = self.get(key) {
+          return v;
+        }
+        match loads.get(&key) {
+          Some(load) => (load.clone(), false),
+          None => {
+            let load = Arc::new(Load::default());
+            loads.insert(key, load.clone());
+            (load, true)
+          }
+        }
+      };
+      if !leader {
+        match (LoadWait { load }).await {
+          Some(v) => return v,
+          None => continue,
+        }
+      }
+      let mut guard = LoadGuard {
+        table: self,
+        key,
+        load,
+        done: false,
+      };
+      let v = loader.take().expect("loader is only awaited once").await;
+      {
+        let mut loads = self.loads.lock().unwrap();
+        self.insert(key, v);
+        loads.remove(&key);
+      }
+      guard.done = true;
+      guard.load.finish(Some(v));
+      return v;
+    }
+  }
+}
+
+impl Default for LoadingTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(test)]
+use std::boxed::Box;
+
+// Loader that stays pending until its gate is opened.
+#[cfg(test)]
+struct GatedLoad<'a> {
+  gate: &'a AtomicBool,
+  calls: &'a AtomicUsize,
+  value: u64,
+  polled: bool,
+}
+
+#[cfg(test)]
+impl Future for GatedLoad<'_> {
+  type Output = u64;
+
+  fn poll(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<u64> {
+    if !self.polled {
+      self.polled = true;
+      self.calls.fetch_add(1, Ordering::SeqCst);
+    }
+    if self.gate.load(Ordering::SeqCst) {
+      Poll::Ready(self.value)
+    } else {
+      Poll::Pending
+    }
+  }
+}
+
+#[test]
+fn test_get_or_load_shares_in_flight_load() {
+  let table = LoadingTable::new();
+  let gate = AtomicBool::new(false);
+  let calls = AtomicUsize::new(0);
+  let load = |value| GatedLoad {
+    gate: &gate,
+    calls: &calls,
+    value,
+    polled: false,
+  };
+  let mut first = std::pin::pin!(table.get_or_load(1, load(10)));
+  let mut second = std::pin::pin!(table.get_or_load(1, load(20)));
+  let mut cx = Context::from_waker(Waker::noop());
+  assert!(first.as_mut().poll(&mut cx).is_pending());
+  assert!(second.as_mut().poll(&mut cx).is_pending());
+  gate.store(true, Ordering::SeqCst);
+  assert_eq!(first.as_mut().poll(&mut cx), Poll::Ready(10));
+  assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(10));
+  assert_eq!(calls.load(Ordering::SeqCst), 1);
+  assert_eq!(table.get(1), Some(10));
+}
+
+#[test]
+fn test_get_or_load_hit_and_abandoned_leader() {
+  let table = LoadingTable::new();
+  table.insert(2, 5);
+  let gate = AtomicBool::new(false);
+  let calls = AtomicUsize::new(0);
+  let load = |value| GatedLoad {
+    gate: &gate,
+    calls: &calls,
+    value,
+    polled: false,
+  };
+  let mut cx = Context::from_waker(Waker::noop());
+  let hit = std::pin::pin!(table.get_or_load(2, load(7)));
+  assert_eq!(hit.poll(&mut cx), Poll::Ready(5));
+  assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+  let mut second = std::pin::pin!(table.get_or_load(3, load(30)));
+  {
+    let mut first = Box::pin(table.get_or_load(3, load(20)));
+    assert!(first.as_mut().poll(&mut cx).is_pending());
+    assert!(second.as_mut().poll(&mut cx).is_pending());
+  }
+  // The first caller was dropped, so the waiter runs its own loader.
+  gate.store(true, Ordering::SeqCst);
+  assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(30));
+  assert_eq!(calls.load(Ordering::SeqCst), 2);
+  assert_eq!(table.delete(3), Some(30));
+}
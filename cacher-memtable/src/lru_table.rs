@@ -0,0 +1,291 @@
+use crate::open_index_table::OpenIndexTable;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+const NIL: u64 = u64::MAX;
+
+struct Node {
+  key: u64,
+  value: u64,
+  prev: u64,
+  next: u64,
+}
+
+pub(crate) type EvictFn = Box<dyn FnMut(u64, u64) + Send>;
+
+// Bounded cache evicting the least recently used entry. The table maps keys
+// to nodes of an intrusive list ordered from most (head) to least (tail)
+// recently used.
+pub struct LruTable {
+  table: OpenIndexTable,
+  nodes: Vec<Node>,
+  free_nodes: Vec<u64>,
+  head: u64,
+  tail: u64,
+  capacity: u64,
+  on_evict: Option<EvictFn>,
+  notify_deletes: bool,
+}
+
+impl LruTable {
+  pub fn new(capacity: u64) -> LruTable {
+    assert!(capacity > 0, "capacity must be non zero");
+    LruTable {
+      table: OpenIndexTable::new(),
+      nodes: Vec::new(),
+      free_nodes: Vec::new(),
+      head: NIL,
+      tail: NIL,
+      capacity,
+      on_evict: None,
+      notify_deletes: false,
+    }
+  }
+
+  // Calls callback(key, value) once for every entry that leaves the cache
+  // through eviction, pop_lru or clear.
+  pub fn on_evict(mut self, callback: impl FnMut(u64, u64) + Send + 'static) -> LruTable {
+    self.on_evict = Some(Box::new(callback));
+    self
+  }
+
+  // Also calls the eviction callback for entries removed by delete.
+  pub fn notify_deletes(mut self) -> LruTable {
+    self.notify_deletes = true;
+    self
+  }
+
+  pub fn len(&self) -> u64 {
+    self.nodes.len() as u64 - self.free_nodes.len() as u64
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> u64 {
+    self.capacity
+  }
+
+  // Bytes allocated by the cache: the index table, the node storage holding
+  // the values and the struct itself.
+  pub fn memory_bytes(&self) -> usize {
+    self.table.memory_bytes() - core::mem::size_of::<OpenIndexTable>()
+      + self.nodes.capacity() * core::mem::size_of::<Node>()
+      + self.free_nodes.capacity() * core::mem::size_of::<u64>()
+      + core::mem::size_of::<LruTable>()
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.table.get(key).is_some()
+  }
+
+  // Reads the value of key without making it the most recently used.
+  pub fn peek(&self, key: u64) -> Option<u64> {
+    let node = self.table.get(key)?;
+    Some(self.nodes[node as usize].value)
+  }
+
+  pub fn get(&mut self, key: u64) -> Option<u64> {
+    let node = self.table.get(key)?;
+    self.detach(node);
+    self.push_front(node);
+    Some(self.nodes[node as usize].value)
+  }
+
+  // Inserts or updates key, returning the entry evicted to make room.
+  pub fn insert(&mut self, key: u64, value: u64) -> Option<(u64, u64)> {
+    if let Some(node) = self.table.get(key) {
+      self.nodes[node as usize].value = value;
+      self.detach(node);
+      self.push_front(node);
+      return None;
+    }
+    let evicted = if self.len() >= self.capacity {
+      self.pop_lru()
+    } else {
+      None
+    };
+    let node = Node {
+      key,
+      value,
+      prev: NIL,
+      next: NIL,
+    };
+    let node = match self.free_nodes.pop() {
+      Some(free) => {
+        self.nodes[free as usize] = node;
+        free
+      }
+      None => {
+        self.nodes.push(node);
+        self.nodes.len() as u64 - 1
+      }
+    };
+    self.table.insert(key, node);
+    self.push_front(node);
+    evicted
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    let value = self.remove(key)?;
+    if self.notify_deletes {
+      self.notify(key, value);
+    }
+    Some(value)
+  }
+
+  fn remove(&mut self, key: u64) -> Option<u64> {
+    let node = self.table.delete(key)?;
+    self.detach(node);
+    self.free_nodes.push(node);
+    Some(self.nodes[node as usize].value)
+  }
+
+  fn notify(&mut self, key: u64, value: u64) {
+    if let Some(on_evict) = &mut self.on_evict {
+      on_evict(key, value);
+    }
+  }
+
+  // Removes every entry, least recently used first.
+  pub fn clear(&mut self) {
+    while self.pop_lru().is_some() {}
+  }
+
+  // The entry that would be evicted next.
+  pub fn lru(&self) -> Option<(u64, u64)> {
+    if self.tail == NIL {
+      return None;
+    }
+    let node = &self.nodes[self.tail as usize];
+    Some((node.key, node.value))
+  }
+
+  pub fn pop_lru(&mut self) -> Option<(u64, u64)> {
+    let (key, value) = self.lru()?;
+    self.remove(key);
+    self.notify(key, value);
+    Some((key, value))
+  }
+
+  fn detach(&mut self, node: u64) {
+    let Node { prev, next, .. } = self.nodes[node as usize];
+    if prev == NIL {
+      self.head = next;
+    } else {
+      self.nodes[prev as usize].next = next;
+    }
+    if next == NIL {
+      self.tail = prev;
+    } else {
+      self.nodes[next as usize].prev = prev;
+    }
+  }
+
+  fn push_front(&mut self, node: u64) {
+    self.nodes[node as usize].prev = NIL;
+    self.nodes[node as usize].next = self.head;
+    if self.head == NIL {
+      self.tail = node;
+    } else {
+      self.nodes[self.head as usize].prev = node;
+    }
+    self.head = node;
+  }
+}
+
+#[test]
+fn test_lru_evicts_least_recently_used() {
+  let mut lru = LruTable::new(3);
+  assert_eq!(lru.insert(1, 10), None);
+  assert_eq!(lru.insert(2, 20), None);
+  assert_eq!(lru.insert(3, 30), None);
+  assert_eq!(lru.get(1), Some(10));
+  assert_eq!(lru.insert(4, 40), Some((2, 20)));
+  assert_eq!(lru.get(2), None);
+  assert_eq!(lru.lru(), Some((3, 30)));
+  assert_eq!(lru.insert(3, 31), None);
+  assert_eq!(lru.insert(5, 50), Some((1, 10)));
+  assert_eq!(lru.len(), 3);
+}
+
+#[test]
+fn test_lru_peek_keeps_recency() {
+  let mut lru = LruTable::new(3);
+  lru.insert(1, 10);
+  lru.insert(2, 20);
+  lru.insert(3, 30);
+  for _ in 0..5 {
+    assert_eq!(lru.peek(1), Some(10));
+  }
+  assert_eq!(lru.peek(4), None);
+  assert_eq!(lru.get(2), Some(20));
+  // 1 was only peeked, so it is still the least recently used.
+  assert_eq!(lru.insert(4, 40), Some((1, 10)));
+  assert_eq!(lru.peek(1), None);
+}
+
+#[test]
+fn test_lru_delete_reuses_nodes() {
+  let mut lru = LruTable::new(2);
+  lru.insert(1, 10);
+  lru.insert(2, 20);
+  assert_eq!(lru.delete(1), Some(10));
+  assert_eq!(lru.delete(1), None);
+  assert_eq!(lru.len(), 1);
+  assert_eq!(lru.insert(3, 30), None);
+  assert_eq!(lru.insert(4, 40), Some((2, 20)));
+  assert_eq!(lru.get(3), Some(30));
+  assert_eq!(lru.get(4), Some(40));
+  assert_eq!(lru.pop_lru(), Some((3, 30)));
+  assert_eq!(lru.pop_lru(), Some((4, 40)));
+  assert_eq!(lru.pop_lru(), None);
+  assert!(lru.is_empty());
+}
+
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_lru_on_evict() {
+  let evicted = Arc::new(Mutex::new(Vec::new()));
+  let log = evicted.clone();
+  let mut lru = LruTable::new(2).on_evict(move |key, value| log.lock().unwrap().push((key, value)));
+  for key in 0..5 {
+    lru.insert(key, key * 10);
+  }
+  assert_eq!(*evicted.lock().unwrap(), [(0, 0), (1, 10), (2, 20)]);
+  assert_eq!(lru.delete(3), Some(30));
+  lru.clear();
+  assert!(lru.is_empty());
+  assert_eq!(
+    *evicted.lock().unwrap(),
+    [(0, 0), (1, 10), (2, 20), (4, 40)]
+  );
+
+  let deleted = Arc::new(Mutex::new(Vec::new()));
+  let log = deleted.clone();
+  let mut lru = LruTable::new(2)
+    .on_evict(move |key, value| log.lock().unwrap().push((key, value)))
+    .notify_deletes();
+  lru.insert(1, 10);
+  assert_eq!(lru.delete(1), Some(10));
+  assert_eq!(lru.delete(1), None);
+  assert_eq!(*deleted.lock().unwrap(), [(1, 10)]);
+}
+
+#[test]
+fn test_lru_memory_bytes() {
+  let mut table = LruTable::new(1000);
+  let initial = table.memory_bytes();
+  assert_eq!(
+    initial,
+    OpenIndexTable::new().memory_bytes() - core::mem::size_of::<OpenIndexTable>()
+      + core::mem::size_of::<LruTable>()
+  );
+  for i in 1..=1000 {
+    table.insert(i, i);
+  }
+  assert!(table.memory_bytes() >= initial + 1000 * core::mem::size_of::<Node>());
+}
@@ -0,0 +1,369 @@
+use crate::open_index_table::scramble;
+use crate::probing::LinearProbe;
+use alloc::vec::Vec;
+
+// Layout written by OpenIndexTable::to_mmap_bytes:
+//
+//   page 0   header, HEADER_WORDS u64s, the rest of the page zero
+//   keys     one u64 per slot
+//   values   one u64 per slot
+//   occupied bitset, one bit per slot
+//   hashed   bitset, one bit per slot, set for entries of insert_hashed
+//
+// Every section starts on a PAGE boundary, so a mapping of the file has each
+// array aligned and in place. Words are stored in the byte order of the
+// machine that wrote them; the header carries a marker that reads back
+// differently on a machine of the other order, and such files are refused
+// rather than converted. Readers only accept files of exactly VERSION, since
+// a new version may change the hash or the probing as well as the layout.
+pub const PAGE: usize = 4096;
+pub const VERSION: u64 = 1;
+const MAGIC: u64 = u64::from_le_bytes(*b"cachertb");
+const BYTE_ORDER: u64 = 0x0102_0304_0506_0708;
+const HEADER_WORDS: usize = 6;
+
+// Why from_mmap refused a buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MapError {
+  // The buffer does not start with the magic number.
+  Magic,
+  // Written by a machine of the other byte order.
+  ByteOrder,
+  // Written by another version of the layout.
+  Version(u64),
+  // The buffer is not aligned to 8 bytes.
+  Alignment,
+  // The slot count is not a power of two, or the header does not fit the
+  // buffer.
+  Header,
+  // The buffer is shorter than the sections the header describes.
+  Truncated { expected: usize, actual: usize },
+}
+
+fn section_bytes(words: usize) -> usize {
+  (words * 8).div_ceil(PAGE) * PAGE
+}
+
+// Bytes of the file for a table of slots slots.
+fn file_bytes(slots: usize) -> usize {
+  let bits = slots.div_ceil(64);
+  PAGE + 2 * section_bytes(slots) + 2 * section_bytes(bits)
+}
+
+fn push_section(out: &mut Vec<u8>, words: impl Iterator<Item = u64>) {
+  for word in words {
+    out.extend_from_slice(&word.to_ne_bytes());
+  }
+  out.resize(out.len().div_ceil(PAGE) * PAGE, 0);
+}
+
+// Lays out the slot arrays of a heap table as described above. hashed may be
+// empty when no entry was stored pre-hashed.
+pub(crate) fn layout(
+  keys: &[u64],
+  values: &[u64],
+  occupied: &[u64],
+  hashed: &[u64],
+  len: u64,
+  home_mask: u64,
+) -> Vec<u8> {
+  let slots = keys.len();
+  let bits = slots.div_ceil(64);
+  let mut out = Vec::with_capacity(file_bytes(slots));
+  let header = [MAGIC, BYTE_ORDER, VERSION, slots as u64, len, home_mask];
+  push_section(&mut out, header.into_iter());
+  push_section(&mut out, keys.iter().copied());
+  push_section(&mut out, values.iter().copied());
+  push_section(&mut out, occupied.iter().copied());
+  let hashed = hashed.iter().copied().chain(core::iter::repeat(0));
+  push_section(&mut out, hashed.take(bits));
+  out
+}
+
+// Read only view of a table persisted by OpenIndexTable::to_mmap_bytes,
+// used in place: loading checks the header and points at the arrays in the
+// buffer, without copying or rehashing anything. Meant for a mapping of
+// the file, see Mmap, but any 8 byte aligned buffer works.
+pub struct MappedIndexTable<'a> {
+  keys: &'a [u64],
+  values: &'a [u64],
+  occupied: &'a [u64],
+  hashed: &'a [u64],
+  len: u64,
+  home_mask: u64,
+}
+
+impl<'a> MappedIndexTable<'a> {
+  pub fn from_mmap(bytes: &'a [u8]) -> Result<MappedIndexTable<'a>, MapError> {
+    if bytes.len() < HEADER_WORDS * 8 {
+      return Err(MapError::Header);
+    }
+    if bytes.as_ptr().align_offset(8) != 0 {
+      return Err(MapError::Alignment);
+    }
+    let words = |start: usize, n: usize| -> &'a [u64] {
+      // SAFETY: bytes is 8 byte aligned, the range was checked to lie
+      // within it and any bit pattern is a valid u64.
+      unsafe { core::slice::from_raw_parts(bytes.as_ptr().add(start) as *const u64, n) }
+    };
+    let header = words(0, HEADER_WORDS);
+    if header[0] != MAGIC {
+      return Err(MapError::Magic);
+    }
+    if header[1] != BYTE_ORDER {
+      return Err(MapError::ByteOrder);
+    }
+    if header[2] != VERSION {
+      return Err(MapError::Version(header[2]));
+    }
+    let (slots, len, home_mask) = (header[3], header[4], header[5]);
+    if !slots.is_power_of_two() || slots > isize::MAX as u64 / 64 || home_mask >= slots {
+      return Err(MapError::Header);
+    }
+    let slots = slots as usize;
+    let expected = file_bytes(slots);
+    if bytes.len() < expected {
+      return Err(MapError::Truncated {
+        expected,
+        actual: bytes.len(),
+      });
+    }
+    let bits = slots.div_ceil(64);
+    let values = PAGE + section_bytes(slots);
+    let occupied = values + section_bytes(slots);
+    let hashed = occupied + section_bytes(bits);
+    Ok(MappedIndexTable {
+      keys: words(PAGE, slots),
+      values: words(values, slots),
+      occupied: words(occupied, bits),
+      hashed: words(hashed, bits),
+      len,
+      home_mask,
+    })
+  }
+
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  fn bit(bits: &[u64], index: u64) -> bool {
+    let slot = index >> 1;
+    bits[(slot >> 6) as usize] & (1 << (slot & 63)) != 0
+  }
+
+  fn find(&self, home: u64, key: u64, hashed: bool) -> Option<u64> {
+    // A corrupt file may have every slot occupied; try_probe stops after
+    // one lap instead of spinning.
+    match self.try_probe(home, |index| {
+      self.keys[(index >> 1) as usize] == key && Self::bit(self.hashed, index) == hashed
+    }) {
+      Some((index, true)) => Some(self.values[(index >> 1) as usize]),
+      _ => None,
+    }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    self.find((scramble(key) & self.home_mask) << 1, key, false)
+  }
+
+  // Looks up an entry stored through insert_hashed.
+  pub fn get_hashed(&self, hash: u64) -> Option<u64> {
+    self.find((hash & self.home_mask) << 1, hash, true)
+  }
+
+  // Walks the entries in slot order, pre-hashed ones with their hash in
+  // place of the key.
+  pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+    (0..self.keys.len() as u64 * 2)
+      .step_by(2)
+      .filter(|index| Self::bit(self.occupied, *index))
+      .map(|index| {
+        let slot = (index >> 1) as usize;
+        (self.keys[slot], self.values[slot])
+      })
+  }
+}
+
+impl LinearProbe for MappedIndexTable<'_> {
+  fn data_mask(&self) -> u64 {
+    self.keys.len() as u64 * 2 - 1
+  }
+
+  fn slot_occupied(&self, index: u64) -> bool {
+    Self::bit(self.occupied, index)
+  }
+
+  fn home_of(&self, index: u64) -> u64 {
+    let key = self.keys[(index >> 1) as usize];
+    if Self::bit(self.hashed, index) {
+      (key & self.home_mask) << 1
+    } else {
+      (scramble(key) & self.home_mask) << 1
+    }
+  }
+
+  fn vacate(&mut self, _index: u64) {
+    unreachable!("mapped tables are read only")
+  }
+
+  fn move_slot(&mut self, _from: u64, _to: u64) {
+    unreachable!("mapped tables are read only")
+  }
+}
+
+// Read only mapping of a whole file, for MappedIndexTable::from_mmap.
+#[cfg(all(feature = "std", feature = "libc", unix))]
+pub struct Mmap {
+  ptr: *mut libc::c_void,
+  len: usize,
+}
+
+#[cfg(all(feature = "std", feature = "libc", unix))]
+impl Mmap {
+  pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Mmap> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len == 0 {
+      return Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "cannot map an empty file",
+      ));
+    }
+    // SAFETY: a fresh shared read only mapping of an open file; the result
+    // is checked before use and the mapping outlives the descriptor.
+    let ptr = unsafe {
+      libc::mmap(
+        core::ptr::null_mut(),
+        len,
+        libc::PROT_READ,
+        libc::MAP_SHARED,
+        file.as_raw_fd(),
+        0,
+      )
+    };
+    if ptr == libc::MAP_FAILED {
+      return Err(std::io::Error::last_os_error());
+    }
+    Ok(Mmap { ptr, len })
+  }
+}
+
+#[cfg(all(feature = "std", feature = "libc", unix))]
+impl core::ops::Deref for Mmap {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    // SAFETY: the mapping is len bytes, readable, and lives as long as self.
+    unsafe { core::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+  }
+}
+
+#[cfg(all(feature = "std", feature = "libc", unix))]
+impl Drop for Mmap {
+  fn drop(&mut self) {
+    // SAFETY: ptr and len are the mapping created in open.
+    unsafe { libc::munmap(self.ptr, self.len) };
+  }
+}
+
+// Copies bytes into 8 byte aligned memory, as a mapping would be.
+#[cfg(test)]
+fn aligned(bytes: &[u8]) -> Vec<u64> {
+  bytes
+    .chunks(8)
+    .map(|chunk| u64::from_ne_bytes(chunk.try_into().unwrap()))
+    .collect()
+}
+
+#[cfg(test)]
+fn as_bytes(words: &[u64]) -> &[u8] {
+  // SAFETY: any u64 is readable as 8 bytes.
+  unsafe { core::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8) }
+}
+
+#[test]
+fn test_mapped_table_matches_source() {
+  use crate::open_index_table::OpenIndexTable;
+
+  for n in [0u64, 3, 1000] {
+    let mut table = OpenIndexTable::new();
+    for key in 0..n {
+      table.insert(key * 7, key + 1);
+    }
+    table.insert_hashed(u64::MAX - 1, 42);
+    table.delete(14);
+    let bytes = table.to_mmap_bytes();
+    assert_eq!(bytes.len() % PAGE, 0);
+    let words = aligned(&bytes);
+    let mapped = MappedIndexTable::from_mmap(as_bytes(&words)).unwrap();
+    assert_eq!(mapped.len(), table.len());
+    for key in 0..n {
+      let expected = if key == 2 { None } else { Some(key + 1) };
+      assert_eq!(mapped.get(key * 7), expected);
+    }
+    assert_eq!(mapped.get(n * 7), None);
+    assert_eq!(mapped.get_hashed(u64::MAX - 1), Some(42));
+    assert_eq!(mapped.get(u64::MAX - 1), None);
+    let mut entries: Vec<(u64, u64)> = mapped.iter().collect();
+    let mut expected: Vec<(u64, u64)> = table.iter().collect();
+    entries.sort();
+    expected.sort();
+    assert_eq!(entries, expected);
+  }
+}
+
+#[test]
+fn test_mapped_table_rejects_bad_headers() {
+  use crate::open_index_table::OpenIndexTable;
+
+  let mut table = OpenIndexTable::new();
+  for key in 0..100 {
+    table.insert(key, key);
+  }
+  let words = aligned(&table.to_mmap_bytes());
+  let load = |words: &[u64]| MappedIndexTable::from_mmap(as_bytes(words)).err();
+  assert_eq!(load(&words), None);
+  let with = |word: usize, value: u64| {
+    let mut words = words.clone();
+    words[word] = value;
+    load(&words)
+  };
+  assert_eq!(with(0, 0), Some(MapError::Magic));
+  assert_eq!(with(1, BYTE_ORDER.swap_bytes()), Some(MapError::ByteOrder));
+  assert_eq!(with(2, VERSION + 1), Some(MapError::Version(VERSION + 1)));
+  assert_eq!(with(3, 100), Some(MapError::Header));
+  assert!(matches!(
+    load(&words[..words.len() - 1]),
+    Some(MapError::Truncated { .. })
+  ));
+  assert_eq!(
+    MappedIndexTable::from_mmap(&as_bytes(&words)[1..]).err(),
+    Some(MapError::Alignment)
+  );
+}
+
+#[cfg(all(feature = "std", feature = "libc", unix))]
+#[test]
+fn test_mapped_table_from_file() {
+  use crate::open_index_table::OpenIndexTable;
+
+  let mut table = OpenIndexTable::new();
+  for key in 0..100_000 {
+    table.insert(key, key ^ 0x5555);
+  }
+  let path = std::env::temp_dir().join(std::format!("cacher-mmap-{}", std::process::id()));
+  std::fs::write(&path, table.to_mmap_bytes()).unwrap();
+  let map = Mmap::open(&path);
+  std::fs::remove_file(&path).unwrap();
+  let map = map.unwrap();
+  let mapped = MappedIndexTable::from_mmap(&map).unwrap();
+  assert_eq!(mapped.len(), 100_000);
+  assert!((0..100_000).all(|key| mapped.get(key) == Some(key ^ 0x5555)));
+  assert_eq!(mapped.get(100_000), None);
+}
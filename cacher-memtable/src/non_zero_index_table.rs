@@ -0,0 +1,182 @@
+use crate::open_index_table::scramble;
+use alloc::vec::Vec;
+use core::num::NonZeroU64;
+
+// Variant of OpenIndexTable whose keys can never be zero, so a zero in the
+// slot array always marks a free slot and no side channel is needed.
+pub struct NonZeroIndexTable {
+  data: Vec<u64>,
+  data_cap: u64,
+  data_mask: u64,
+  cap: u64,
+  cap_mask: u64,
+  size: u64,
+}
+
+const FREE_KEY: u64 = 0;
+impl NonZeroIndexTable {
+  pub fn new() -> NonZeroIndexTable {
+    let initial_cap: u64 = 64;
+    NonZeroIndexTable {
+      data: alloc::vec::from_elem(0, initial_cap as usize),
+      data_mask: initial_cap - 1,
+      data_cap: initial_cap,
+      cap: ((initial_cap >> 1) / 16) * 14, // 87.5% fill
+      cap_mask: (initial_cap >> 1) - 1,
+      size: 0,
+    }
+  }
+
+  fn index(&self, k: u64) -> u64 {
+    (scramble(k) & self.cap_mask) << 1
+  }
+
+  fn next(&self, index: u64) -> u64 {
+    (index + 2) & self.data_mask
+  }
+
+  pub fn get(&self, key: NonZeroU64) -> Option<u64> {
+    let key = key.get();
+    let mut index = self.index(key);
+    loop {
+      let data = &self.data;
+      let assigned_key = data[index as usize];
+      if assigned_key == FREE_KEY {
+        return None;
+      }
+      if assigned_key == key {
+        return Some(data[index as usize + 1]);
+      }
+      index = self.next(index);
+    }
+  }
+
+  pub fn insert(&mut self, new_key: NonZeroU64, v: u64) {
+    let new_key = new_key.get();
+    let mut index = self.index(new_key);
+    loop {
+      let assigned_key = self.data[index as usize];
+      if assigned_key == new_key || assigned_key == FREE_KEY {
+        if assigned_key == FREE_KEY {
+          self.size += 1;
+          self.data[index as usize] = new_key;
+        }
+        self.data[index as usize + 1] = v;
+        break;
+      }
+      index = self.next(index);
+    }
+    self.expand();
+  }
+
+  pub fn delete(&mut self, key: NonZeroU64) -> Option<u64> {
+    let key = key.get();
+    let mut index = self.index(key);
+    loop {
+      let assigned_key = self.data[index as usize];
+      if assigned_key == FREE_KEY {
+        return None;
+      }
+      if assigned_key == key {
+        break;
+      }
+      index = self.next(index);
+    }
+    self.size -= 1;
+    self.data[index as usize] = FREE_KEY;
+    let v = self.data[index as usize + 1];
+    self.unshift(index);
+    Some(v)
+  }
+
+  fn unshift(&mut self, current: u64) {
+    let mut current = current;
+    let mut key;
+    loop {
+      let last = current;
+      current = self.next(current);
+      loop {
+        key = self.data[current as usize];
+        if key == FREE_KEY {
+          self.data[last as usize] = FREE_KEY;
+          return;
+        }
+        let slot = self.index(key);
+        if last < current {
+          if last >= slot || slot > current {
+            break;
+          }
+        } else if last >= slot && slot > current {
+          break;
+        }
+        current = self.next(current);
+      }
+      self.data[last as usize] = key;
+      self.data[last as usize + 1] = self.data[current as usize + 1];
+    }
+  }
+
+  fn expand(&mut self) {
+    if self.size <= self.cap {
+      return;
+    }
+
+    let data_cap = self.data_cap * 2;
+    let mut new = NonZeroIndexTable {
+      data: alloc::vec::from_elem(0, data_cap as usize),
+      data_cap,
+      data_mask: data_cap - 1,
+      cap_mask: (data_cap >> 1) - 1,
+      cap: self.cap * 2,
+      size: 0,
+    };
+    let mut n = 0;
+    while n < self.data_cap {
+      if let Some(key) = NonZeroU64::new(self.data[n as usize]) {
+        new.insert(key, self.data[n as usize + 1]);
+      }
+      n += 2;
+    }
+    *self = new;
+  }
+}
+
+impl Default for NonZeroIndexTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+use test::Bencher;
+
+#[cfg(test)]
+index_table_tests!(NonZeroIndexTable::new(), |k: u64| NonZeroU64::new(k)
+  .unwrap());
+
+#[cfg(test)]
+#[bench]
+fn bench_non_zero_table_insert(b: &mut Bencher) {
+  let mut table = NonZeroIndexTable::new();
+  let mut i: u64 = 1;
+  b.iter(|| {
+    table.insert(NonZeroU64::new(i).unwrap(), i);
+    i += 1;
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_non_zero_table_get(b: &mut Bencher) {
+  let mut table = NonZeroIndexTable::new();
+  let max = 2 << 24;
+  for i in 1..=max {
+    table.insert(NonZeroU64::new(i).unwrap(), i);
+  }
+  let mut i: u64 = 0;
+  b.iter(|| {
+    test::black_box(table.get(NonZeroU64::new(i + 1).unwrap()));
+    i += 1;
+    i %= max;
+  });
+}
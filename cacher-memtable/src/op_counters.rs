@@ -0,0 +1,106 @@
+use crate::open_index_table::{Entry, OpenIndexTable};
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// Snapshot of the operations a concurrent table has served since it was
+// created or its counters were last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpCounters {
+  // Lookups, split into the ones that found their key and the ones that
+  // did not.
+  pub gets: u64,
+  pub hits: u64,
+  pub misses: u64,
+  // Insert calls, overwrites among them the ones that replaced the value of
+  // a key already present.
+  pub inserts: u64,
+  pub overwrites: u64,
+  // Entries removed by delete; deletes of missing keys do not count.
+  pub deletes: u64,
+  // Rehashes of the shard tables, from growing or any other rebuild.
+  pub resizes: u64,
+}
+
+// Counters of one shard, bumped with relaxed increments next to the
+// operation they count, under whatever lock that operation takes anyway.
+// Every operation touches a single counter; gets and inserts are summed up
+// from their parts in the snapshot.
+#[derive(Default)]
+pub(crate) struct ShardCounters {
+  hits: AtomicU64,
+  misses: AtomicU64,
+  added: AtomicU64,
+  overwrites: AtomicU64,
+  deletes: AtomicU64,
+  // Shared with the resize hook of the shard table.
+  resizes: Arc<AtomicU64>,
+}
+
+impl ShardCounters {
+  // Creates the shard table, with a resize hook counting its rehashes.
+  pub(crate) fn table(&self) -> OpenIndexTable {
+    let mut table = OpenIndexTable::new();
+    let resizes = self.resizes.clone();
+    table.set_resize_hook(Box::new(move |_| {
+      resizes.fetch_add(1, Ordering::Relaxed);
+    }));
+    table
+  }
+
+  pub(crate) fn get(&self, hit: bool) {
+    let counter = if hit { &self.hits } else { &self.misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  // Inserts with a single probe, counting whether key was present.
+  pub(crate) fn insert(&self, table: &mut OpenIndexTable, key: u64, v: u64) {
+    match table.entry(key) {
+      Entry::Occupied(mut entry) => {
+        *entry.get_mut() = v;
+        self.overwrites.fetch_add(1, Ordering::Relaxed);
+      }
+      Entry::Vacant(entry) => {
+        entry.insert(v);
+        self.added.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+  }
+
+  pub(crate) fn delete(&self, removed: bool) {
+    if removed {
+      self.deletes.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  pub(crate) fn add_to(&self, total: &mut OpCounters) {
+    let (hits, misses) = (
+      self.hits.load(Ordering::Relaxed),
+      self.misses.load(Ordering::Relaxed),
+    );
+    let (added, overwrites) = (
+      self.added.load(Ordering::Relaxed),
+      self.overwrites.load(Ordering::Relaxed),
+    );
+    total.gets += hits + misses;
+    total.hits += hits;
+    total.misses += misses;
+    total.inserts += added + overwrites;
+    total.overwrites += overwrites;
+    total.deletes += self.deletes.load(Ordering::Relaxed);
+    total.resizes += self.resizes.load(Ordering::Relaxed);
+  }
+
+  pub(crate) fn reset(&self) {
+    for counter in [
+      &self.hits,
+      &self.misses,
+      &self.added,
+      &self.overwrites,
+      &self.deletes,
+      &*self.resizes,
+    ] {
+      counter.store(0, Ordering::Relaxed);
+    }
+  }
+}
@@ -0,0 +1,238 @@
+use crate::open_index_table::OpenIndexTable;
+use alloc::vec::Vec;
+
+const INLINE_VALUES: usize = 2;
+
+// Keys with few values keep them inline, larger sets spill into a list owned
+// by the table's arena.
+enum Values {
+  Inline(usize, [u64; INLINE_VALUES]),
+  Spilled(usize),
+}
+
+// Multimap from u64 keys to u64 values. Values of a key are kept in insertion
+// order and may contain duplicates.
+pub struct OpenIndexMultiTable {
+  table: OpenIndexTable,
+  values: Vec<Values>,
+  free_values: Vec<u64>,
+  arena: Vec<Vec<u64>>,
+  free_lists: Vec<usize>,
+  size: u64,
+}
+
+impl OpenIndexMultiTable {
+  pub fn new() -> OpenIndexMultiTable {
+    OpenIndexMultiTable {
+      table: OpenIndexTable::new(),
+      values: Vec::new(),
+      free_values: Vec::new(),
+      arena: Vec::new(),
+      free_lists: Vec::new(),
+      size: 0,
+    }
+  }
+
+  // Number of (key, value) pairs.
+  pub fn len(&self) -> u64 {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  pub fn key_count(&self) -> u64 {
+    self.values.len() as u64 - self.free_values.len() as u64
+  }
+
+  fn slice(&self, index: u64) -> &[u64] {
+    match &self.values[index as usize] {
+      Values::Inline(len, values) => &values[..*len],
+      Values::Spilled(list) => &self.arena[*list],
+    }
+  }
+
+  pub fn get_all(&self, key: u64) -> core::iter::Copied<core::slice::Iter<'_, u64>> {
+    match self.table.get(key) {
+      Some(index) => self.slice(index).iter().copied(),
+      None => [].iter().copied(),
+    }
+  }
+
+  pub fn count(&self, key: u64) -> u64 {
+    match self.table.get(key) {
+      Some(index) => self.slice(index).len() as u64,
+      None => 0,
+    }
+  }
+
+  pub fn insert(&mut self, key: u64, v: u64) {
+    self.size += 1;
+    let Some(index) = self.table.get(key) else {
+      let values = Values::Inline(1, [v, 0]);
+      let index = match self.free_values.pop() {
+        Some(index) => {
+          self.values[index as usize] = values;
+          index
+        }
+        None => {
+          self.values.push(values);
+          self.values.len() as u64 - 1
+        }
+      };
+      self.table.insert(key, index);
+      return;
+    };
+    match &mut self.values[index as usize] {
+      Values::Inline(len, values) if *len < INLINE_VALUES => {
+        values[*len] = v;
+        *len += 1;
+      }
+      Values::Inline(_, values) => {
+        let inline = *values;
+        let list = self.spill();
+        self.arena[list].extend_from_slice(&inline);
+        self.arena[list].push(v);
+        self.values[index as usize] = Values::Spilled(list);
+      }
+      Values::Spilled(list) => self.arena[*list].push(v),
+    }
+  }
+
+  fn spill(&mut self) -> usize {
+    match self.free_lists.pop() {
+      Some(list) => list,
+      None => {
+        self.arena.push(Vec::new());
+        self.arena.len() - 1
+      }
+    }
+  }
+
+  // Removes one occurrence of the pair, returning whether it was present.
+  pub fn remove(&mut self, key: u64, v: u64) -> bool {
+    let Some(index) = self.table.get(key) else {
+      return false;
+    };
+    let remaining = match &mut self.values[index as usize] {
+      Values::Inline(len, values) => match values[..*len].iter().position(|x| *x == v) {
+        Some(position) => {
+          values.copy_within(position + 1..*len, position);
+          *len -= 1;
+          *len
+        }
+        None => return false,
+      },
+      Values::Spilled(list) => {
+        let list = &mut self.arena[*list];
+        match list.iter().position(|x| *x == v) {
+          Some(position) => {
+            list.remove(position);
+            list.len()
+          }
+          None => return false,
+        }
+      }
+    };
+    self.size -= 1;
+    if remaining == 0 {
+      self.release(key, index);
+    }
+    true
+  }
+
+  // Removes every value of key, returning how many there were.
+  pub fn remove_all(&mut self, key: u64) -> u64 {
+    let Some(index) = self.table.get(key) else {
+      return 0;
+    };
+    let count = self.slice(index).len() as u64;
+    self.size -= count;
+    self.release(key, index);
+    count
+  }
+
+  fn release(&mut self, key: u64, index: u64) {
+    self.table.delete(key);
+    if let Values::Spilled(list) = self.values[index as usize] {
+      self.arena[list].clear();
+      self.free_lists.push(list);
+    }
+    self.values[index as usize] = Values::Inline(0, [0; INLINE_VALUES]);
+    self.free_values.push(index);
+  }
+}
+
+impl Default for OpenIndexMultiTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[test]
+fn test_multi_table_many_values() {
+  let mut table = OpenIndexMultiTable::new();
+  for v in 0..1000 {
+    table.insert(7, v);
+  }
+  table.insert(8, 1);
+  assert_eq!(table.len(), 1001);
+  assert_eq!(table.key_count(), 2);
+  assert_eq!(table.count(7), 1000);
+  assert!(table.get_all(7).eq(0..1000));
+  assert!(table.get_all(8).eq([1]));
+  assert_eq!(table.get_all(9).count(), 0);
+  assert_eq!(table.remove_all(7), 1000);
+  assert_eq!(table.remove_all(7), 0);
+  assert_eq!(table.len(), 1);
+  assert_eq!(table.key_count(), 1);
+}
+
+#[test]
+fn test_multi_table_interleaved_removes() {
+  let mut table = OpenIndexMultiTable::new();
+  table.insert(1, 10);
+  table.insert(2, 20);
+  table.insert(1, 11);
+  table.insert(1, 10);
+  table.insert(2, 21);
+  assert!(table.get_all(1).eq([10, 11, 10]));
+  assert!(table.remove(1, 10));
+  assert!(table.get_all(1).eq([11, 10]));
+  assert!(!table.remove(1, 12));
+  assert!(!table.remove(3, 10));
+  assert!(table.remove(2, 20));
+  assert!(table.get_all(2).eq([21]));
+  assert!(table.remove(1, 11));
+  assert!(table.remove(1, 10));
+  assert_eq!(table.count(1), 0);
+  assert!(!table.remove(1, 10));
+  assert_eq!(table.len(), 1);
+  // Freed inline and spilled storage is reused for new keys.
+  table.insert(3, 30);
+  table.insert(3, 31);
+  table.insert(3, 32);
+  assert!(table.get_all(3).eq([30, 31, 32]));
+  assert_eq!(table.key_count(), 2);
+}
+
+#[test]
+fn test_multi_table_after_resize() {
+  let mut table = OpenIndexMultiTable::new();
+  for key in 1..=2000 {
+    for v in 0..key % 5 {
+      table.insert(key, key * 10 + v);
+    }
+  }
+  for key in (1..=2000).step_by(3) {
+    table.remove_all(key);
+  }
+  for key in 1..=2000 {
+    if key % 3 == 1 {
+      assert_eq!(table.count(key), 0);
+    } else {
+      assert!(table.get_all(key).eq((0..key % 5).map(|v| key * 10 + v)));
+    }
+  }
+}
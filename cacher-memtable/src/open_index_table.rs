@@ -1,198 +1,4625 @@
-pub struct OpenIndexTable {
-  data: Vec<u64>,
+#[cfg(any(feature = "std", test))]
+use crate::hasher::SipHash64;
+use crate::hasher::{Hasher64, Scramble};
+use crate::mapped_index_table;
+use crate::probing::LinearProbe;
+#[cfg(feature = "raw")]
+use crate::raw::{Occupancy, RawSlots, RawSlotsMut};
+use crate::slot_array::{
+  allocated_len, capacity_overflow, AllocHint, Global, SlotAllocator, SlotArray,
+};
+use alloc::boxed::Box;
+use alloc::collections::{BinaryHeap, TryReserveError};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::time::Duration;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+// Keys and values are kept in separate arrays, so probing only walks keys
+// and a hit reads its value once. Slots are still addressed by index = 2 *
+// slot, and data_cap counts two words per slot. Whether a slot holds an
+// entry is tracked in the occupancy bitset, one bit per slot, so every u64
+// including 0 is an ordinary key. The hashed bitset marks entries stored through the
+// pre-hashed API and is only allocated once such an entry is inserted.
+//
+// A new table allocates nothing: its first INLINE_CAP entries are kept
+// packed at the front of the inline arrays and found by a linear scan
+// without hashing. The table moves to the heap once it outgrows the array
+// or a pre-hashed entry is stored, and stays there.
+//
+// Keys are hashed with H, Scramble unless the table is built with_hasher.
+// The slot arrays and bitsets are allocated from A, the global allocator
+// unless the table is built with an allocator of its own (new_in, which needs
+// the allocator_api feature). The insertion order log always uses the global
+// allocator.
+pub struct OpenIndexTable<H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  keys: SlotArray<A>,
+  values: SlotArray<A>,
+  occupancy: SlotArray<A>,
+  hashed: SlotArray<A>,
+  inline_keys: [u64; INLINE_CAP as usize],
+  inline_values: [u64; INLINE_CAP as usize],
+  order: Option<Box<InsertionOrder<H>>>,
+  resize_hook: Option<ResizeHook>,
+  #[cfg(feature = "zeroize")]
+  zeroize: bool,
+  growth_factor: f64,
+  growth_policy: GrowthPolicy,
+  auto_resize: bool,
+  bucket_mask: u64,
+  max_size: Option<u64>,
+  alloc_hint: Option<AllocHint>,
   data_cap: u64,
   data_mask: u64,
   cap: u64,
   cap_mask: u64,
   size: u64,
-  free_value: u64,
-  free_set: bool,
+  hasher: H,
+}
+
+// Reported to the resize hook after every rehash of the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeEvent {
+  // Entries the table held before it had to grow, before and after.
+  pub old_capacity: u64,
+  pub new_capacity: u64,
+  pub len: u64,
+  // Time spent rehashing; always zero without the std feature.
+  pub elapsed: Duration,
+}
+
+type ResizeHook = Box<dyn FnMut(ResizeEvent) + Send + Sync>;
+
+// Times a rehash for the resize hook, if there is one.
+struct RehashTimer {
+  #[cfg(feature = "std")]
+  started: Option<std::time::Instant>,
+}
+
+impl RehashTimer {
+  #[cfg(feature = "std")]
+  fn start(hooked: bool) -> RehashTimer {
+    RehashTimer {
+      started: hooked.then(std::time::Instant::now),
+    }
+  }
+
+  #[cfg(not(feature = "std"))]
+  fn start(_hooked: bool) -> RehashTimer {
+    RehashTimer {}
+  }
+
+  #[cfg(feature = "std")]
+  fn elapsed(&self) -> Duration {
+    self
+      .started
+      .map_or(Duration::ZERO, |started| started.elapsed())
+  }
+
+  #[cfg(not(feature = "std"))]
+  fn elapsed(&self) -> Duration {
+    Duration::ZERO
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError {
+  // The key is not in the table.
+  Missing(u64),
+}
+
+// Returned when an insert would take the table past its maximum capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityExceeded {
+  // Entries the table would hold after the insert.
+  pub requested: u64,
+  pub limit: u64,
+}
+
+// What the table does once an insert takes it past its fill limit. Slot
+// counts stay powers of two under every policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrowthPolicy {
+  // Grows by the growth factor, doubling by default.
+  Double,
+  // Grows like Double while the key and value arrays stay within this many
+  // bytes, at least those of the smallest heap table. Once the largest such
+  // table is full, insert_checked rejects new keys and insert panics, as
+  // with with_max_capacity.
+  DoubleUntil(usize),
+  // Raises the fill limit by 1/64 of the slots, at least one, each time it
+  // is passed, from 87.5% up to 93.75%, and only grows after that. Fuller
+  // tables probe longer but postpone the next doubling of memory.
+  Chunked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertOutcome {
+  Inserted,
+  // The key was already present with this value, which was kept.
+  Existing(u64),
+}
+
+// The splitmix64 finalizer. Every key bit affects every hash bit, so tables
+// can take their slot from the low bits even for keys that only differ high
+// up, like multiples of a large power of two.
+pub(crate) fn scramble(k: u64) -> u64 {
+  let mut hash = k;
+  hash ^= hash >> 30;
+  hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  hash ^= hash >> 27;
+  hash = hash.wrapping_mul(0x94d0_49bb_1331_11eb);
+  hash ^ (hash >> 31)
+}
+
+fn occupancy_words(data_cap: u64) -> u64 {
+  ((data_cap >> 1) + 63) >> 6
+}
+
+const MIN_DATA_CAP: u64 = 64;
+// Keys per 64 byte cache line, the bucket size with_cache_line_buckets
+// hashes to.
+const BUCKET_SLOTS: u64 = 8;
+// Entries a table holds before it allocates.
+const INLINE_CAP: u64 = 8;
+
+// Number of entries a table with data_cap data length holds before growing.
+fn cap_for(data_cap: u64) -> u64 {
+  ((data_cap >> 1) / 16) * 14 // 87.5% fill
+}
+
+// Most slots a table may have: one more and its key array alone would
+// exceed isize::MAX bytes, the limit of any allocation.
+const MAX_SLOTS: u64 = isize::MAX as u64 / 8;
+
+// Slot count for data_cap as a usize, or None if it exceeds MAX_SLOTS.
+fn slot_len(data_cap: u64) -> Option<usize> {
+  let slots = data_cap >> 1;
+  (slots <= MAX_SLOTS).then_some(slots as usize)
+}
+
+// Twice the slot count a table picks to hold n entries without growing,
+// counting the inline entries of a table holding at most INLINE_CAP as slots.
+//
+// Panics if that exceeds MAX_SLOTS.
+pub fn capacity_for(n: usize) -> usize {
+  if n as u64 <= INLINE_CAP {
+    return 2 * INLINE_CAP as usize;
+  }
+  heap_capacity_for(n)
 }
 
-fn scramble(k: u64) -> u64 {
-  let hash = k * 0x9E3779B9;
-  hash * (hash >> 16)
+// capacity_for for a table on the heap, whatever n.
+pub(crate) fn heap_capacity_for(n: usize) -> usize {
+  let mut data_cap = MIN_DATA_CAP;
+  while cap_for(data_cap) < n as u64 {
+    data_cap *= 2;
+    assert!(slot_len(data_cap).is_some(), "capacity overflow");
+  }
+  data_cap as usize
 }
-const FREE_KEY: u64 = 0;
+
 impl OpenIndexTable {
   pub fn new() -> OpenIndexTable {
-    let initial_cap: u64 = 64;
+    OpenIndexTable::with_hasher(Scramble)
+  }
+
+  // Creates a table holding n entries before it has to grow.
+  pub fn with_capacity(n: usize) -> OpenIndexTable {
+    OpenIndexTable::with_capacity_and_hasher(n, Scramble)
+  }
+
+  // Like with_capacity, but returns an error instead of panicking or
+  // aborting if n entries need more than isize::MAX bytes or the arrays fail
+  // to allocate.
+  pub fn try_with_capacity(n: usize) -> Result<OpenIndexTable, TryReserveError> {
+    let mut table = OpenIndexTable::new();
+    table.try_reserve(n as u64)?;
+    Ok(table)
+  }
+
+  // Like with_capacity, but touches every page of the new arrays, so filling
+  // the table and looking up in it never waits on a page fault. Arrays
+  // allocated when the table grows later are faulted in lazily as usual.
+  pub fn with_capacity_prefaulted(n: usize) -> OpenIndexTable {
+    let mut table = OpenIndexTable::with_capacity(n);
+    if !table.is_inline() {
+      table.keys.prefault();
+      table.values.prefault();
+      table.occupancy.prefault();
+    }
+    table
+  }
+}
+
+#[cfg(feature = "std")]
+impl OpenIndexTable<SipHash64> {
+  // Creates a table hashing keys with SipHash under a random key, for keys
+  // from untrusted input.
+  pub fn with_sip_hash() -> OpenIndexTable<SipHash64> {
+    OpenIndexTable::with_hasher(SipHash64::random())
+  }
+}
+
+impl<H: Hasher64> OpenIndexTable<H> {
+  pub fn with_hasher(hasher: H) -> OpenIndexTable<H> {
+    OpenIndexTable::with_hasher_in(hasher, Global)
+  }
+
+  pub fn with_capacity_and_hasher(n: usize, hasher: H) -> OpenIndexTable<H> {
+    OpenIndexTable::with_capacity_and_hasher_in(n, hasher, Global)
+  }
+}
+
+impl<A: SlotAllocator> OpenIndexTable<Scramble, A> {
+  pub fn new_in(alloc: A) -> OpenIndexTable<Scramble, A> {
+    OpenIndexTable::with_hasher_in(Scramble, alloc)
+  }
+
+  pub fn with_capacity_in(n: usize, alloc: A) -> OpenIndexTable<Scramble, A> {
+    OpenIndexTable::with_capacity_and_hasher_in(n, Scramble, alloc)
+  }
+
+  // Writes the slot arrays as they are, page aligned behind a header, for
+  // MappedIndexTable::from_mmap to use in place; see mapped_index_table for
+  // the layout and its byte order and version rules. Only Scramble hashed
+  // tables, since the reader has to find the same home slots.
+  pub fn to_mmap_bytes(&self) -> Vec<u8> {
+    if self.is_inline() {
+      // Inline entries are not hashed into slots; spread them out first.
+      let mut heap = OpenIndexTable::with_data_cap(MIN_DATA_CAP, Scramble, Global);
+      for (key, v) in self.iter() {
+        heap.insert(key, v);
+      }
+      return heap.to_mmap_bytes();
+    }
+    mapped_index_table::layout(
+      &self.keys,
+      &self.values,
+      &self.occupancy,
+      &self.hashed,
+      self.size,
+      self.home_mask(),
+    )
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  pub fn with_hasher_in(hasher: H, alloc: A) -> OpenIndexTable<H, A> {
+    // The inline arrays stand in for the slot arrays, with is_occupied true
+    // for the packed prefix, so slot walks work in either mode.
+    OpenIndexTable {
+      keys: SlotArray::new_in(alloc.clone()),
+      values: SlotArray::new_in(alloc.clone()),
+      occupancy: SlotArray::new_in(alloc.clone()),
+      hashed: SlotArray::new_in(alloc),
+      inline_keys: [0; INLINE_CAP as usize],
+      inline_values: [0; INLINE_CAP as usize],
+      order: None,
+      resize_hook: None,
+      #[cfg(feature = "zeroize")]
+      zeroize: false,
+      growth_factor: 2.0,
+      growth_policy: GrowthPolicy::Double,
+      auto_resize: true,
+      bucket_mask: u64::MAX,
+      max_size: None,
+      alloc_hint: None,
+      data_cap: 2 * INLINE_CAP,
+      data_mask: 2 * INLINE_CAP - 1,
+      cap: INLINE_CAP,
+      cap_mask: INLINE_CAP - 1,
+      size: 0,
+      hasher,
+    }
+  }
+
+  pub fn with_capacity_and_hasher_in(n: usize, hasher: H, alloc: A) -> OpenIndexTable<H, A> {
+    if n as u64 <= INLINE_CAP {
+      return OpenIndexTable::with_hasher_in(hasher, alloc);
+    }
+    OpenIndexTable::with_data_cap(capacity_for(n) as u64, hasher, alloc)
+  }
+
+  fn with_data_cap(data_cap: u64, hasher: H, alloc: A) -> OpenIndexTable<H, A> {
+    let slots = slot_len(data_cap).expect("capacity overflow");
     OpenIndexTable {
-      data: std::vec::from_elem(0, initial_cap as usize),
-      data_mask: initial_cap - 1,
-      data_cap: initial_cap,
-      cap: ((initial_cap >> 1) / 16) * 14, // 87.5% fill
-      cap_mask: (initial_cap >> 1) - 1,
-      free_value: 0,
-      free_set: false,
+      keys: SlotArray::zeroed(slots, None, alloc.clone()),
+      values: SlotArray::zeroed(slots, None, alloc.clone()),
+      occupancy: SlotArray::zeroed(occupancy_words(data_cap) as usize, None, alloc.clone()),
+      hashed: SlotArray::new_in(alloc),
+      inline_keys: [0; INLINE_CAP as usize],
+      inline_values: [0; INLINE_CAP as usize],
+      order: None,
+      resize_hook: None,
+      #[cfg(feature = "zeroize")]
+      zeroize: false,
+      growth_factor: 2.0,
+      growth_policy: GrowthPolicy::Double,
+      auto_resize: true,
+      bucket_mask: u64::MAX,
+      max_size: None,
+      alloc_hint: None,
+      data_mask: data_cap - 1,
+      data_cap,
+      cap: cap_for(data_cap),
+      cap_mask: (data_cap >> 1) - 1,
       size: 0,
+      hasher,
+    }
+  }
+
+  // Makes the table remember the order keys were first inserted in, so
+  // iter_ordered is deterministic regardless of capacity and resizes. Entries
+  // already in the table are ordered by their slot. Costs memory only once
+  // enabled.
+  pub fn with_insertion_order(mut self) -> OpenIndexTable<H, A> {
+    let mut order = InsertionOrder::new(self.hasher.clone());
+    for index in (0..self.data_cap).step_by(2) {
+      if self.is_occupied(index) {
+        order.push(self.key_at(index), self.is_hashed(index));
+      }
+    }
+    self.order = Some(Box::new(order));
+    self
+  }
+
+  // Sets how much the table grows when it is full: the new data length is
+  // the next power of two at or above the current one times factor. Sizes
+  // stay powers of two, so growth is quantized: any factor up to 2.0 still
+  // doubles, and e.g. 3.0 quadruples. Panics unless factor is above 1.0.
+  pub fn with_growth_factor(mut self, factor: f64) -> OpenIndexTable<H, A> {
+    assert!(factor > 1.0, "growth factor must be above 1.0");
+    self.growth_factor = factor;
+    self
+  }
+
+  // Overwrites the slots entries leave with zeros, whether deleted, moved by
+  // a rehash or dropped with the table, so no copy of a value is left in
+  // memory once it is gone. The writes are volatile and cannot be optimized
+  // away. Iterators consuming the table scrub its arrays when dropped.
+  #[cfg(feature = "zeroize")]
+  pub fn with_zeroize(mut self) -> OpenIndexTable<H, A> {
+    self.zeroize = true;
+    self
+  }
+
+  // Stops inserts from growing the table once it passes its fill limit, so
+  // the rehash can be done with resize at a convenient time instead. Inserts
+  // still grow the table when only one free slot is left, since probing
+  // needs a free slot to end on, and an inline table still moves to the heap
+  // when it runs out of room.
+  pub fn with_manual_resize(mut self) -> OpenIndexTable<H, A> {
+    self.auto_resize = false;
+    self
+  }
+
+  // Sends every key to the first slot of a bucket of BUCKET_SLOTS slots, whose
+  // keys fill one 64 byte cache line, instead of to a slot of its own. Short
+  // probe chains then stay within one or two lines, at the cost of keys
+  // sharing a bucket always scanning past each other. Probing, deletion and
+  // growth work as before since only the home slot changes.
+  pub fn with_cache_line_buckets(mut self) -> OpenIndexTable<H, A> {
+    self.bucket_mask = !(BUCKET_SLOTS - 1);
+    if !self.is_inline() {
+      self.rebuild(self.data_cap);
+    }
+    self
+  }
+
+  // Allocates the key and value arrays as hint asks, from now on and for
+  // every resize. Panics if an Aligned hint is not a power of two.
+  pub fn alloc_hint(mut self, hint: AllocHint) -> OpenIndexTable<H, A> {
+    if let AllocHint::Aligned(align) = hint {
+      assert!(align.is_power_of_two(), "alignment must be a power of two");
+    }
+    self.alloc_hint = Some(hint);
+    if !self.is_inline() {
+      self.rebuild(self.data_cap);
+    }
+    self
+  }
+
+  // Caps the table at entries entries: it never allocates more slots than
+  // they need, insert_checked rejects new keys once the table is full, and
+  // any other insert of a new key panics. Updates of present keys always
+  // succeed. The table has no eviction policy; LruTable and the other caches
+  // evict instead. Combined with with_max_memory the lower cap applies.
+  // Panics if the table already holds more entries.
+  pub fn with_max_capacity(mut self, entries: u64) -> OpenIndexTable<H, A> {
+    assert!(self.size <= entries, "table already holds more entries");
+    self.max_size = Some(self.max_size.map_or(entries, |max| max.min(entries)));
+    self
+  }
+
+  // Caps the table at the entries of the largest table whose memory_bytes,
+  // leaving out the insertion order log, fits in bytes, with room for
+  // pre-hashed entries and the alloc hint set so far. Otherwise it behaves
+  // like with_max_capacity. Panics if not even the smallest heap table fits
+  // or the table already holds more entries.
+  pub fn with_max_memory(self, bytes: usize) -> OpenIndexTable<H, A> {
+    let entries = self
+      .entries_within(bytes)
+      .expect("memory budget is below the smallest table");
+    self.with_max_capacity(entries)
+  }
+
+  // Entries of the largest table fitting in bytes as with_max_memory counts
+  // them, or None if not even the smallest heap table fits.
+  pub(crate) fn entries_within(&self, bytes: usize) -> Option<u64> {
+    let fits = |data_cap: u64| {
+      self
+        .heap_bytes(data_cap)
+        .is_some_and(|needed| needed <= bytes)
+    };
+    if !fits(MIN_DATA_CAP) {
+      return None;
+    }
+    let mut data_cap = MIN_DATA_CAP;
+    while data_cap < 1 << 62 && fits(data_cap * 2) {
+      data_cap *= 2;
+    }
+    Some(cap_for(data_cap))
+  }
+
+  // memory_bytes of a heap table with data_cap data length holding a
+  // pre-hashed entry, without the insertion order log.
+  fn heap_bytes(&self, data_cap: u64) -> Option<usize> {
+    let slots = usize::try_from(data_cap >> 1).ok()?;
+    let words = occupancy_words(data_cap) as usize;
+    let arrays = allocated_len(slots, self.alloc_hint)
+      .checked_mul(2)?
+      .checked_add(2 * words)?;
+    arrays
+      .checked_mul(core::mem::size_of::<u64>())?
+      .checked_add(core::mem::size_of::<Self>())
+  }
+
+  // Sets how the table grows once it passes its fill limit. Panics if the
+  // table already holds more entries than the policy allows.
+  pub fn with_growth_policy(mut self, policy: GrowthPolicy) -> OpenIndexTable<H, A> {
+    self.growth_policy = policy;
+    if let Some(limit) = self.size_limit() {
+      assert!(self.size <= limit, "table already holds more entries");
+    }
+    self
+  }
+
+  // Calls hook after every rehash: growing, shrinking, compact and the bulk
+  // rebuilds of delete_many, replacing any earlier hook. The hook gets the
+  // event by value and cannot reach the table, which stays borrowed while the
+  // hook runs. Clones and partitions of the table do not inherit it.
+  pub fn set_resize_hook(&mut self, hook: Box<dyn FnMut(ResizeEvent) + Send + Sync>) {
+    self.resize_hook = Some(hook);
+  }
+
+  // Most entries the table may hold, from with_max_capacity or the growth
+  // policy, whichever is lower.
+  pub(crate) fn size_limit(&self) -> Option<u64> {
+    match self.growth_policy {
+      GrowthPolicy::DoubleUntil(bytes) => {
+        let slots = (bytes as u64 / 16).max(MIN_DATA_CAP >> 1);
+        let budget = cap_for(2 << slots.ilog2());
+        Some(self.max_size.map_or(budget, |max| max.min(budget)))
+      }
+      _ => self.max_size,
+    }
+  }
+
+  // Whether inserting a new key would take the table past its fill limit.
+  pub fn needs_resize(&self) -> bool {
+    self.size >= self.cap
+  }
+
+  // Grows the table by its growth factor and rehashes every entry.
+  //
+  // Panics if the grown table would exceed MAX_SLOTS.
+  pub fn resize(&mut self) {
+    let data_cap = self
+      .grown_data_cap(self.data_cap)
+      .expect("capacity overflow")
+      .max(MIN_DATA_CAP);
+    self.rebuild(self.limit_data_cap(data_cap));
+  }
+
+  // Data length growing by the growth factor takes the table to from
+  // data_cap, or None if that exceeds MAX_SLOTS.
+  fn grown_data_cap(&self, data_cap: u64) -> Option<u64> {
+    // The cast saturates, and a saturated size has no next power of two.
+    let grown = ((data_cap as f64 * self.growth_factor) as u64).max(data_cap + 1);
+    grown
+      .checked_next_power_of_two()
+      .filter(|data_cap| slot_len(*data_cap).is_some())
+  }
+
+  // Clamps a grown data length to the largest table the maximum capacity
+  // allows, if one is set, never below the current one.
+  fn limit_data_cap(&self, data_cap: u64) -> u64 {
+    match self.size_limit() {
+      Some(max) => data_cap.min((heap_capacity_for(max as usize) as u64).max(self.data_cap)),
+      None => data_cap,
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  // Live entries relative to the number the table holds before growing. At
+  // 1.0 the next new key triggers a resize.
+  pub fn load_factor(&self) -> f64 {
+    self.size as f64 / self.cap as f64
+  }
+
+  // Number of entries the table holds before the next new key grows it.
+  pub fn capacity(&self) -> u64 {
+    self.cap
+  }
+
+  // Bytes allocated by the table, including the struct itself. This is the
+  // allocation size, not the size of the live entries, so it only changes
+  // when the table grows or is shrunk.
+  pub fn memory_bytes(&self) -> usize {
+    let order = self.order.as_ref().map_or(0, |order| order.memory_bytes());
+    (self.keys.capacity()
+      + self.values.capacity()
+      + self.occupancy.capacity()
+      + self.hashed.capacity())
+      * core::mem::size_of::<u64>()
+      + core::mem::size_of::<Self>()
+      + order
+  }
+
+  // Same as memory_bytes.
+  pub fn memory_usage(&self) -> usize {
+    self.memory_bytes()
+  }
+
+  // Bytes of the backing allocation occupied by live entries. Comparing it to
+  // memory_usage tells how much a shrink could give back.
+  pub fn memory_usage_live(&self) -> usize {
+    self.size as usize * 2 * core::mem::size_of::<u64>()
+  }
+
+  // Allocator the slot arrays are allocated from.
+  pub fn allocator(&self) -> &A {
+    self.keys.allocator()
+  }
+
+  fn is_inline(&self) -> bool {
+    self.keys.is_empty()
+  }
+
+  fn key_at(&self, index: u64) -> u64 {
+    let slot = (index >> 1) as usize;
+    if self.is_inline() {
+      self.inline_keys[slot]
+    } else {
+      self.keys[slot]
+    }
+  }
+
+  fn value_at(&self, index: u64) -> u64 {
+    let slot = (index >> 1) as usize;
+    if self.is_inline() {
+      self.inline_values[slot]
+    } else {
+      self.values[slot]
+    }
+  }
+
+  fn value_mut(&mut self, index: u64) -> &mut u64 {
+    let slot = (index >> 1) as usize;
+    if self.is_inline() {
+      &mut self.inline_values[slot]
+    } else {
+      &mut self.values[slot]
     }
   }
 
   fn index(&self, k: u64) -> u64 {
-    (scramble(k) & self.cap_mask) << 1
+    #[cfg(test)]
+    HASH_CALLS.with(|calls| calls.set(calls.get() + 1));
+    (self.hasher.hash(k) & self.cap_mask & self.bucket_mask) << 1
+  }
+
+  fn hashed_index(&self, hash: u64) -> u64 {
+    (hash & self.cap_mask & self.bucket_mask) << 1
+  }
+
+  // Home slot of the entry stored at index.
+  fn home(&self, index: u64) -> u64 {
+    let key = self.keys[(index >> 1) as usize];
+    if self.is_hashed(index) {
+      self.hashed_index(key)
+    } else {
+      self.index(key)
+    }
+  }
+
+  fn is_occupied(&self, index: u64) -> bool {
+    if self.is_inline() {
+      return index < self.size << 1;
+    }
+    self.occupancy_bit(index)
+  }
+
+  // is_occupied for a table known to be on the heap.
+  fn occupancy_bit(&self, index: u64) -> bool {
+    let slot = index >> 1;
+    self.occupancy[(slot >> 6) as usize] & (1 << (slot & 63)) != 0
+  }
+
+  fn set_occupied(&mut self, index: u64) {
+    if self.is_inline() {
+      return;
+    }
+    let slot = index >> 1;
+    self.occupancy[(slot >> 6) as usize] |= 1 << (slot & 63);
+  }
+
+  fn clear_occupied(&mut self, index: u64) {
+    let slot = index >> 1;
+    self.occupancy[(slot >> 6) as usize] &= !(1 << (slot & 63));
+  }
+
+  fn is_hashed(&self, index: u64) -> bool {
+    let slot = index >> 1;
+    !self.hashed.is_empty() && self.hashed[(slot >> 6) as usize] & (1 << (slot & 63)) != 0
+  }
+
+  fn set_hashed(&mut self, index: u64, hashed: bool) {
+    let slot = index >> 1;
+    if self.hashed.is_empty() {
+      if !hashed {
+        return;
+      }
+      self.hashed = SlotArray::zeroed(self.occupancy.len(), None, self.allocator().clone());
+    }
+    if hashed {
+      self.hashed[(slot >> 6) as usize] |= 1 << (slot & 63);
+    } else {
+      self.hashed[(slot >> 6) as usize] &= !(1 << (slot & 63));
+    }
+  }
+
+  // Probes for key, returning its slot and true, or the free slot ending its
+  // probe chain and false.
+  fn find(&self, key: u64) -> (u64, bool) {
+    if self.is_inline() {
+      return self.find_inline(key, false);
+    }
+    self.find_from(self.index(key), key, false)
+  }
+
+  // Keys and pre-hashed values live side by side, so a slot only matches if
+  // it was stored through the same API.
+  fn find_from(&self, home: u64, key: u64, hashed: bool) -> (u64, bool) {
+    if self.is_inline() {
+      return self.find_inline(key, hashed);
+    }
+    let keys: &[u64] = &self.keys;
+    self.probe(home, |index| {
+      keys[(index >> 1) as usize] == key && self.is_hashed(index) == hashed
+    })
+  }
+
+  // Scans the packed inline entries. Pre-hashed entries are never inline.
+  fn find_inline(&self, key: u64, hashed: bool) -> (u64, bool) {
+    if !hashed {
+      let live = &self.inline_keys[..self.size as usize];
+      if let Some(slot) = live.iter().position(|stored| *stored == key) {
+        return ((slot as u64) << 1, true);
+      }
+    }
+    (self.size << 1, false)
+  }
+
+  // Returns the value of key, or None if it is missing, so a stored 0 reads
+  // as Some(0).
+  pub fn get(&self, key: u64) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => Some(self.value_at(index)),
+      _ => None,
+    }
+  }
+
+  // Returns the value of key, or default if it is missing.
+  pub fn get_or(&self, key: u64, default: u64) -> u64 {
+    self.get(key).unwrap_or(default)
+  }
+
+  // Returns the value of key, or 0 if it is missing. Suits counters, where a
+  // missing key and a count of 0 mean the same.
+  pub fn get_or_default(&self, key: u64) -> u64 {
+    self.get_or(key, 0)
+  }
+
+  // Inserts or overwrites key, returning the value it replaced.
+  pub fn insert(&mut self, new_key: u64, v: u64) -> Option<u64> {
+    let (index, found) = self.find(new_key);
+    let replaced = found.then(|| self.value_at(index));
+    self.insert_at(index, found, new_key, v, false);
+    replaced
+  }
+
+  // Inserts like insert, but a new key that would take the table past its
+  // maximum capacity is rejected and the table left unchanged.
+  pub fn insert_checked(&mut self, key: u64, v: u64) -> Result<(), CapacityExceeded> {
+    let (index, found) = self.find(key);
+    match self.size_limit() {
+      Some(limit) if !found && self.size >= limit => Err(CapacityExceeded {
+        requested: self.size + 1,
+        limit,
+      }),
+      _ => {
+        self.insert_at(index, found, key, v, false);
+        Ok(())
+      }
+    }
+  }
+
+  // Inserts key only if it is missing, otherwise returns the stored value
+  // and leaves it untouched. Key 0 is handled like any other key.
+  pub fn try_insert(&mut self, key: u64, v: u64) -> Result<(), u64> {
+    match self.find(key) {
+      (index, true) => Err(self.value_at(index)),
+      (index, false) => {
+        self.insert_at(index, false, key, v, false);
+        Ok(())
+      }
+    }
+  }
+
+  fn insert_at(&mut self, index: u64, found: bool, key: u64, v: u64, hashed: bool) {
+    let index = if found {
+      index
+    } else {
+      self.occupy(index, key, hashed)
+    };
+    *self.value_mut(index) = v;
+    self.expand();
+  }
+
+  // Stores a new key in the free slot at index, returning the slot it ended
+  // up in. That is index unless the key did not fit inline and the table
+  // moved to the heap.
+  fn occupy(&mut self, index: u64, key: u64, hashed: bool) -> u64 {
+    if let Some(limit) = self.size_limit() {
+      assert!(
+        self.size < limit,
+        "table is at its maximum capacity of {limit} entries"
+      );
+    }
+    let mut index = index;
+    if self.is_inline() && (hashed || self.size == INLINE_CAP) {
+      self.rebuild(MIN_DATA_CAP);
+      let home = if hashed {
+        self.hashed_index(key)
+      } else {
+        self.index(key)
+      };
+      index = self.find_from(home, key, hashed).0;
+    }
+    self.size += 1;
+    let slot = (index >> 1) as usize;
+    if self.is_inline() {
+      self.inline_keys[slot] = key;
+    } else {
+      self.keys[slot] = key;
+    }
+    self.set_occupied(index);
+    self.set_hashed(index, hashed);
+    if let Some(order) = &mut self.order {
+      order.push(key, hashed);
+    }
+    index
+  }
+
+  // Returns the value of key, first inserting default if it is missing. The
+  // key is hashed and probed once; a miss fills the free slot the probe
+  // stopped at before the table grows.
+  pub fn get_or_insert(&mut self, key: u64, default: u64) -> u64 {
+    *self.entry(key).or_insert(default)
+  }
+
+  // Applies f to the value of key in place, inserting default first if key
+  // is missing. The slot is located once; a new key may grow the table.
+  pub fn upsert_modify(&mut self, key: u64, default: u64, f: impl FnOnce(&mut u64)) {
+    f(self.entry(key).or_insert(default));
+  }
+
+  // Inserts f() for key if it is missing. f is only called when the key is
+  // vacant, so expensive values are not computed for keys already present.
+  pub fn insert_with(&mut self, key: u64, f: impl FnOnce() -> u64) -> InsertOutcome {
+    match self.find(key) {
+      (index, true) => InsertOutcome::Existing(self.value_at(index)),
+      (index, false) => {
+        self.insert_at(index, false, key, f(), false);
+        InsertOutcome::Inserted
+      }
+    }
+  }
+
+  // Updates key in a single probe: f gets the current value, or None if key
+  // is missing, and returns the new value, or None to remove the key.
+  // Returns the previous value.
+  pub fn fetch_update(
+    &mut self,
+    key: u64,
+    f: impl FnOnce(Option<u64>) -> Option<u64>,
+  ) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => {
+        let previous = self.value_at(index);
+        match f(Some(previous)) {
+          Some(v) => *self.value_mut(index) = v,
+          None => {
+            self.remove_at(index);
+          }
+        }
+        Some(previous)
+      }
+      (index, false) => {
+        if let Some(v) = f(None) {
+          self.insert_at(index, false, key, v, false);
+        }
+        None
+      }
+    }
+  }
+
+  // Removes key, returning its value, or None if it was missing.
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    match self.find(key) {
+      (index, true) => Some(self.remove_at(index)),
+      _ => None,
+    }
+  }
+
+  // Removes key only if pred holds for its current value, returning the
+  // removed value. Key 0 is handled like any other key.
+  pub fn remove_if(&mut self, key: u64, pred: impl FnOnce(u64) -> bool) -> Option<u64> {
+    match self.find(key) {
+      (index, true) if pred(self.value_at(index)) => Some(self.remove_at(index)),
+      _ => None,
+    }
+  }
+
+  // Deletes every key in keys, returning how many were present. Duplicate
+  // and missing keys are fine. When the batch is a large fraction of the
+  // table, the survivors are rehashed into a fresh table in one pass instead
+  // of probing and shifting once per key.
+  pub fn delete_many(&mut self, keys: &[u64]) -> u64 {
+    let before = self.size;
+    // Rebuilding would lose the insertion order.
+    if self.order.is_some() || (keys.len() as u64) < self.size / 4 {
+      for &key in keys {
+        self.delete(key);
+      }
+    } else {
+      let mut doomed = OpenIndexTable::with_capacity_and_hasher(keys.len(), self.hasher.clone());
+      for &key in keys {
+        doomed.insert(key, 0);
+      }
+      let timer = RehashTimer::start(self.resize_hook.is_some());
+      let mut new = self.heap_like(self.data_cap);
+      self.move_entries_into_filtered(&mut new, |key, hashed| hashed || doomed.get(key).is_none());
+      self.replace_rehashed(new, timer);
+    }
+    before - self.size
+  }
+
+  // The pre-hashed API takes a hash computed by the caller and uses it for
+  // slot selection without mixing it again, so the caller guarantees that
+  // its low bits are uniformly distributed. The hash is also the stored key.
+  // Entries stored this way never match the plain API for the same u64.
+  pub fn get_hashed(&self, hash: u64) -> Option<u64> {
+    match self.find_from(self.hashed_index(hash), hash, true) {
+      (index, true) => Some(self.value_at(index)),
+      _ => None,
+    }
+  }
+
+  pub fn insert_hashed(&mut self, hash: u64, v: u64) {
+    let (index, found) = self.find_from(self.hashed_index(hash), hash, true);
+    self.insert_at(index, found, hash, v, true);
+  }
+
+  pub fn delete_hashed(&mut self, hash: u64) -> Option<u64> {
+    match self.find_from(self.hashed_index(hash), hash, true) {
+      (index, true) => Some(self.remove_at(index)),
+      _ => None,
+    }
+  }
+
+  // The precomputed API takes the home slot of a plain key, its hash masked
+  // with home_mask, from a caller that already has the hash and skips
+  // hashing it again. Entries are ordinary keys, so the home has to be the
+  // one the table's hasher gives; debug builds check that. The mask changes
+  // as the table grows, so it has to be read again for every call.
+  pub fn home_mask(&self) -> u64 {
+    self.cap_mask & self.bucket_mask
+  }
+
+  fn precomputed_index(&self, home: u64, key: u64) -> u64 {
+    assert!(
+      home & !self.home_mask() == 0,
+      "home slot {home} is outside the home mask {}",
+      self.home_mask()
+    );
+    debug_assert_eq!(
+      home,
+      self.hasher.hash(key) & self.home_mask(),
+      "home slot does not match the hash of the key"
+    );
+    home << 1
+  }
+
+  // Same as get, probing from home.
+  pub fn get_precomputed(&self, home: u64, key: u64) -> Option<u64> {
+    match self.find_from(self.precomputed_index(home, key), key, false) {
+      (index, true) => Some(self.value_at(index)),
+      _ => None,
+    }
+  }
+
+  // Same as insert, probing from home.
+  pub fn insert_precomputed(&mut self, home: u64, key: u64, v: u64) {
+    let (index, found) = self.find_from(self.precomputed_index(home, key), key, false);
+    self.insert_at(index, found, key, v, false);
+  }
+
+  // Returns the key as stored along with its value.
+  pub fn get_key_value(&self, key: u64) -> Option<(u64, u64)> {
+    match self.find(key) {
+      (index, true) => Some((self.key_at(index), self.value_at(index))),
+      _ => None,
+    }
+  }
+
+  // Whether any entry holds value. Scans every slot, so O(n) in the capacity;
+  // meant for debugging and assertions rather than lookups.
+  pub fn contains_value(&self, value: u64) -> bool {
+    self.iter().any(|(_, v)| v == value)
+  }
+
+  // Smallest key in the table. Scans every slot, so O(n) in the capacity.
+  // Pre-hashed entries count with their hash.
+  pub fn min_key(&self) -> Option<u64> {
+    self.iter().map(|(key, _)| key).min()
+  }
+
+  // Largest key in the table, scanning like min_key.
+  pub fn max_key(&self) -> Option<u64> {
+    self.iter().map(|(key, _)| key).max()
+  }
+
+  // Removes key, returning the stored key and value. The pair is read before
+  // the backward shift moves other entries into the slot.
+  pub fn remove_entry(&mut self, key: u64) -> Option<(u64, u64)> {
+    match self.entry(key) {
+      Entry::Occupied(entry) => {
+        let stored = entry.key();
+        Some((stored, entry.remove()))
+      }
+      Entry::Vacant(_) => None,
+    }
+  }
+
+  // Exchanges the values of a and b in place. Nothing changes if either key
+  // is missing.
+  pub fn swap_values(&mut self, a: u64, b: u64) -> Result<(), SwapError> {
+    let (index_a, found) = self.find(a);
+    if !found {
+      return Err(SwapError::Missing(a));
+    }
+    let (index_b, found) = self.find(b);
+    if !found {
+      return Err(SwapError::Missing(b));
+    }
+    let v = self.value_at(index_a);
+    *self.value_mut(index_a) = self.value_at(index_b);
+    *self.value_mut(index_b) = v;
+    Ok(())
+  }
+
+  // Mutable references to the values of two keys at once, None for a
+  // missing key. Panics if a and b are the same key.
+  pub fn get_disjoint_mut(&mut self, a: u64, b: u64) -> (Option<&mut u64>, Option<&mut u64>) {
+    assert_ne!(a, b, "keys must be distinct");
+    let (index_a, found_a) = self.find(a);
+    let (index_b, found_b) = self.find(b);
+    let values: &mut [u64] = if self.is_inline() {
+      &mut self.inline_values
+    } else {
+      &mut self.values
+    };
+    let (slot_a, slot_b) = ((index_a >> 1) as usize, (index_b >> 1) as usize);
+    match (found_a, found_b) {
+      (true, true) => {
+        let [a, b] = values
+          .get_disjoint_mut([slot_a, slot_b])
+          .expect("distinct keys occupy distinct slots");
+        (Some(a), Some(b))
+      }
+      (true, false) => (Some(&mut values[slot_a]), None),
+      (false, true) => (None, Some(&mut values[slot_b])),
+      (false, false) => (None, None),
+    }
+  }
+
+  // The k entries with the largest values, sorted descending by value. Ties
+  // are ordered by ascending key.
+  pub fn top_k(&self, k: usize) -> Vec<(u64, u64)> {
+    self.top_k_by(k, |_, v| v)
+  }
+
+  // The k entries with the largest score(key, value), sorted descending by
+  // score and then ascending by key. Only k entries are buffered at a time.
+  pub fn top_k_by(&self, k: usize, mut score: impl FnMut(u64, u64) -> u64) -> Vec<(u64, u64)> {
+    if k == 0 {
+      return Vec::new();
+    }
+    // Min-heap whose top is the entry to drop first.
+    let mut heap = BinaryHeap::with_capacity(k.min(self.size as usize));
+    for (key, v) in self.iter() {
+      let candidate = Reverse((score(key, v), Reverse(key), v));
+      if heap.len() < k {
+        heap.push(candidate);
+      } else if let Some(mut worst) = heap.peek_mut() {
+        if candidate < *worst {
+          *worst = candidate;
+        }
+      }
+    }
+    heap
+      .into_sorted_vec()
+      .into_iter()
+      .map(|Reverse((_, Reverse(key), v))| (key, v))
+      .collect()
+  }
+
+  // Counts entries by their distance in slots from their home slot; index i
+  // holds the number of entries i slots away.
+  pub fn probe_histogram(&self) -> Vec<usize> {
+    let mut histogram = Vec::new();
+    if self.is_inline() {
+      // Inline entries are not hashed, every one sits where it is looked for.
+      if self.size > 0 {
+        histogram.push(self.size as usize);
+      }
+      return histogram;
+    }
+    for index in (0..self.data_cap).step_by(2) {
+      if !self.is_occupied(index) {
+        continue;
+      }
+      let distance = (index.wrapping_sub(self.home(index)) & self.data_mask) >> 1;
+      if histogram.len() <= distance as usize {
+        histogram.resize(distance as usize + 1, 0);
+      }
+      histogram[distance as usize] += 1;
+    }
+    histogram
+  }
+
+  // Counts the entries at least threshold slots from their home slot, the
+  // tail of probe_histogram without building it. Inline entries are all at
+  // distance 0.
+  pub fn long_probe_count(&self, threshold: usize) -> usize {
+    if self.is_inline() {
+      return if threshold == 0 {
+        self.size as usize
+      } else {
+        0
+      };
+    }
+    (0..self.data_cap)
+      .step_by(2)
+      .filter(|index| self.occupancy_bit(*index))
+      .filter(|index| {
+        (index.wrapping_sub(self.home(*index)) & self.data_mask) >> 1 >= threshold as u64
+      })
+      .count()
+  }
+
+  // Panics unless the sizes, masks and occupancy agree with each other and
+  // every entry can be found by probing from its home slot.
+  #[cfg(test)]
+  pub(crate) fn check_invariants(&self) {
+    if self.is_inline() {
+      assert_eq!(self.data_cap, 2 * INLINE_CAP);
+      assert!(self.size <= INLINE_CAP);
+      assert!(self.values.is_empty() && self.occupancy.is_empty() && self.hashed.is_empty());
+      let live = &self.inline_keys[..self.size as usize];
+      for (i, key) in live.iter().enumerate() {
+        assert!(!live[..i].contains(key), "inline key {key} is stored twice");
+      }
+      return;
+    }
+    let slots = self.data_cap >> 1;
+    assert!(self.data_cap.is_power_of_two() && self.data_cap >= MIN_DATA_CAP);
+    assert_eq!(self.data_mask, self.data_cap - 1);
+    assert_eq!(self.cap_mask, slots - 1);
+    assert!(self.bucket_mask == u64::MAX || self.bucket_mask == !(BUCKET_SLOTS - 1));
+    if self.growth_policy == GrowthPolicy::Chunked {
+      assert!(self.cap >= cap_for(self.data_cap) && self.cap <= slots - slots / 16);
+    } else {
+      assert_eq!(self.cap, cap_for(self.data_cap));
+    }
+    assert_eq!(self.keys.len() as u64, slots);
+    assert_eq!(self.values.len() as u64, slots);
+    assert_eq!(self.occupancy.len() as u64, occupancy_words(self.data_cap));
+    assert!(self.hashed.is_empty() || self.hashed.len() == self.occupancy.len());
+    let occupied: u64 = self
+      .occupancy
+      .iter()
+      .map(|word| word.count_ones() as u64)
+      .sum();
+    assert_eq!(
+      occupied, self.size,
+      "size disagrees with the occupancy bitset"
+    );
+    assert!(self.size < slots, "no free slot is left to end probes");
+    for index in (0..self.data_cap).step_by(2) {
+      if !self.is_occupied(index) {
+        continue;
+      }
+      let mut walk = self.home(index);
+      while walk != index {
+        assert!(
+          self.is_occupied(walk),
+          "free slot {} between slot {} and its home",
+          walk >> 1,
+          index >> 1
+        );
+        walk = self.next_slot(walk);
+      }
+      let (key, hashed) = (self.key_at(index), self.is_hashed(index));
+      assert_eq!(
+        self.find_from(self.home(index), key, hashed),
+        (index, true),
+        "key {key} is stored twice"
+      );
+    }
+    if let Some(order) = &self.order {
+      assert_eq!(order.positions.len(), self.size);
+      order.positions.check_invariants();
+    }
+  }
+
+  // Rebuilds the table at its current size with every probe chain sorted by
+  // home slot. Inserts and deletes in arbitrary order can leave keys pushed
+  // far past later homed ones; sorting keeps the total probe length but
+  // shortens the longest probes. Meant for idle time, as it allocates and
+  // fills a second table.
+  pub fn compact(&mut self) {
+    if self.is_inline() {
+      return;
+    }
+    // Ordering homes from a free slot on keeps a chain wrapping around the
+    // end of the table in one piece.
+    let mut start = 0;
+    while self.is_occupied(start) {
+      start += 2;
+    }
+    let mut slots: Vec<u64> = (0..self.data_cap)
+      .step_by(2)
+      .filter(|index| self.is_occupied(*index))
+      .collect();
+    slots.sort_unstable_by_key(|index| self.home(*index).wrapping_sub(start) & self.data_mask);
+    let timer = RehashTimer::start(self.resize_hook.is_some());
+    let mut new = self.heap_like(self.data_cap);
+    for index in slots {
+      let (key, v) = (self.key_at(index), self.value_at(index));
+      if self.is_hashed(index) {
+        new.insert_hashed(key, v);
+      } else {
+        new.insert(key, v);
+      }
+    }
+    self.replace_rehashed(new, timer);
+  }
+
+  // Rebuilds the table with only the entries keep accepts, calling
+  // on_removed once with every entry dropped, for releasing what the values
+  // refer to. Pre-hashed entries are passed with their hash as the key, as
+  // iter reports them. A table on the heap is rebuilt at its current size.
+  pub fn retain_notify(
+    &mut self,
+    mut keep: impl FnMut(u64, u64) -> bool,
+    mut on_removed: impl FnMut(u64, u64),
+  ) {
+    if self.is_inline() {
+      let removed: Vec<(u64, u64)> = self.iter().filter(|&(key, v)| !keep(key, v)).collect();
+      for (key, v) in removed {
+        self.delete(key);
+        on_removed(key, v);
+      }
+      return;
+    }
+    let timer = RehashTimer::start(self.resize_hook.is_some());
+    let mut new = self.heap_like(self.data_cap);
+    for index in (0..self.data_cap).step_by(2) {
+      if !self.occupancy_bit(index) {
+        continue;
+      }
+      let (key, v, hashed) = (
+        self.key_at(index),
+        self.value_at(index),
+        self.is_hashed(index),
+      );
+      if keep(key, v) {
+        if hashed {
+          new.insert_hashed(key, v);
+        } else {
+          new.insert(key, v);
+        }
+      } else {
+        if let Some(order) = &mut self.order {
+          order.remove(key, hashed);
+        }
+        on_removed(key, v);
+      }
+    }
+    self.replace_rehashed(new, timer);
+  }
+
+  fn remove_at(&mut self, index: u64) -> u64 {
+    self.size -= 1;
+    let (key, hashed) = (self.key_at(index), self.is_hashed(index));
+    if let Some(order) = &mut self.order {
+      order.remove(key, hashed);
+    }
+    let v = self.value_at(index);
+    if self.is_inline() {
+      // Keep the inline entries packed by moving the last one into the gap.
+      let (slot, last) = ((index >> 1) as usize, self.size as usize);
+      self.inline_keys[slot] = self.inline_keys[last];
+      self.inline_values[slot] = self.inline_values[last];
+      #[cfg(feature = "zeroize")]
+      if self.zeroize {
+        self.inline_keys[last].zeroize();
+        self.inline_values[last].zeroize();
+      }
+    } else {
+      self.unshift(index);
+    }
+    v
+  }
+
+  fn move_entries_into(&self, new: &mut OpenIndexTable<H, A>) {
+    self.move_entries_into_filtered(new, |_, _| true);
+  }
+
+  // Like move_entries_into, but only moves entries for which keep(key,
+  // hashed) holds.
+  fn move_entries_into_filtered(
+    &self,
+    new: &mut OpenIndexTable<H, A>,
+    mut keep: impl FnMut(u64, bool) -> bool,
+  ) {
+    #[cfg(test)]
+    RESIZES.with(|resizes| resizes.set(resizes.get() + 1));
+    for index in (0..self.data_cap).step_by(2) {
+      if !self.is_occupied(index) {
+        continue;
+      }
+      let (key, v) = (self.key_at(index), self.value_at(index));
+      if !keep(key, self.is_hashed(index)) {
+        continue;
+      }
+      if self.is_hashed(index) {
+        new.insert_hashed(key, v);
+      } else {
+        new.insert(key, v);
+      }
+    }
+  }
+
+  fn expand(&mut self) {
+    if self.size <= self.cap {
+      return;
+    }
+    if !self.auto_resize && self.size + 1 < self.data_cap >> 1 {
+      return;
+    }
+    if self.growth_policy == GrowthPolicy::Chunked && !self.is_inline() {
+      let slots = self.data_cap >> 1;
+      let ceiling = slots - slots / 16;
+      if self.cap < ceiling {
+        self.cap = (self.cap + (slots / 64).max(1)).min(ceiling);
+        return;
+      }
+    }
+    self.resize();
+  }
+
+  // Empty heap table with data_cap data length, built from the given arrays,
+  // and with the hasher and settings of this one. At the same data length it
+  // keeps the fill limit too, which the Chunked policy may have raised.
+  fn with_slots(
+    &self,
+    data_cap: u64,
+    keys: SlotArray<A>,
+    values: SlotArray<A>,
+    occupancy: SlotArray<A>,
+  ) -> OpenIndexTable<H, A> {
+    OpenIndexTable {
+      keys,
+      values,
+      occupancy,
+      hashed: SlotArray::new_in(self.allocator().clone()),
+      inline_keys: [0; INLINE_CAP as usize],
+      inline_values: [0; INLINE_CAP as usize],
+      order: None,
+      resize_hook: None,
+      #[cfg(feature = "zeroize")]
+      zeroize: self.zeroize,
+      growth_factor: self.growth_factor,
+      growth_policy: self.growth_policy,
+      auto_resize: self.auto_resize,
+      bucket_mask: self.bucket_mask,
+      max_size: self.max_size,
+      alloc_hint: self.alloc_hint,
+      data_cap,
+      data_mask: data_cap - 1,
+      cap: if data_cap == self.data_cap {
+        self.cap
+      } else {
+        cap_for(data_cap)
+      },
+      cap_mask: (data_cap >> 1) - 1,
+      size: 0,
+      hasher: self.hasher.clone(),
+    }
+  }
+
+  fn heap_like(&self, data_cap: u64) -> OpenIndexTable<H, A> {
+    let slots = slot_len(data_cap).expect("capacity overflow");
+    self.with_slots(
+      data_cap,
+      SlotArray::zeroed(slots, self.alloc_hint, self.allocator().clone()),
+      SlotArray::zeroed(slots, self.alloc_hint, self.allocator().clone()),
+      SlotArray::zeroed(
+        occupancy_words(data_cap) as usize,
+        None,
+        self.allocator().clone(),
+      ),
+    )
+  }
+
+  // Moves every entry into a new heap table with data_cap data length.
+  fn rebuild(&mut self, data_cap: u64) {
+    let timer = RehashTimer::start(self.resize_hook.is_some());
+    let mut new = self.heap_like(data_cap);
+    self.move_entries_into(&mut new);
+    self.replace_rehashed(new, timer);
+  }
+
+  // Replaces the table with new, a rehashed copy, handing over the insertion
+  // order and the resize hook and then calling the hook.
+  fn replace_rehashed(&mut self, mut new: OpenIndexTable<H, A>, timer: RehashTimer) {
+    new.order = self.order.take();
+    new.resize_hook = self.resize_hook.take();
+    let old_capacity = self.cap;
+    *self = new;
+    if let Some(hook) = &mut self.resize_hook {
+      hook(ResizeEvent {
+        old_capacity,
+        new_capacity: self.cap,
+        len: self.size,
+        elapsed: timer.elapsed(),
+      });
+    }
+  }
+
+  // Grows the table so that additional more entries fit without a resize,
+  // to the size inserting them one by one would grow it to. Unlike insert,
+  // which aborts the process if growing fails to allocate, the error is
+  // returned and the table is left as it was.
+  // With a maximum capacity set, the table only grows as far as that.
+  pub fn try_reserve(&mut self, additional: u64) -> Result<(), TryReserveError> {
+    self.try_reserve_sized(additional, false)
+  }
+
+  // try_reserve, growing by the growth factor step by step, or with exact
+  // straight to the smallest table that fits.
+  fn try_reserve_sized(&mut self, additional: u64, exact: bool) -> Result<(), TryReserveError> {
+    let needed = self
+      .size
+      .saturating_add(additional)
+      .min(self.size_limit().unwrap_or(u64::MAX));
+    if needed <= self.cap {
+      return Ok(());
+    }
+    let mut data_cap = self.data_cap.max(MIN_DATA_CAP);
+    while cap_for(data_cap) < needed {
+      let grown = if exact {
+        data_cap.checked_mul(2)
+      } else {
+        self.grown_data_cap(data_cap)
+      };
+      match grown {
+        Some(grown) => data_cap = grown,
+        None => {
+          data_cap = u64::MAX;
+          break;
+        }
+      }
+    }
+    let data_cap = self.limit_data_cap(data_cap);
+    if data_cap == self.data_cap {
+      return Ok(());
+    }
+    self.try_resize(data_cap)
+  }
+
+  // Grows the table so that additional more entries fit without a resize,
+  // like try_reserve.
+  //
+  // Panics if the new capacity overflows or fails to allocate.
+  pub fn reserve(&mut self, additional: u64) {
+    self
+      .try_reserve(additional)
+      .expect("failed to reserve capacity");
+  }
+
+  // Grows the table to the smallest power of two slot count that holds
+  // additional more entries without a resize, whatever the growth factor.
+  // With a factor above 2.0, reserve may allocate more.
+  //
+  // Panics if the new capacity overflows or fails to allocate.
+  pub fn reserve_exact(&mut self, additional: u64) {
+    self
+      .try_reserve_sized(additional, true)
+      .expect("failed to reserve capacity");
+  }
+
+  // Inserts a batch of pairs, growing the table at most once up front
+  // instead of doubling repeatedly along the way. The batch does not have to
+  // be sorted for correctness; sorted input from another table only means
+  // no key repeats. If a key does repeat, the last pair wins.
+  pub fn insert_sorted(&mut self, pairs: &[(u64, u64)]) {
+    self.reserve(pairs.len() as u64);
+    for &(key, v) in pairs {
+      self.insert(key, v);
+    }
+  }
+
+  // Inserts like insert, but surfaces an allocation failure while growing
+  // instead of aborting, leaving the table in its pre-resize state. A miss
+  // fills the free slot the probe stopped at unless the table grew. A new key
+  // in a table at its maximum capacity fails with CapacityOverflow.
+  pub fn try_insert_grow(&mut self, key: u64, v: u64) -> Result<(), TryReserveError> {
+    let (mut index, found) = self.find(key);
+    if !found {
+      // try_reserve stops growing at the limit and still succeeds.
+      if self.size_limit().is_some_and(|limit| self.size >= limit) {
+        return Err(capacity_overflow());
+      }
+      let data_cap = self.data_cap;
+      self.try_reserve(1)?;
+      if self.data_cap != data_cap {
+        index = self.find(key).0;
+      }
+    }
+    self.insert_at(index, found, key, v, false);
+    Ok(())
+  }
+
+  fn try_resize(&mut self, data_cap: u64) -> Result<(), TryReserveError> {
+    let timer = RehashTimer::start(self.resize_hook.is_some());
+    // An oversized request fails in try_zeroed with CapacityOverflow.
+    let slots = slot_len(data_cap).unwrap_or(usize::MAX);
+    let alloc = self.allocator();
+    let keys = SlotArray::try_zeroed(slots, self.alloc_hint, alloc.clone())?;
+    let values = SlotArray::try_zeroed(slots, self.alloc_hint, alloc.clone())?;
+    let words = occupancy_words(data_cap) as usize;
+    let occupancy = SlotArray::try_zeroed(words, None, alloc.clone())?;
+    let mut new = self.with_slots(data_cap, keys, values, occupancy);
+    self.move_entries_into(&mut new);
+    self.replace_rehashed(new, timer);
+    Ok(())
+  }
+
+  // Rebuilds the table at the smallest size that holds the live entries
+  // within the fill limit, releasing the rest of the allocation. If the
+  // smaller allocation fails the table is kept as it is.
+  pub fn shrink_to_fit(&mut self) {
+    let data_cap = heap_capacity_for(self.size as usize) as u64;
+    if !self.is_inline() && data_cap < self.data_cap {
+      let _ = self.try_resize(data_cap);
+    }
+  }
+}
+
+// Probing only runs once the table is on the heap.
+impl<H: Hasher64, A: SlotAllocator> LinearProbe for OpenIndexTable<H, A> {
+  fn data_mask(&self) -> u64 {
+    self.data_mask
+  }
+
+  fn slot_occupied(&self, index: u64) -> bool {
+    self.occupancy_bit(index)
+  }
+
+  fn home_of(&self, index: u64) -> u64 {
+    self.home(index)
+  }
+
+  fn vacate(&mut self, index: u64) {
+    self.clear_occupied(index);
+    self.set_hashed(index, false);
+    #[cfg(feature = "zeroize")]
+    if self.zeroize {
+      let slot = (index >> 1) as usize;
+      self.keys[slot].zeroize();
+      self.values[slot].zeroize();
+    }
+  }
+
+  fn move_slot(&mut self, from: u64, to: u64) {
+    let (from_slot, to_slot) = ((from >> 1) as usize, (to >> 1) as usize);
+    self.keys[to_slot] = self.keys[from_slot];
+    self.values[to_slot] = self.values[from_slot];
+    self.set_hashed(to, self.is_hashed(from));
+  }
+}
+
+// Log of keys in insertion order. Deleted keys stay in the log until it is
+// compacted; positions maps each live key to its log entry, which tells live
+// entries from stale ones.
+struct InsertionOrder<H: Hasher64> {
+  log: Vec<(u64, bool)>,
+  positions: OpenIndexTable<H>,
+}
+
+impl<H: Hasher64> InsertionOrder<H> {
+  fn new(hasher: H) -> InsertionOrder<H> {
+    InsertionOrder {
+      log: Vec::new(),
+      positions: OpenIndexTable::with_hasher(hasher),
+    }
+  }
+
+  fn push(&mut self, key: u64, hashed: bool) {
+    let position = self.log.len() as u64;
+    self.log.push((key, hashed));
+    if hashed {
+      self.positions.insert_hashed(key, position);
+    } else {
+      self.positions.insert(key, position);
+    }
+  }
+
+  fn position(&self, key: u64, hashed: bool) -> Option<u64> {
+    if hashed {
+      self.positions.get_hashed(key)
+    } else {
+      self.positions.get(key)
+    }
+  }
+
+  fn remove(&mut self, key: u64, hashed: bool) {
+    if hashed {
+      self.positions.delete_hashed(key);
+    } else {
+      self.positions.delete(key);
+    }
+    if self.log.len() as u64 > 2 * self.positions.size + 32 {
+      self.compact();
+    }
+  }
+
+  fn compact(&mut self) {
+    let log = core::mem::take(&mut self.log);
+    for (position, (key, hashed)) in log.into_iter().enumerate() {
+      if self.position(key, hashed) == Some(position as u64) {
+        self.push(key, hashed);
+      }
+    }
+  }
+
+  fn memory_bytes(&self) -> usize {
+    self.log.capacity() * core::mem::size_of::<(u64, bool)>() + self.positions.memory_bytes()
+  }
+}
+
+impl<H: Hasher64> Clone for InsertionOrder<H> {
+  fn clone(&self) -> Self {
+    InsertionOrder {
+      log: self.log.clone(),
+      positions: self.positions.clone(),
+    }
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  // Walks the entries in the order their keys were first inserted. A key that
+  // is deleted and inserted again moves to the end. Entries stored through
+  // the pre-hashed API yield their hash as key.
+  //
+  // Panics unless the table was set up with with_insertion_order.
+  pub fn iter_ordered(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+    self
+      .ordered_slots()
+      .map(|index| (self.key_at(index), self.value_at(index)))
+  }
+
+  // Slots of the live entries in insertion order.
+  fn ordered_slots(&self) -> impl Iterator<Item = u64> + '_ {
+    let order = self
+      .order
+      .as_ref()
+      .expect("table was not created with_insertion_order");
+    order
+      .log
+      .iter()
+      .enumerate()
+      .filter(|(position, (key, hashed))| order.position(*key, *hashed) == Some(*position as u64))
+      .map(|(_, (key, hashed))| {
+        let home = if *hashed {
+          self.hashed_index(*key)
+        } else {
+          self.index(*key)
+        };
+        self.find_from(home, *key, *hashed).0
+      })
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  // Consumes the table, moving every entry for which pred returns true into
+  // the first table and the rest into the second. Both are sized for half
+  // the entries and keep the growth factor and insertion order mode.
+  pub fn partition(
+    self,
+    pred: impl FnMut(u64, u64) -> bool,
+  ) -> (OpenIndexTable<H, A>, OpenIndexTable<H, A>) {
+    let half = (self.size / 2) as usize;
+    self.partition_sized([half, half], pred)
+  }
+
+  // Like partition, with the two tables sized for lens entries.
+  fn partition_sized(
+    self,
+    lens: [usize; 2],
+    mut pred: impl FnMut(u64, u64) -> bool,
+  ) -> (OpenIndexTable<H, A>, OpenIndexTable<H, A>) {
+    let empty = |len: usize| {
+      if len as u64 <= INLINE_CAP {
+        let mut empty =
+          OpenIndexTable::with_hasher_in(self.hasher.clone(), self.allocator().clone());
+        empty.growth_factor = self.growth_factor;
+        empty.growth_policy = self.growth_policy;
+        empty.auto_resize = self.auto_resize;
+        empty.bucket_mask = self.bucket_mask;
+        empty.max_size = self.max_size;
+        empty.alloc_hint = self.alloc_hint;
+        #[cfg(feature = "zeroize")]
+        {
+          empty.zeroize = self.zeroize;
+        }
+        empty
+      } else {
+        self.heap_like(capacity_for(len) as u64)
+      }
+    };
+    let mut halves = lens.map(empty);
+    for half in &mut halves {
+      if self.order.is_some() {
+        half.order = Some(Box::new(InsertionOrder::new(self.hasher.clone())));
+      }
+    }
+    let mut place = |index: u64| {
+      let (key, v) = (self.key_at(index), self.value_at(index));
+      let half = &mut halves[!pred(key, v) as usize];
+      if self.is_hashed(index) {
+        half.insert_hashed(key, v);
+      } else {
+        half.insert(key, v);
+      }
+    };
+    if self.order.is_some() {
+      self.ordered_slots().for_each(&mut place);
+    } else {
+      (0..self.data_cap)
+        .step_by(2)
+        .filter(|index| self.is_occupied(*index))
+        .for_each(&mut place);
+    }
+    let [matching, rest] = halves;
+    (matching, rest)
+  }
+
+  // Moves the entries with keys of at least pivot into a new table sized for
+  // them, keeping the rest, key 0 included unless pivot is 0. The new table
+  // has the same settings; the resize hook stays with this one. Pre-hashed
+  // entries split by their hash.
+  pub fn split_off(&mut self, pivot: u64) -> OpenIndexTable<H, A> {
+    let moving = self.iter().filter(|(key, _)| *key >= pivot).count();
+    let kept = self.size as usize - moving;
+    let hook = self.resize_hook.take();
+    let empty = OpenIndexTable::with_hasher_in(self.hasher.clone(), self.allocator().clone());
+    let table = core::mem::replace(self, empty);
+    let (split, mut rest) = table.partition_sized([moving, kept], |key, _| key >= pivot);
+    rest.resize_hook = hook;
+    *self = rest;
+    split
+  }
+
+  // Splits on bit of the key: keys with the bit set go to the first table.
+  // Splitting repeatedly on successive bits shards a table into power of two
+  // parts.
+  pub fn split_by_key_bit(self, bit: u32) -> (OpenIndexTable<H, A>, OpenIndexTable<H, A>) {
+    self.partition(|key, _| key & (1 << bit) != 0)
+  }
+
+  // Overwrites dest with the entries and settings of this table. dest's slot
+  // arrays are reused when they have at least as many slots as this table
+  // and the same alloc hint; a smaller or inline dest becomes a fresh clone.
+  // dest keeps its resize hook.
+  pub fn clone_into(&self, dest: &mut OpenIndexTable<H, A>) {
+    let hook = dest.resize_hook.take();
+    if dest.is_inline() || dest.alloc_hint != self.alloc_hint || dest.data_cap < self.data_cap {
+      *dest = self.clone();
+      dest.resize_hook = hook;
+      return;
+    }
+    let data_cap = dest.data_cap;
+    let alloc = dest.allocator().clone();
+    let keys = core::mem::replace(&mut dest.keys, SlotArray::new_in(alloc.clone()));
+    let values = core::mem::replace(&mut dest.values, SlotArray::new_in(alloc.clone()));
+    let mut occupancy = core::mem::replace(&mut dest.occupancy, SlotArray::new_in(alloc.clone()));
+    let mut hashed = core::mem::replace(&mut dest.hashed, SlotArray::new_in(alloc.clone()));
+    let same_layout = !self.is_inline() && data_cap == self.data_cap;
+    if !same_layout {
+      occupancy.fill(0);
+      hashed.fill(0);
+    }
+    let mut new = self.with_slots(data_cap, keys, values, occupancy);
+    new.hashed = hashed;
+    #[cfg(feature = "zeroize")]
+    if dest.zeroize {
+      new.keys.zeroize();
+      new.values.zeroize();
+    }
+    if same_layout {
+      // Slot for slot, without rehashing.
+      new.keys.copy_from_slice(&self.keys);
+      new.values.copy_from_slice(&self.values);
+      new.occupancy.copy_from_slice(&self.occupancy);
+      if self.hashed.is_empty() {
+        new.hashed.fill(0);
+      } else if new.hashed.is_empty() {
+        new.hashed = SlotArray::from_slice(&self.hashed, None, alloc);
+      } else {
+        new.hashed.copy_from_slice(&self.hashed);
+      }
+      new.size = self.size;
+      new.cap = self.cap;
+    } else {
+      self.move_entries_into(&mut new);
+    }
+    new.order = self.order.clone();
+    new.resize_hook = hook;
+    *dest = new;
+  }
+}
+
+impl<H: Hasher64 + Default, A: SlotAllocator + Default> Default for OpenIndexTable<H, A> {
+  fn default() -> Self {
+    Self::with_hasher_in(H::default(), A::default())
+  }
+}
+
+// The clone has the same capacity, slot layout and settings, with its arrays
+// allocated from a clone of the allocator.
+impl<H: Hasher64, A: SlotAllocator> Clone for OpenIndexTable<H, A> {
+  fn clone(&self) -> Self {
+    let alloc = self.allocator();
+    OpenIndexTable {
+      keys: SlotArray::from_slice(&self.keys, self.alloc_hint, alloc.clone()),
+      values: SlotArray::from_slice(&self.values, self.alloc_hint, alloc.clone()),
+      occupancy: SlotArray::from_slice(&self.occupancy, None, alloc.clone()),
+      hashed: SlotArray::from_slice(&self.hashed, None, alloc.clone()),
+      inline_keys: self.inline_keys,
+      inline_values: self.inline_values,
+      order: self.order.clone(),
+      resize_hook: None,
+      #[cfg(feature = "zeroize")]
+      zeroize: self.zeroize,
+      growth_factor: self.growth_factor,
+      growth_policy: self.growth_policy,
+      auto_resize: self.auto_resize,
+      bucket_mask: self.bucket_mask,
+      max_size: self.max_size,
+      alloc_hint: self.alloc_hint,
+      data_cap: self.data_cap,
+      data_mask: self.data_mask,
+      cap: self.cap,
+      cap_mask: self.cap_mask,
+      size: self.size,
+      hasher: self.hasher.clone(),
+    }
+  }
+}
+
+#[cfg(feature = "zeroize")]
+impl<H: Hasher64, A: SlotAllocator> Drop for OpenIndexTable<H, A> {
+  fn drop(&mut self) {
+    if self.zeroize {
+      self.keys.zeroize();
+      self.values.zeroize();
+      self.occupancy.zeroize();
+      self.hashed.zeroize();
+      self.inline_keys.zeroize();
+      self.inline_values.zeroize();
+    }
+  }
+}
+
+// Tables are equal when they hold the same entries, whatever their capacity,
+// slot layout or insertion order. A pre-hashed entry only equals a pre-hashed
+// entry with the same hash.
+impl<H: Hasher64, A: SlotAllocator> PartialEq for OpenIndexTable<H, A> {
+  fn eq(&self, other: &Self) -> bool {
+    if self.size != other.size {
+      return false;
+    }
+    // Equal sizes mean a key missing from other leaves one of its keys
+    // unmatched too, so checking one direction is enough.
+    (0..self.data_cap).step_by(2).all(|index| {
+      if !self.is_occupied(index) {
+        return true;
+      }
+      let (key, hashed) = (self.key_at(index), self.is_hashed(index));
+      let home = if hashed {
+        other.hashed_index(key)
+      } else {
+        other.index(key)
+      };
+      match other.find_from(home, key, hashed) {
+        (found, true) => other.value_at(found) == self.value_at(index),
+        _ => false,
+      }
+    })
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> Eq for OpenIndexTable<H, A> {}
+
+// Walks the table while allowing the current entry to be removed or updated.
+// The walk starts just after a free slot so no probe chain wraps past its
+// end; removing an entry only shifts not yet visited entries backwards into
+// the current slot, which is therefore examined again.
+pub struct Cursor<'a, H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  table: &'a mut OpenIndexTable<H, A>,
+  start: u64,
+  position: u64,
+  current: Option<u64>,
+}
+
+// Where a walk that takes out the entries it visits has got to, kept
+// across calls to take_next. Deletes in between are fine: they only shift
+// entries backwards within chains that lie ahead of the walk.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Walk {
+  start: u64,
+  position: u64,
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  pub fn cursor(&mut self) -> Cursor<'_, H, A> {
+    Cursor {
+      start: self.walk_start(),
+      table: self,
+      position: 0,
+      current: None,
+    }
+  }
+
+  // Index the walks of cursor and take_next start just after.
+  fn walk_start(&self) -> u64 {
+    // Removing an inline entry moves the last one into its slot, so the walk
+    // has to go front to back, starting after the final slot.
+    if self.is_inline() {
+      return self.data_cap - 2;
+    }
+    let mut start = 0;
+    while self.is_occupied(start) {
+      start += 2;
+    }
+    start
+  }
+
+  pub(crate) fn walk(&self) -> Walk {
+    Walk {
+      start: self.walk_start(),
+      position: 0,
+    }
+  }
+
+  // Removes the next entry of walk and returns its key, value and whether it
+  // is pre-hashed, or None once the table is empty. The table must not be
+  // rehashed or take new keys while the walk is in progress.
+  pub(crate) fn take_next(&mut self, walk: &mut Walk) -> Option<(u64, u64, bool)> {
+    let slots = self.data_cap >> 1;
+    while walk.position < slots {
+      let index = (walk.start + 2 + (walk.position << 1)) & self.data_mask;
+      if self.is_occupied(index) {
+        // Not advancing: the removal may shift the next entry into this slot.
+        let (key, hashed) = (self.key_at(index), self.is_hashed(index));
+        return Some((key, self.remove_at(index), hashed));
+      }
+      walk.position += 1;
+    }
+    None
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> Cursor<'_, H, A> {
+  #[allow(clippy::should_implement_trait)]
+  pub fn next(&mut self) -> Option<(u64, u64)> {
+    let slots = self.table.data_cap >> 1;
+    while self.position < slots {
+      let index = (self.start + 2 + (self.position << 1)) & self.table.data_mask;
+      self.position += 1;
+      if self.table.is_occupied(index) {
+        self.current = Some(index);
+        return Some((self.table.key_at(index), self.table.value_at(index)));
+      }
+    }
+    self.current = None;
+    None
+  }
+
+  // Removes the entry last returned by next, returning it.
+  pub fn remove_current(&mut self) -> Option<(u64, u64)> {
+    let index = self.current.take()?;
+    let key = self.table.key_at(index);
+    let v = self.table.remove_at(index);
+    self.position -= 1;
+    Some((key, v))
+  }
+
+  // Replaces the value of the entry last returned by next.
+  pub fn set_value(&mut self, v: u64) -> bool {
+    match self.current {
+      None => false,
+      Some(index) => {
+        *self.table.value_mut(index) = v;
+        true
+      }
+    }
+  }
+}
+
+// Removes and yields the entries pred accepts, walking the table like
+// Cursor. Entries not reached before the iterator is dropped stay in place.
+pub struct ExtractIf<'a, F, H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  cursor: Cursor<'a, H, A>,
+  pred: F,
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  // Removes every entry pred(key, value) returns true for, yielding it, in
+  // one pass. The table never rehashes during the walk.
+  pub fn extract_if<F: FnMut(u64, u64) -> bool>(&mut self, pred: F) -> ExtractIf<'_, F, H, A> {
+    ExtractIf {
+      cursor: self.cursor(),
+      pred,
+    }
+  }
+}
+
+impl<F: FnMut(u64, u64) -> bool, H: Hasher64, A: SlotAllocator> Iterator
+  for ExtractIf<'_, F, H, A>
+{
+  type Item = (u64, u64);
+
+  fn next(&mut self) -> Option<(u64, u64)> {
+    while let Some((key, v)) = self.cursor.next() {
+      if (self.pred)(key, v) {
+        return self.cursor.remove_current();
+      }
+    }
+    None
+  }
+}
+
+// Walks the entries in slot order.
+pub struct Iter<'a, H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  table: &'a OpenIndexTable<H, A>,
+  index: u64,
+}
+
+impl<H: Hasher64, A: SlotAllocator> Iterator for Iter<'_, H, A> {
+  type Item = (u64, u64);
+
+  fn next(&mut self) -> Option<(u64, u64)> {
+    while self.index < self.table.data_cap {
+      let index = self.index;
+      self.index += 2;
+      if self.table.is_occupied(index) {
+        return Some((self.table.key_at(index), self.table.value_at(index)));
+      }
+    }
+    None
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  pub fn iter(&self) -> Iter<'_, H, A> {
+    Iter {
+      table: self,
+      index: 0,
+    }
+  }
+
+  // Calls f with every entry in slot order, like iter but faster for full
+  // scans: free slots are skipped a bitset word at a time and the arrays are
+  // indexed directly.
+  pub fn for_each(&self, mut f: impl FnMut(u64, u64)) {
+    if self.is_inline() {
+      let live = self.size as usize;
+      for (key, v) in self.inline_keys[..live]
+        .iter()
+        .zip(&self.inline_values[..live])
+      {
+        f(*key, *v);
+      }
+      return;
+    }
+    for (word_index, word) in self.occupancy.iter().enumerate() {
+      let mut bits = *word;
+      while bits != 0 {
+        let slot = word_index * 64 + bits.trailing_zeros() as usize;
+        f(self.keys[slot], self.values[slot]);
+        bits &= bits - 1;
+      }
+    }
+  }
+
+  // Folds f over every value, walking the slots the way for_each does, for
+  // aggregates such as sums or maxima of values used as gauges.
+  pub fn fold_values<B>(&self, init: B, mut f: impl FnMut(B, u64) -> B) -> B {
+    if self.is_inline() {
+      return self.inline_values[..self.size as usize]
+        .iter()
+        .fold(init, |acc, v| f(acc, *v));
+    }
+    let mut acc = init;
+    for (word_index, word) in self.occupancy.iter().enumerate() {
+      let mut bits = *word;
+      while bits != 0 {
+        acc = f(
+          acc,
+          self.values[word_index * 64 + bits.trailing_zeros() as usize],
+        );
+        bits &= bits - 1;
+      }
+    }
+    acc
+  }
+
+  // Appends the entries of the home slots from cursor on to out, going
+  // through the home slots in reverse binary order like Redis SCAN, until at
+  // least count entries were added. Returns the cursor to go on from, or 0
+  // once every home slot was visited; a walk starts at 0. Deletes and inserts
+  // between calls only move entries within the chain of their home slot, so
+  // entries present for the whole walk come out exactly once. Growing keeps
+  // the visited home slots ahead of the cursor's order, so entries may come
+  // out twice but are never missed. Inline tables come out whole.
+  #[cfg(any(feature = "tokio", test))]
+  pub(crate) fn scan(&self, mut cursor: u64, count: usize, out: &mut Vec<(u64, u64)>) -> u64 {
+    if self.is_inline() {
+      out.extend(self.iter());
+      return 0;
+    }
+    let start = out.len();
+    loop {
+      let home = (cursor & self.cap_mask) << 1;
+      let mut index = home;
+      while self.is_occupied(index) {
+        if self.home(index) == home {
+          out.push((self.key_at(index), self.value_at(index)));
+        }
+        index = self.next_slot(index);
+      }
+      // Increments the reversed cursor, carrying across the mask's bits only.
+      cursor = (cursor | !self.cap_mask)
+        .reverse_bits()
+        .wrapping_add(1)
+        .reverse_bits();
+      if cursor == 0 || out.len() - start >= count {
+        return cursor;
+      }
+    }
+  }
+
+  // Walks the entries in slot order along with the slot each sits in, for
+  // structures kept alongside the table. Slots only stay valid until the
+  // table changes: a delete shifts entries back into freed slots, and growing
+  // or any other rehash moves every entry.
+  pub fn iter_with_slots(&self) -> impl Iterator<Item = (usize, u64, u64)> + '_ {
+    (0..self.data_cap)
+      .step_by(2)
+      .filter(|index| self.is_occupied(*index))
+      .map(|index| {
+        (
+          (index >> 1) as usize,
+          self.key_at(index),
+          self.value_at(index),
+        )
+      })
+  }
+}
+
+// Consumes the table, walking its slot arrays in place. An inline table's
+// entries are copied into arrays of their own first.
+struct IntoSlots<A: SlotAllocator> {
+  occupancy: SlotArray<A>,
+  keys: SlotArray<A>,
+  values: SlotArray<A>,
+  slot: usize,
+  #[cfg(feature = "zeroize")]
+  zeroize: bool,
+}
+
+#[cfg(feature = "zeroize")]
+impl<A: SlotAllocator> Drop for IntoSlots<A> {
+  fn drop(&mut self) {
+    if self.zeroize {
+      self.keys.zeroize();
+      self.values.zeroize();
+      self.occupancy.zeroize();
+    }
+  }
+}
+
+impl<A: SlotAllocator> Iterator for IntoSlots<A> {
+  type Item = (u64, u64);
+
+  fn next(&mut self) -> Option<(u64, u64)> {
+    while self.slot < self.keys.len() {
+      let slot = self.slot;
+      self.slot += 1;
+      if self.occupancy[slot >> 6] & (1 << (slot & 63)) != 0 {
+        return Some((self.keys[slot], self.values[slot]));
+      }
+    }
+    None
+  }
+}
+
+pub struct IntoKeys<A: SlotAllocator = Global> {
+  slots: IntoSlots<A>,
+}
+
+impl<A: SlotAllocator> Iterator for IntoKeys<A> {
+  type Item = u64;
+
+  fn next(&mut self) -> Option<u64> {
+    self.slots.next().map(|(key, _)| key)
+  }
+}
+
+pub struct IntoValues<A: SlotAllocator = Global> {
+  slots: IntoSlots<A>,
+}
+
+impl<A: SlotAllocator> Iterator for IntoValues<A> {
+  type Item = u64;
+
+  fn next(&mut self) -> Option<u64> {
+    self.slots.next().map(|(_, v)| v)
+  }
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  fn into_slots(mut self) -> IntoSlots<A> {
+    let alloc = self.allocator().clone();
+    if self.is_inline() {
+      let end = self.size as usize;
+      return IntoSlots {
+        occupancy: SlotArray::from_slice(&[u64::MAX], None, alloc.clone()),
+        keys: SlotArray::from_slice(&self.inline_keys[..end], None, alloc.clone()),
+        values: SlotArray::from_slice(&self.inline_values[..end], None, alloc),
+        slot: 0,
+        #[cfg(feature = "zeroize")]
+        zeroize: self.zeroize,
+      };
+    }
+    // Taken rather than moved out, since the table may scrub itself on drop.
+    let take =
+      |array: &mut SlotArray<A>| core::mem::replace(array, SlotArray::new_in(alloc.clone()));
+    IntoSlots {
+      occupancy: take(&mut self.occupancy),
+      keys: take(&mut self.keys),
+      values: take(&mut self.values),
+      slot: 0,
+      #[cfg(feature = "zeroize")]
+      zeroize: self.zeroize,
+    }
+  }
+
+  pub fn into_keys(self) -> IntoKeys<A> {
+    IntoKeys {
+      slots: self.into_slots(),
+    }
+  }
+
+  pub fn into_values(self) -> IntoValues<A> {
+    IntoValues {
+      slots: self.into_slots(),
+    }
+  }
+}
+
+// Sized up front for the map, so filling the table never grows it.
+#[cfg(feature = "std")]
+impl<S> From<std::collections::HashMap<u64, u64, S>> for OpenIndexTable {
+  fn from(map: std::collections::HashMap<u64, u64, S>) -> Self {
+    let mut table = OpenIndexTable::with_capacity(map.len());
+    for (key, v) in map {
+      table.insert(key, v);
+    }
+    table
+  }
+}
+
+// Moves the entries out of the table. Pre-hashed entries come out with
+// their hash as the key, as iter reports them.
+#[cfg(feature = "std")]
+impl<H: Hasher64, A: SlotAllocator> From<OpenIndexTable<H, A>>
+  for std::collections::HashMap<u64, u64>
+{
+  fn from(table: OpenIndexTable<H, A>) -> Self {
+    let mut map = std::collections::HashMap::with_capacity(table.len() as usize);
+    map.extend(table.into_slots());
+    map
+  }
+}
+
+// A view into a single slot of the table, found with one probe.
+pub enum Entry<'a, H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  Occupied(OccupiedEntry<'a, H, A>),
+  Vacant(VacantEntry<'a, H, A>),
+}
+
+pub struct OccupiedEntry<'a, H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  table: &'a mut OpenIndexTable<H, A>,
+  index: u64,
+}
+
+pub struct VacantEntry<'a, H: Hasher64 = Scramble, A: SlotAllocator = Global> {
+  table: &'a mut OpenIndexTable<H, A>,
+  key: u64,
+  index: u64,
+}
+
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  pub fn entry(&mut self, key: u64) -> Entry<'_, H, A> {
+    match self.find(key) {
+      (index, true) => Entry::Occupied(OccupiedEntry { table: self, index }),
+      (index, false) => Entry::Vacant(VacantEntry {
+        table: self,
+        key,
+        index,
+      }),
+    }
+  }
+}
+
+impl<'a, H: Hasher64, A: SlotAllocator> Entry<'a, H, A> {
+  pub fn key(&self) -> u64 {
+    match self {
+      Entry::Occupied(entry) => entry.key(),
+      Entry::Vacant(entry) => entry.key,
+    }
+  }
+
+  pub fn or_insert(self, v: u64) -> &'a mut u64 {
+    self.or_insert_with(|| v)
+  }
+
+  pub fn or_insert_with<F: FnOnce() -> u64>(self, f: F) -> &'a mut u64 {
+    match self {
+      Entry::Occupied(entry) => entry.into_mut(),
+      Entry::Vacant(entry) => entry.insert(f()),
+    }
+  }
+
+  pub fn or_default(self) -> &'a mut u64 {
+    self.or_insert(0)
+  }
+
+  pub fn and_modify<F: FnOnce(&mut u64)>(self, f: F) -> Entry<'a, H, A> {
+    match self {
+      Entry::Occupied(mut entry) => {
+        f(entry.get_mut());
+        Entry::Occupied(entry)
+      }
+      Entry::Vacant(entry) => Entry::Vacant(entry),
+    }
+  }
+}
+
+impl<'a, H: Hasher64, A: SlotAllocator> OccupiedEntry<'a, H, A> {
+  pub fn key(&self) -> u64 {
+    self.table.key_at(self.index)
+  }
+
+  pub fn get(&self) -> u64 {
+    self.table.value_at(self.index)
+  }
+
+  pub fn get_mut(&mut self) -> &mut u64 {
+    self.table.value_mut(self.index)
+  }
+
+  pub fn into_mut(self) -> &'a mut u64 {
+    self.table.value_mut(self.index)
+  }
+
+  pub fn remove(self) -> u64 {
+    self.table.remove_at(self.index)
+  }
+}
+
+impl<'a, H: Hasher64, A: SlotAllocator> VacantEntry<'a, H, A> {
+  pub fn key(&self) -> u64 {
+    self.key
+  }
+
+  // Stores v in the probed slot. If this grows the table the entry has moved,
+  // so it is looked up again before handing out the reference.
+  pub fn insert(self, v: u64) -> &'a mut u64 {
+    let table = self.table;
+    let mut index = table.occupy(self.index, self.key, false);
+    *table.value_mut(index) = v;
+    if table.size > table.cap {
+      table.expand();
+      index = table.find(self.key).0;
+    }
+    table.value_mut(index)
+  }
+}
+
+#[cfg(feature = "raw")]
+impl<H: Hasher64, A: SlotAllocator> OpenIndexTable<H, A> {
+  // Number of slots, INLINE_CAP while the table is inline.
+  pub fn slot_count(&self) -> usize {
+    (self.data_cap >> 1) as usize
+  }
+
+  // Slot holding key, stored through the plain API.
+  pub fn slot_index_of(&self, key: u64) -> Option<usize> {
+    match self.find(key) {
+      (index, true) => Some((index >> 1) as usize),
+      _ => None,
+    }
+  }
+
+  // The slots as laid out in memory, see the raw module for the contract.
+  pub fn raw_slots(&self) -> RawSlots<'_> {
+    if self.is_inline() {
+      return RawSlots {
+        keys: &self.inline_keys,
+        values: &self.inline_values,
+        occupancy: Occupancy::Prefix(self.size as usize),
+        hashed: &[],
+      };
+    }
+    RawSlots {
+      keys: &self.keys,
+      values: &self.values,
+      occupancy: Occupancy::Bits(&self.occupancy),
+      hashed: &self.hashed,
+    }
+  }
+
+  // Like raw_slots, with the keys and values writable.
+  //
+  // Safety: values may be rewritten freely. Keys of occupied slots may only
+  // be rewritten so that the layout contract of the raw module still holds:
+  // each key sits between its home slot and the next free slot and appears
+  // once among the plain, and once among the pre-hashed, entries. Lookups
+  // may rely on this without checking it.
+  #[allow(clippy::missing_safety_doc)]
+  pub unsafe fn raw_slots_mut(&mut self) -> RawSlotsMut<'_> {
+    if self.is_inline() {
+      return RawSlotsMut {
+        keys: &mut self.inline_keys,
+        values: &mut self.inline_values,
+        occupancy: Occupancy::Prefix(self.size as usize),
+        hashed: &[],
+      };
+    }
+    RawSlotsMut {
+      keys: &mut self.keys,
+      values: &mut self.values,
+      occupancy: Occupancy::Bits(&self.occupancy),
+      hashed: &self.hashed,
+    }
+  }
+}
+
+#[cfg(feature = "rayon")]
+impl<H: Hasher64 + Sync, A: SlotAllocator + Sync> OpenIndexTable<H, A> {
+  // Visits every entry in parallel, splitting the slots into contiguous
+  // ranges.
+  pub fn par_iter(&self) -> impl ParallelIterator<Item = (u64, u64)> + '_ {
+    (0..(self.data_cap >> 1) as usize)
+      .into_par_iter()
+      .with_min_len(1 << 12)
+      .map(|slot| (slot as u64) << 1)
+      .filter(|index| self.is_occupied(*index))
+      .map(|index| (self.key_at(index), self.value_at(index)))
+  }
+
+  pub fn par_values(&self) -> impl ParallelIterator<Item = u64> + '_ {
+    self.par_iter().map(|(_, v)| v)
+  }
+}
+
+// Fills one contiguous range of slots, starting at slot base, with pairs
+// homed in it, ordered by home. Returns how many entries it added and the
+// pairs whose probe ran past the end of the range.
+#[cfg(feature = "rayon")]
+fn fill_slot_range(
+  keys: &mut [u64],
+  values: &mut [u64],
+  occupancy: &mut [u64],
+  base: u64,
+  pairs: &[(u64, u64, u64)],
+) -> (u64, Vec<(u64, u64)>) {
+  let mut added = 0;
+  let mut spilled = Vec::new();
+  for &(home, key, v) in pairs {
+    let mut slot = (home - base) as usize;
+    loop {
+      if slot == keys.len() {
+        spilled.push((key, v));
+        break;
+      }
+      if occupancy[slot >> 6] & (1 << (slot & 63)) == 0 {
+        keys[slot] = key;
+        values[slot] = v;
+        occupancy[slot >> 6] |= 1 << (slot & 63);
+        added += 1;
+        break;
+      }
+      if keys[slot] == key {
+        values[slot] = v;
+        break;
+      }
+      slot += 1;
+    }
+  }
+  (added, spilled)
+}
+
+// Builds a table from pairs on the rayon pool; if a key repeats, the last
+// pair wins, as with insert_sorted. The table is sized for all pairs up
+// front and the pairs are sorted by home slot under its hasher, so the slots
+// split into ranges that threads fill independently, each from the pairs
+// homed in its range. Pairs whose probe runs past the end of their range
+// are inserted afterwards the usual way: the entries placed in parallel
+// leave no free slot inside any chain, so they land where a sequential
+// insert would have probed to.
+#[cfg(feature = "rayon")]
+impl<H: Hasher64 + Default + Sync> FromParallelIterator<(u64, u64)> for OpenIndexTable<H> {
+  fn from_par_iter<I: IntoParallelIterator<Item = (u64, u64)>>(pairs: I) -> Self {
+    let pairs: Vec<(u64, u64)> = pairs.into_par_iter().collect();
+    let mut table = OpenIndexTable::with_capacity_and_hasher(pairs.len(), H::default());
+    if table.is_inline() {
+      for (key, v) in pairs {
+        table.insert(key, v);
+      }
+      return table;
+    }
+    let (hasher, home_mask) = (&table.hasher, table.cap_mask & table.bucket_mask);
+    let mut homed: Vec<(u64, u64, u64)> = pairs
+      .into_par_iter()
+      .map(|(key, v)| (hasher.hash(key) & home_mask, key, v))
+      .collect();
+    // Stable, so repeats of a key keep their order.
+    homed.par_sort_by_key(|&(home, _, _)| home);
+    // Whole occupancy words per range, several ranges per thread.
+    let slots = table.keys.len();
+    let range = slots
+      .div_ceil(rayon::current_num_threads() * 8)
+      .next_multiple_of(64);
+    let mut start = 0;
+    let parts: Vec<&[(u64, u64, u64)]> = (0..slots.div_ceil(range))
+      .map(|part| {
+        let end = start
+          + homed[start..].partition_point(|&(home, _, _)| home < ((part + 1) * range) as u64);
+        let pairs = &homed[start..end];
+        start = end;
+        pairs
+      })
+      .collect();
+    let filled: Vec<(u64, Vec<(u64, u64)>)> = table
+      .keys
+      .par_chunks_mut(range)
+      .zip(table.values.par_chunks_mut(range))
+      .zip(table.occupancy.par_chunks_mut(range / 64))
+      .zip(parts)
+      .enumerate()
+      .map(|(part, (((keys, values), occupancy), pairs))| {
+        fill_slot_range(keys, values, occupancy, (part * range) as u64, pairs)
+      })
+      .collect();
+    table.size = filled.iter().map(|(added, _)| added).sum();
+    for (_, spilled) in filled {
+      for (key, v) in spilled {
+        table.insert(key, v);
+      }
+    }
+    table
+  }
+}
+
+#[cfg(all(test, feature = "allocator_api"))]
+use alloc::alloc::{AllocError, Allocator};
+#[cfg(all(test, feature = "allocator_api"))]
+use core::{alloc::Layout, ptr::NonNull};
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use test::Bencher;
+
+#[cfg(test)]
+std::thread_local! {
+  // Number of keys hashed by index on this thread.
+  static HASH_CALLS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+  // Number of times a table's entries were rehashed into a new table on this
+  // thread.
+  static RESIZES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+fn count_resizes(f: impl FnOnce()) -> usize {
+  let before = RESIZES.with(|resizes| resizes.get());
+  f();
+  RESIZES.with(|resizes| resizes.get()) - before
+}
+
+#[cfg(test)]
+fn count_hash_calls(f: impl FnOnce()) -> usize {
+  let before = HASH_CALLS.with(|calls| calls.get());
+  f();
+  HASH_CALLS.with(|calls| calls.get()) - before
+}
+
+#[cfg(test)]
+index_table_tests!(OpenIndexTable::new(), |k: u64| k);
+
+// The shared tests again on the heap, where small keys probe and shift too.
+#[cfg(test)]
+mod heap_table_tests {
+  use super::heap_table;
+
+  index_table_tests!(heap_table(), |k: u64| k);
+}
+
+// A table that skips the inline stage, for tests about the slot layout.
+#[cfg(test)]
+fn heap_table() -> OpenIndexTable {
+  OpenIndexTable::with_data_cap(MIN_DATA_CAP, Scramble, Global)
+}
+
+#[cfg(test)]
+fn keys_with_index(table: &OpenIndexTable, index: u64, n: usize) -> Vec<u64> {
+  (1..).filter(|k| table.index(*k) == index).take(n).collect()
+}
+
+// Regression test: the backward shift once freed the slot at the deleted
+// key's value instead of its position, corrupting unrelated slots.
+#[test]
+fn test_delete_in_collision_chains() {
+  let template = heap_table();
+  let middle = 20;
+  let chains = [
+    // One chain in the middle of the array, followed by a chain homed on
+    // its tail.
+    (
+      keys_with_index(&template, middle, 5),
+      keys_with_index(&template, middle + 8, 2),
+    ),
+    // One chain homed in the last slot wrapping into a chain homed in slot 0.
+    (
+      keys_with_index(&template, template.data_cap - 2, 4),
+      keys_with_index(&template, 0, 3),
+    ),
+  ];
+  for (chain, next) in chains {
+    let keys: Vec<u64> = chain.iter().chain(&next).copied().collect();
+    // Head, middle and tail of the first chain, and every other entry.
+    for deleted in 0..keys.len() {
+      let mut table = heap_table();
+      for key in &keys {
+        table.insert(*key, !key);
+      }
+      assert_eq!(table.delete(keys[deleted]), Some(!keys[deleted]));
+      assert_eq!(table.get(keys[deleted]), None);
+      assert_eq!(table.len(), keys.len() as u64 - 1);
+      for (position, key) in keys.iter().enumerate() {
+        if position != deleted {
+          assert_eq!(table.get(*key), Some(!key));
+        }
+      }
+      // No entry was lost or duplicated by the shift.
+      assert_eq!(table.iter().count(), keys.len() - 1);
+      assert_eq!(
+        table.probe_histogram().iter().sum::<usize>(),
+        keys.len() - 1
+      );
+      // Deleting the rest one by one empties the table cleanly.
+      for (position, key) in keys.iter().enumerate().rev() {
+        if position != deleted {
+          assert_eq!(table.delete(*key), Some(!key));
+        }
+      }
+      assert!(table.is_empty());
+      assert_eq!(table.iter().count(), 0);
+    }
+  }
+}
+
+#[test]
+fn test_cursor_removal_with_shifts() {
+  let template = heap_table();
+  // One chain homed in the last slot wraps around into a chain homed in slot 0.
+  let mut keys = keys_with_index(&template, template.data_cap - 2, 4);
+  keys.extend(keys_with_index(&template, 0, 3));
+  for removed in 0u32..1 << keys.len() {
+    let mut table = heap_table();
+    for key in &keys {
+      table.insert(*key, key * 10);
+    }
+    let mut visited = Vec::new();
+    let mut cursor = table.cursor();
+    while let Some((key, v)) = cursor.next() {
+      assert_eq!(v, key * 10);
+      visited.push(key);
+      let position = keys.iter().position(|k| *k == key).unwrap();
+      if removed & (1 << position) != 0 {
+        assert_eq!(cursor.remove_current(), Some((key, v)));
+        assert_eq!(cursor.remove_current(), None);
+      }
+    }
+    visited.sort();
+    let mut expected = keys.clone();
+    expected.sort();
+    assert_eq!(visited, expected);
+    for (position, key) in keys.iter().enumerate() {
+      let expected = (removed & (1 << position) == 0).then_some(key * 10);
+      assert_eq!(table.get(*key), expected);
+    }
+  }
+}
+
+#[test]
+fn test_extract_if() {
+  for n in [6u64, 5000] {
+    let mut table = OpenIndexTable::new();
+    for key in 0..n {
+      table.insert(key, key * 7);
+    }
+    let mut extracted: Vec<(u64, u64)> = table.extract_if(|_, v| v % 2 == 1).collect();
+    extracted.sort();
+    assert!(extracted
+      .into_iter()
+      .eq((0..n).filter(|key| key % 2 == 1).map(|key| (key, key * 7))));
+    assert_eq!(table.len(), n.div_ceil(2));
+    // The survivors are still reachable after the backward shifts.
+    assert!((0..n)
+      .filter(|key| key % 2 == 0)
+      .all(|key| table.get(key) == Some(key * 7)));
+    table.check_invariants();
+  }
+  // Entries the iterator never reached stay in the table.
+  let mut table = heap_table();
+  for key in 0..20 {
+    table.insert(key, key);
+  }
+  assert_eq!(table.extract_if(|_, _| true).take(5).count(), 5);
+  assert_eq!(table.len(), 15);
+  table.check_invariants();
+}
+
+#[test]
+fn test_cursor_set_value_and_key_zero() {
+  let mut table = OpenIndexTable::new();
+  table.insert(0, 1);
+  table.insert(5, 2);
+  table.insert(6, 3);
+  let mut cursor = table.cursor();
+  assert!(!cursor.set_value(9));
+  while let Some((key, v)) = cursor.next() {
+    if key == 5 {
+      cursor.remove_current();
+    } else {
+      assert!(cursor.set_value(v + 100));
+    }
+  }
+  assert_eq!(cursor.next(), None);
+  assert!(!cursor.set_value(9));
+  assert_eq!(table.get(0), Some(101));
+  assert_eq!(table.get(5), None);
+  assert_eq!(table.get(6), Some(103));
+  let mut cursor = table.cursor();
+  while let Some((key, v)) = cursor.next() {
+    if key == 0 {
+      assert_eq!(cursor.remove_current(), Some((0, v)));
+    }
+  }
+  assert_eq!(table.get(0), None);
+  assert_eq!(table.get(6), Some(103));
+}
+
+#[test]
+fn test_inline_to_heap_transition() {
+  let mut table = OpenIndexTable::new();
+  assert_eq!(table.memory_bytes(), core::mem::size_of::<OpenIndexTable>());
+  let keys = [0, 3, 1 << 40, 7, 11, 13, 17, 19];
+  assert_eq!(
+    count_hash_calls(|| {
+      for key in keys {
+        table.insert(key, key + 1);
+      }
+      table.insert(7, 70);
+      assert_eq!(table.delete(3), Some(4));
+      table.insert(3, 30);
+      assert_eq!(table.get(7), Some(70));
+      assert_eq!(table.get(99), None);
+    }),
+    0
+  );
+  assert!(table.is_inline());
+  assert_eq!(table.memory_bytes(), core::mem::size_of::<OpenIndexTable>());
+  table.insert(23, 24);
+  assert!(!table.is_inline());
+  assert_eq!(table.len(), 9);
+  for key in keys {
+    let expected = match key {
+      3 => 30,
+      7 => 70,
+      _ => key + 1,
+    };
+    assert_eq!(table.get(key), Some(expected));
+  }
+  // Dropping back below the inline capacity keeps the table on the heap.
+  for key in keys {
+    table.delete(key);
+  }
+  assert!(!table.is_inline());
+  assert_eq!(table.len(), 1);
+  assert_eq!(table.get(23), Some(24));
+
+  let mut table = OpenIndexTable::new();
+  table.insert(1, 2);
+  table.insert_hashed(0x9E37_79B9_7F4A_7C15, 3);
+  assert!(!table.is_inline());
+  assert_eq!(table.get(1), Some(2));
+  assert_eq!(table.get_hashed(0x9E37_79B9_7F4A_7C15), Some(3));
+}
+
+#[test]
+fn test_inline_cursor_and_order() {
+  let mut table = OpenIndexTable::new().with_insertion_order();
+  for key in (0..8).rev() {
+    table.insert(key, key);
+  }
+  let mut visited = Vec::new();
+  let mut cursor = table.cursor();
+  while let Some((key, _)) = cursor.next() {
+    visited.push(key);
+    if key % 2 == 0 {
+      cursor.remove_current();
+    }
+  }
+  visited.sort();
+  assert_eq!(visited, (0..8).collect::<Vec<_>>());
+  assert!(table.is_inline());
+  assert_eq!(
+    table.iter_ordered().collect::<Vec<_>>(),
+    [(7, 7), (5, 5), (3, 3), (1, 1)]
+  );
+  let mut keys: Vec<u64> = table.into_keys().collect();
+  keys.sort();
+  assert_eq!(keys, [1, 3, 5, 7]);
+}
+
+#[test]
+fn test_memory_usage() {
+  let mut table = OpenIndexTable::new();
+  let struct_size = core::mem::size_of::<OpenIndexTable>();
+  assert_eq!(
+    table.memory_usage(),
+    (table.keys.capacity() + table.values.capacity() + table.occupancy.capacity()) * 8
+      + struct_size
+  );
+  assert_eq!(table.memory_usage_live(), 0);
+  let initial = table.memory_usage();
+  for i in 1..=100 {
+    table.insert(i, i);
+  }
+  assert_eq!(
+    table.memory_usage(),
+    (table.keys.capacity() + table.values.capacity() + table.occupancy.capacity()) * 8
+      + struct_size
+  );
+  assert!(table.memory_usage() > initial);
+  assert_eq!(table.memory_usage_live(), 100 * 16);
+  table.delete(1);
+  assert_eq!(table.memory_usage_live(), 99 * 16);
+}
+
+#[test]
+fn test_memory_bytes_expand_and_shrink() {
+  let mut table = OpenIndexTable::new();
+  let initial = table.memory_bytes();
+  // Shrinking keeps the table on the heap.
+  let smallest = heap_table().memory_bytes();
+  for i in 1..=10_000 {
+    table.insert(i, i);
+  }
+  let grown = table.memory_bytes();
+  assert!(grown > initial);
+  for i in 1..=10_000 {
+    if i > 10 {
+      table.delete(i);
+    }
+  }
+  assert_eq!(table.memory_bytes(), grown);
+  table.insert(0, 3);
+  table.shrink_to_fit();
+  assert_eq!(table.memory_bytes(), smallest);
+  assert_eq!(table.get(0), Some(3));
+  for i in 1..=10_000 {
+    let expected = (i <= 10).then_some(i);
+    assert_eq!(table.get(i), expected);
+  }
+  table.shrink_to_fit();
+  assert_eq!(table.memory_bytes(), smallest);
+}
+
+#[cfg(test)]
+fn grown_table_with_deletions() -> OpenIndexTable {
+  let mut table = OpenIndexTable::new();
+  for i in 1..=1000 {
+    table.insert(i, i * 2);
+  }
+  for i in (1..=1000).step_by(3) {
+    table.delete(i);
+  }
+  table
+}
+
+#[test]
+fn test_into_keys_and_values() {
+  let expected: Vec<u64> = (1..=1000).filter(|i| i % 3 != 1).collect();
+  let mut keys: Vec<u64> = grown_table_with_deletions().into_keys().collect();
+  keys.sort();
+  assert_eq!(keys, expected);
+  let mut values: Vec<u64> = grown_table_with_deletions().into_values().collect();
+  values.sort();
+  assert_eq!(values, expected.iter().map(|i| i * 2).collect::<Vec<_>>());
+
+  let mut table = grown_table_with_deletions();
+  table.insert(0, 5);
+  assert_eq!(table.into_keys().filter(|k| *k == 0).count(), 1);
+  let mut table = grown_table_with_deletions();
+  table.insert(0, 5);
+  let values: Vec<u64> = table.into_values().collect();
+  assert_eq!(values.len(), expected.len() + 1);
+  assert!(values.contains(&5));
+  let mut table = grown_table_with_deletions();
+  table.insert(0, 5);
+  table.delete(0);
+  assert_eq!(table.into_keys().count(), expected.len());
+}
+
+#[cfg(test)]
+fn top_k_reference(
+  table: &OpenIndexTable,
+  k: usize,
+  score: fn(u64, u64) -> u64,
+) -> Vec<(u64, u64)> {
+  let mut entries: Vec<(u64, u64)> = table.iter().collect();
+  entries.sort_by_key(|&(key, v)| (Reverse(score(key, v)), key));
+  entries.truncate(k);
+  entries
+}
+
+#[test]
+fn test_top_k_matches_sort() {
+  let mut state = 0x2545_F491_4F6C_DD1Du64;
+  let mut random = move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+  };
+  for round in 0..30 {
+    let mut table = OpenIndexTable::new();
+    let entries = random() % 2_000;
+    // Few distinct values so ties are common.
+    let values = 1 + round * 10;
+    for _ in 0..entries {
+      table.insert(random() % 4_096, random() % values);
+    }
+    let score: fn(u64, u64) -> u64 = |key, v| (key ^ v) % 100;
+    for k in [0, 1, 7, 100, entries as usize, entries as usize + 10] {
+      assert_eq!(table.top_k(k), top_k_reference(&table, k, |_, v| v));
+      assert_eq!(table.top_k_by(k, score), top_k_reference(&table, k, score));
+    }
+  }
+}
+
+#[test]
+fn test_probe_histogram() {
+  let mut table = heap_table();
+  assert!(table.probe_histogram().is_empty());
+  // Three keys share the last slot and wrap around into slot 0, where a
+  // fourth key is homed and pushed back by the wrapped one.
+  let mut keys = keys_with_index(&table, table.data_cap - 2, 3);
+  keys.extend(keys_with_index(&table, 0, 1));
+  for key in &keys {
+    table.insert(*key, 1);
+  }
+  assert_eq!(table.probe_histogram(), [1, 1, 2]);
+  let mut table = OpenIndexTable::new();
+  for i in 1..=10_000 {
+    table.insert(i, i);
+  }
+  let histogram = table.probe_histogram();
+  assert_eq!(histogram.iter().sum::<usize>(), 10_000);
+  assert!(histogram[0] > 0 && histogram.len() > 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_hash_map_round_trip() {
+  for n in [0, 5, 1000] {
+    let mut map: HashMap<u64, u64> = (1..n).map(|key| (key * 31, key)).collect();
+    map.insert(0, 77);
+    let mut table = OpenIndexTable::new();
+    // Sized once up front, not grown entry by entry.
+    assert!(count_resizes(|| table = OpenIndexTable::from(map.clone())) <= 1);
+    assert_eq!(table.len(), map.len() as u64);
+    assert_eq!(table.get(0), Some(77));
+    assert!(map.iter().all(|(key, v)| table.get(*key) == Some(*v)));
+    assert_eq!(HashMap::from(table), map);
+  }
+  let mut table = heap_table();
+  table.insert(0, 0);
+  table.delete(0);
+  table.insert(0, 5);
+  assert_eq!(HashMap::from(table), HashMap::from([(0, 5)]));
+}
+
+#[test]
+fn test_long_probe_count() {
+  let mut table = heap_table();
+  // Five keys homed at index 4 sit at distances 0 to 4, and a key homed at
+  // index 6 is pushed to distance 4 behind them.
+  for key in keys_with_index(&table, 4, 5) {
+    table.insert(key, 1);
+  }
+  table.insert(keys_with_index(&table, 6, 1)[0], 1);
+  let counts: Vec<usize> = (0..7)
+    .map(|threshold| table.long_probe_count(threshold))
+    .collect();
+  assert_eq!(counts, [6, 5, 4, 3, 2, 0, 0]);
+  let mut table = OpenIndexTable::new();
+  for i in 1..=10_000 {
+    table.insert(i, i);
+  }
+  let histogram = table.probe_histogram();
+  for threshold in [0, 1, 3, histogram.len()] {
+    let tail = histogram.iter().skip(threshold).sum::<usize>();
+    assert_eq!(table.long_probe_count(threshold), tail);
+  }
+  let mut inline = OpenIndexTable::new();
+  inline.insert(1, 1);
+  assert_eq!(
+    (inline.long_probe_count(0), inline.long_probe_count(1)),
+    (1, 0)
+  );
+}
+
+#[test]
+fn test_retain_notify() {
+  for n in [6u64, 5000] {
+    let mut table = OpenIndexTable::new().with_insertion_order();
+    for key in 0..n {
+      table.insert(key, key * 2);
+    }
+    let mut removed = Vec::new();
+    table.retain_notify(|key, _| key % 2 == 1, |key, v| removed.push((key, v)));
+    // Key 0 is dropped like any other even key, and reported once.
+    removed.sort();
+    assert!(removed
+      .iter()
+      .copied()
+      .eq((0..n).step_by(2).map(|key| (key, key * 2))));
+    assert_eq!(table.len() + removed.len() as u64, n);
+    assert!((0..n).all(|key| table.get(key) == (key % 2 == 1).then_some(key * 2)));
+    assert!(table
+      .iter_ordered()
+      .map(|(key, _)| key)
+      .eq((1..n).step_by(2)));
+  }
+  let mut table = heap_table();
+  table.insert(1, 1);
+  table.insert_hashed(2, 2);
+  let mut calls = 0;
+  table.retain_notify(
+    |key, _| key == 1,
+    |key, v| {
+      assert_eq!((key, v), (2, 2));
+      calls += 1;
+    },
+  );
+  assert_eq!(calls, 1);
+  assert_eq!((table.len(), table.get_hashed(2)), (1, None));
+  table.check_invariants();
+}
+
+#[test]
+fn test_compact_sorts_chains_by_home() {
+  let mut table = heap_table();
+  let last = table.data_cap - 2;
+  // A key homed in slot 1 arrives first, so the chain of the last slot
+  // wrapping around has to step over it.
+  let early = keys_with_index(&table, 2, 4);
+  let wrapping = keys_with_index(&table, last, 5);
+  for key in &early {
+    table.insert(*key, 1);
+  }
+  for key in &early[1..] {
+    table.delete(*key);
+  }
+  for (v, key) in wrapping.iter().enumerate() {
+    table.insert(*key, v as u64);
+  }
+  table.insert_hashed(7, 70);
+  let before = table.probe_histogram();
+  assert_eq!(before.len(), 6);
+  let expected: HashMap<u64, u64> = table.iter().collect();
+  let data_cap = table.data_cap;
+  table.compact();
+  let after = table.probe_histogram();
+  assert_eq!(after.len(), 5);
+  let total =
+    |histogram: &[usize]| -> usize { histogram.iter().enumerate().map(|(d, n)| d * n).sum() };
+  assert_eq!(total(&after), total(&before));
+  assert_eq!(table.data_cap, data_cap);
+  assert_eq!(table.iter().collect::<HashMap<_, _>>(), expected);
+  assert_eq!(table.get_hashed(7), Some(70));
+  assert_eq!(table.get(early[0]), Some(1));
+}
+
+#[test]
+fn test_insert_sorted_resizes_once() {
+  let pairs: Vec<(u64, u64)> = (0..50_000).map(|k| (k * 3, k)).collect();
+  let mut bulk = OpenIndexTable::new();
+  let mut single = OpenIndexTable::new();
+  assert_eq!(count_resizes(|| bulk.insert_sorted(&pairs)), 1);
+  assert!(
+    count_resizes(|| pairs.iter().for_each(|&(k, v)| {
+      single.insert(k, v);
+    }))
+      > 5
+  );
+  assert_eq!(bulk.len(), single.len());
+  for &(key, v) in &pairs {
+    assert_eq!(bulk.get(key), Some(v));
+  }
+  assert_eq!(count_resizes(|| bulk.insert_sorted(&pairs[..10])), 0);
+  assert_eq!(bulk.len(), 50_000);
+}
+
+#[test]
+fn test_try_reserve() {
+  let mut table = OpenIndexTable::new();
+  for i in 1..=100 {
+    table.insert(i, i);
+  }
+  let before = table.memory_bytes();
+  assert!(table.try_reserve(isize::MAX as u64).is_err());
+  assert!(table.try_reserve(u64::MAX).is_err());
+  assert_eq!(table.memory_bytes(), before);
+  assert_eq!(table.size, 100);
+  table.try_reserve(10_000).unwrap();
+  let reserved = table.memory_bytes();
+  assert!(reserved > before);
+  for i in 101..=10_100 {
+    table.try_insert_grow(i, i).unwrap();
+  }
+  assert_eq!(table.memory_bytes(), reserved);
+  for i in 10_101..=20_000 {
+    table.try_insert_grow(i, i).unwrap();
+  }
+  for i in 1..=20_000 {
+    assert_eq!(table.get(i), Some(i));
+  }
+}
+
+#[test]
+fn test_try_insert_grow_probes_once() {
+  let mut table = heap_table();
+  assert_eq!(count_hash_calls(|| table.try_insert_grow(1, 1).unwrap()), 1);
+  assert_eq!(count_hash_calls(|| table.try_insert_grow(1, 2).unwrap()), 1);
+  assert_eq!(table.get(1), Some(2));
+  let mut key = 2;
+  while !table.needs_resize() {
+    table.insert(key, key);
+    key += 1;
+  }
+  // Growing moves the free slot, so the new key is probed for again.
+  let data_cap = table.data_cap;
+  table.try_insert_grow(key, key).unwrap();
+  assert!(table.data_cap > data_cap);
+  assert!((1..=key).all(|k| table.get(k) == Some(if k == 1 { 2 } else { k })));
+}
+
+#[test]
+fn test_try_insert_grow_at_max_capacity() {
+  for max in [5, 100] {
+    let mut table = OpenIndexTable::new().with_max_capacity(max);
+    for key in 1..=max {
+      table.try_insert_grow(key, key).unwrap();
+    }
+    let memory = table.memory_bytes();
+    assert!(table.try_insert_grow(max + 1, 0).is_err());
+    assert_eq!(table.get(max + 1), None);
+    // Updates of present keys still succeed.
+    table.try_insert_grow(1, 0).unwrap();
+    assert_eq!(table.get(1), Some(0));
+    assert_eq!(table.len(), max);
+    assert_eq!(table.memory_bytes(), memory);
+  }
+}
+
+#[test]
+fn test_key_zero_is_ordinary() {
+  let mut table = heap_table();
+  // Key 0 shares its home slot with other keys and sits inside their chain.
+  let home = table.index(0);
+  let colliding = keys_with_index(&table, home, 3);
+  table.insert(colliding[0], 10);
+  table.insert(0, 1);
+  table.insert(colliding[1], 20);
+  table.insert(colliding[2], 30);
+  assert_eq!(table.size, 4);
+  assert_eq!(table.probe_histogram(), [1, 1, 1, 1]);
+  assert_eq!(table.get(0), Some(1));
+  assert_eq!(table.delete(0), Some(1));
+  assert_eq!(table.delete(0), None);
+  assert_eq!(table.size, 3);
+  for (key, v) in colliding.iter().zip([10, 20, 30]) {
+    assert_eq!(table.get(*key), Some(v));
+  }
+
+  let mut table = OpenIndexTable::new();
+  for i in 0..5000 {
+    table.insert(i, i + 1);
+  }
+  for i in (0..5000).step_by(2) {
+    assert_eq!(table.delete(i), Some(i + 1));
+  }
+  table.insert(0, 7);
+  let mut visited = Vec::new();
+  let mut cursor = table.cursor();
+  while let Some((key, v)) = cursor.next() {
+    assert_eq!(v, if key == 0 { 7 } else { key + 1 });
+    visited.push(key);
+  }
+  visited.sort();
+  let mut expected: Vec<u64> = (1..5000).step_by(2).collect();
+  expected.insert(0, 0);
+  assert_eq!(visited, expected);
+}
+
+// Regression test: deleting key 0 once reported success whether or not it
+// was present, and key 0 was left out of len.
+#[test]
+fn test_key_zero_matrix() {
+  let mut tables = [OpenIndexTable::new(), heap_table()];
+  for table in &mut tables {
+    table.insert(1, 1);
+    assert_eq!(table.delete(0), None);
+    assert_eq!(table.get(0), None);
+    assert_eq!(table.len(), 1);
+    table.insert(0, 10);
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.get(0), Some(10));
+    table.insert(0, 20);
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.get(0), Some(20));
+    assert_eq!(table.delete(0), Some(20));
+    assert_eq!(table.len(), 1);
+    assert_eq!(table.delete(0), None);
+    assert_eq!(table.len(), 1);
+    assert_eq!(table.get(0), None);
+    table.insert(0, 30);
+    assert_eq!(table.get(0), Some(30));
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.get(1), Some(1));
+  }
+}
+
+#[test]
+fn test_key_zero_survives_growth() {
+  // Mostly empty slots get rehashed as well; none of them may turn into a
+  // key 0 entry or overwrite the real one.
+  let mut table = OpenIndexTable::new();
+  table.insert(0, 42);
+  table.insert(5, 6);
+  let data_cap = table.data_cap;
+  for key in 1..100 {
+    table.insert(key * 1000, key);
+    assert_eq!(table.get(0), Some(42));
+  }
+  assert!(table.data_cap >= data_cap * 4);
+  assert_eq!(table.len(), 101);
+  assert_eq!(table.iter().filter(|(key, _)| *key == 0).count(), 1);
+  table.delete(0);
+  table.resize();
+  assert_eq!(table.get(0), None);
+  assert_eq!(table.len(), 100);
+
+  let mut table = OpenIndexTable::new();
+  for key in 1..200 {
+    table.insert(key, key);
+  }
+  assert_eq!(table.get(0), None);
+  assert_eq!(table.len(), 199);
+}
+
+#[test]
+fn test_hashed_and_plain_keys_mixed() {
+  let mut table = OpenIndexTable::new();
+  // Sequential hashes are homed in sequential slots without remixing.
+  assert_eq!(table.hashed_index(5), 10);
+  // Plain keys double as the hashes of their own hashed entries.
+  let hash = |i: u64| i.wrapping_mul(0x9E3779B97F4A7C15);
+  for i in 0..5000 {
+    table.insert(hash(i), i);
+    table.insert_hashed(hash(i), i + 1_000_000);
+  }
+  assert_eq!(table.size, 10_000);
+  for i in 0..5000 {
+    assert_eq!(table.get(hash(i)), Some(i));
+    assert_eq!(table.get_hashed(hash(i)), Some(i + 1_000_000));
+  }
+  for i in (0..5000).step_by(2) {
+    assert_eq!(table.delete_hashed(hash(i)), Some(i + 1_000_000));
+    assert_eq!(table.delete_hashed(hash(i)), None);
+  }
+  for i in (1..5000).step_by(2) {
+    assert_eq!(table.delete(hash(i)), Some(i));
+  }
+  for i in 0..5000 {
+    let plain = (i % 2 == 0).then_some(i);
+    let hashed = if i % 2 == 1 {
+      Some(i + 1_000_000)
+    } else {
+      None
+    };
+    assert_eq!(table.get(hash(i)), plain);
+    assert_eq!(table.get_hashed(hash(i)), hashed);
+  }
+  assert_eq!(table.size, 5000);
+  assert_eq!(table.get_hashed(hash(5000)), None);
+}
+
+#[test]
+fn test_capacity_for_and_load_factor() {
+  for n in (0..=9).chain([28, 29, 56, 57, 1000, 100_000]) {
+    let table = OpenIndexTable::with_capacity(n);
+    assert_eq!(table.data_cap, capacity_for(n) as u64);
+    assert!(table.cap >= n as u64);
+    if n as u64 <= INLINE_CAP {
+      assert!(table.is_inline());
+      continue;
+    }
+    assert_eq!(table.keys.len() * 2, capacity_for(n));
+  }
+  assert_eq!(capacity_for(0), 16);
+  assert_eq!(capacity_for(8), 16);
+  assert_eq!(capacity_for(9), 64);
+  assert_eq!(capacity_for(28), 64);
+  assert_eq!(capacity_for(29), 128);
+
+  let mut table = OpenIndexTable::with_capacity(1000);
+  let data_cap = table.data_cap;
+  let mut key = 0;
+  while table.load_factor() < 1.0 {
+    table.insert(key, key);
+    key += 1;
+    assert_eq!(table.data_cap, data_cap);
+  }
+  assert_eq!(table.size, table.cap);
+  table.insert(key, key);
+  assert_eq!(table.data_cap, data_cap * 2);
+  assert!(table.load_factor() < 0.6);
+}
+
+#[test]
+fn test_iter_ordered() {
+  let mut table = OpenIndexTable::new().with_insertion_order();
+  let keys: Vec<u64> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+  for key in &keys {
+    table.insert(*key, key + 1);
+  }
+  // Inserting 1000 keys grew the table several times.
+  assert!(table.data_cap > 64);
+  let ordered: Vec<(u64, u64)> = table.iter_ordered().collect();
+  assert_eq!(
+    ordered,
+    keys.iter().map(|k| (*k, k + 1)).collect::<Vec<_>>()
+  );
+
+  table.insert(keys[0], 5);
+  table.delete(keys[1]);
+  table.insert(keys[1], 6);
+  for key in &keys[2..900] {
+    table.delete(*key);
+  }
+  table.insert_hashed(42, 7);
+  let mut expected = Vec::from([(keys[0], 5)]);
+  expected.extend(keys[900..].iter().map(|k| (*k, k + 1)));
+  expected.push((keys[1], 6));
+  expected.push((42, 7));
+  assert_eq!(table.iter_ordered().collect::<Vec<_>>(), expected);
+  // The deletes compacted the log down to the live entries.
+  assert!(table.order.as_ref().unwrap().log.len() < 300);
+
+  let mut table = OpenIndexTable::new();
+  table.insert(3, 3);
+  let mut table = table.with_insertion_order();
+  table.insert(1, 1);
+  assert_eq!(table.iter_ordered().collect::<Vec<_>>(), [(3, 3), (1, 1)]);
+}
+
+#[cfg(test)]
+fn peak_memory_bytes(mut table: OpenIndexTable) -> usize {
+  let mut peak = 0;
+  for i in 0..10_000 {
+    table.insert(i, i);
+    peak = peak.max(table.memory_bytes());
+  }
+  peak
+}
+
+#[test]
+fn test_cache_line_buckets_delete_across_buckets() {
+  let mut table = heap_table().with_cache_line_buckets();
+  let slots = table.data_cap >> 1;
+  // Keys homed to the last bucket spill over the end of the slot array into
+  // the first bucket, whose own keys then queue behind them.
+  let last: Vec<u64> = (0..)
+    .filter(|key| scramble(*key) & (slots - 1) >= slots - BUCKET_SLOTS)
+    .take(14)
+    .collect();
+  let first: Vec<u64> = (0..)
+    .filter(|key| scramble(*key) & (slots - 1) < BUCKET_SLOTS)
+    .take(10)
+    .collect();
+  let mut model = HashMap::new();
+  let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+  for round in 0..5000 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    let pool = if state & 1 == 0 { &last } else { &first };
+    let key = pool[(state >> 8) as usize % pool.len()];
+    if state & 2 == 0 {
+      table.insert(key, round);
+      model.insert(key, round);
+    } else {
+      assert_eq!(table.delete(key).is_some(), model.remove(&key).is_some());
+    }
+    for key in last.iter().chain(&first) {
+      assert_eq!(table.get(*key), model.get(key).copied());
+    }
+  }
+  assert_eq!(table.data_cap >> 1, slots);
+  let mut grown = OpenIndexTable::new().with_cache_line_buckets();
+  for key in 0..10_000 {
+    grown.insert(key, key);
+  }
+  assert!((0..10_000).all(|key| grown.get(key) == Some(key)));
+}
+
+#[test]
+fn test_alloc_hint_aligned_storage() {
+  let aligned = |table: &OpenIndexTable, align: usize| {
+    (table.keys.as_ptr() as usize).is_multiple_of(align)
+      && (table.values.as_ptr() as usize).is_multiple_of(align)
+  };
+  let mut table = OpenIndexTable::new().alloc_hint(AllocHint::Aligned(4096));
+  let mut model = HashMap::new();
+  for key in 0..20_000 {
+    table.insert(key, key * 2);
+    model.insert(key, key * 2);
+  }
+  assert!(aligned(&table, 4096));
+  for key in (0..20_000).step_by(3) {
+    assert_eq!(table.delete(key), Some(key * 2));
+    model.remove(&key);
+  }
+  table.insert_hashed(u64::MAX, 1);
+  assert_eq!(table.delete_many(&(0..8000).collect::<Vec<_>>()), 5333);
+  model.retain(|key, _| *key >= 8000);
+  table.shrink_to_fit();
+  assert!(aligned(&table, 4096));
+  assert!(table.try_reserve(50_000).is_ok());
+  assert!(aligned(&table, 4096));
+  assert_eq!(table.delete_hashed(u64::MAX), Some(1));
+  assert_eq!(table.iter().collect::<HashMap<_, _>>(), model);
+  let (even, odd) = table.partition(|key, _| key % 2 == 0);
+  assert!(aligned(&even, 4096) && aligned(&odd, 4096));
+  assert_eq!(even.len() + odd.len(), model.len() as u64);
+  let mut keys: Vec<u64> = even.into_keys().chain(odd.into_keys()).collect();
+  keys.sort();
+  let mut expected: Vec<u64> = model.into_keys().collect();
+  expected.sort();
+  assert_eq!(keys, expected);
+  // Arrays of at least one huge page start on a huge page boundary.
+  let mut huge = OpenIndexTable::with_capacity(1 << 19).alloc_hint(AllocHint::HugePages);
+  assert!(aligned(&huge, 2 << 20));
+  huge.insert(7, 70);
+  assert_eq!(huge.get(7), Some(70));
+}
+
+// Allocator counting the allocations it makes and frees, and the bytes live.
+#[cfg(all(test, feature = "allocator_api"))]
+#[derive(Clone, Default)]
+struct CountingAlloc(std::rc::Rc<core::cell::Cell<(usize, usize, usize)>>);
+
+#[cfg(all(test, feature = "allocator_api"))]
+unsafe impl Allocator for CountingAlloc {
+  fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    let (allocs, frees, live) = self.0.get();
+    self.0.set((allocs + 1, frees, live + layout.size()));
+    Global.allocate(layout)
+  }
+
+  unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+    let (allocs, frees, live) = self.0.get();
+    self.0.set((allocs, frees + 1, live - layout.size()));
+    Global.deallocate(ptr, layout)
+  }
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn test_custom_allocator_owns_every_array() {
+  let alloc = CountingAlloc::default();
+  let mut table = OpenIndexTable::new_in(alloc.clone()).alloc_hint(AllocHint::Aligned(128));
+  for key in 0..8 {
+    table.insert(key, key);
+  }
+  // Inline tables allocate nothing.
+  assert_eq!(alloc.0.get(), (0, 0, 0));
+  for key in 8..10_000 {
+    table.insert(key, key * 2);
+  }
+  table.insert_hashed(u64::MAX, 1);
+  let (allocs, frees, live) = alloc.0.get();
+  assert!(allocs > 4 && frees > 0 && live > 0);
+  assert_eq!(allocs - frees, 4);
+  let copy = table.clone();
+  assert!(copy == table);
+  assert_eq!(alloc.0.get().0 - alloc.0.get().1, 8);
+  table.shrink_to_fit();
+  for key in 0..5000 {
+    table.delete(key);
+  }
+  let (even, odd) = copy.partition(|key, _| key % 2 == 0);
+  assert_eq!(even.len() + odd.len(), 10_001);
+  assert_eq!(even.into_keys().count(), 5000);
+  drop(odd);
+  assert_eq!(table.into_values().count(), 5001);
+  let mut inline = OpenIndexTable::with_capacity_in(4, alloc.clone());
+  inline.insert(1, 2);
+  assert_eq!(inline.into_keys().collect::<Vec<_>>(), [1]);
+  let (allocs, frees, live) = alloc.0.get();
+  assert_eq!((frees, live), (allocs, 0));
+}
+
+#[cfg(feature = "zeroize")]
+#[test]
+fn test_zeroize_scrubs_vacated_slots() {
+  let mut table = OpenIndexTable::new().with_zeroize();
+  for key in 1..=6 {
+    table.insert(key, key << 32);
+  }
+  table.delete(2);
+  assert_eq!(table.inline_values[5], 0);
+  for key in 7..=5000 {
+    table.insert(key, key << 32);
+  }
+  for key in (1..=5000).step_by(2) {
+    table.delete(key);
+  }
+  table.insert_hashed(u64::MAX, 1);
+  table.delete_hashed(u64::MAX);
+  let stale = |table: &OpenIndexTable| {
+    (0..table.data_cap >> 1)
+      .filter(|slot| !table.is_occupied(slot << 1))
+      .any(|slot| table.keys[slot as usize] != 0 || table.values[slot as usize] != 0)
+  };
+  assert!(!stale(&table));
+  table.shrink_to_fit();
+  assert!(!stale(&table));
+  assert!((4..=5000)
+    .step_by(2)
+    .all(|key| table.get(key) == Some(key << 32)));
+  // Without the flag deletes leave the old words behind.
+  let mut plain = OpenIndexTable::new();
+  for key in 1..=100 {
+    plain.insert(key, key << 32);
+  }
+  plain.delete(50);
+  assert!(stale(&plain));
+}
+
+// Allocator counting the blocks it frees, and those freed with nonzero bytes.
+#[cfg(all(test, feature = "allocator_api", feature = "zeroize"))]
+#[derive(Clone, Default)]
+struct ScrubCheckingAlloc(std::rc::Rc<core::cell::Cell<(usize, usize)>>);
+
+#[cfg(all(test, feature = "allocator_api", feature = "zeroize"))]
+unsafe impl Allocator for ScrubCheckingAlloc {
+  fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+    Global.allocate(layout)
+  }
+
+  unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+    let bytes = core::slice::from_raw_parts(ptr.as_ptr(), layout.size());
+    let (frees, dirty) = self.0.get();
+    self
+      .0
+      .set((frees + 1, dirty + bytes.iter().any(|b| *b != 0) as usize));
+    Global.deallocate(ptr, layout)
+  }
+}
+
+#[cfg(all(feature = "allocator_api", feature = "zeroize"))]
+#[test]
+fn test_zeroize_scrubs_freed_arrays() {
+  let alloc = ScrubCheckingAlloc::default();
+  let mut table = OpenIndexTable::new_in(alloc.clone()).with_zeroize();
+  for key in 0..10_000 {
+    table.insert(key, !key);
+  }
+  table.insert_hashed(u64::MAX, 1);
+  for key in 0..9000 {
+    table.delete(key);
+  }
+  table.shrink_to_fit();
+  table.compact();
+  let copy = table.clone();
+  assert_eq!(copy.into_values().take(10).count(), 10);
+  drop(table);
+  let (frees, dirty) = alloc.0.get();
+  assert!(frees > 20);
+  assert_eq!(dirty, 0);
+}
+
+#[test]
+fn test_max_capacity_rejects_new_keys() {
+  let mut table = OpenIndexTable::new().with_max_capacity(100);
+  for key in 0..100 {
+    assert_eq!(table.insert_checked(key, key), Ok(()));
+  }
+  let data_cap = table.data_cap;
+  assert_eq!(
+    table.insert_checked(100, 100),
+    Err(CapacityExceeded {
+      requested: 101,
+      limit: 100
+    })
+  );
+  assert_eq!(table.get(100), None);
+  assert_eq!(table.insert_checked(5, 50), Ok(()));
+  assert_eq!(table.get(5), Some(50));
+  assert_eq!(table.len(), 100);
+  // Reserving past the cap only sizes the table for the cap.
+  table.reserve(1 << 20);
+  assert_eq!(table.data_cap, data_cap);
+  assert_eq!(table.data_cap, capacity_for(100) as u64);
+  table.delete(0);
+  assert_eq!(table.insert_checked(100, 100), Ok(()));
+  let mut grown = OpenIndexTable::new()
+    .with_growth_factor(16.0)
+    .with_max_capacity(1000);
+  for key in 0..1000 {
+    grown.insert(key, key);
+  }
+  assert_eq!(grown.data_cap, capacity_for(1000) as u64);
+  let mut exact = OpenIndexTable::new();
+  exact.reserve_exact(1000);
+  assert_eq!(exact.data_cap, capacity_for(1000) as u64);
+}
+
+#[test]
+fn test_reserve_exact_ignores_growth_factor() {
+  // A factor of 3.0 quadruples: 64, 256, 1024, then 4096 slots' worth to
+  // hold 800 entries, where 2048 would do.
+  let mut reserved = OpenIndexTable::new().with_growth_factor(3.0);
+  let mut exact = OpenIndexTable::new().with_growth_factor(3.0);
+  reserved.reserve(800);
+  exact.reserve_exact(800);
+  assert_eq!(reserved.capacity(), cap_for(4096));
+  assert_eq!(exact.capacity(), cap_for(capacity_for(800) as u64));
+  assert_eq!(exact.data_cap, 2048);
+  for table in [&mut reserved, &mut exact] {
+    let capacity = table.capacity();
+    for key in 0..800 {
+      table.insert(key, key);
+    }
+    assert_eq!(table.capacity(), capacity);
+  }
+  // reserve lands where inserting one by one would.
+  let mut grown = OpenIndexTable::new().with_growth_factor(3.0);
+  for key in 0..800 {
+    grown.insert(key, key);
+  }
+  assert_eq!(grown.capacity(), reserved.capacity());
+  // With the default factor both pick the same size.
+  let mut doubled = OpenIndexTable::new();
+  doubled.reserve(800);
+  assert_eq!(doubled.capacity(), exact.capacity());
+}
+
+#[test]
+fn test_max_memory_caps_allocation() {
+  let budget = 40_000;
+  let mut table = OpenIndexTable::new().with_max_memory(budget);
+  let mut inserted = 0;
+  while table.insert_checked(inserted, inserted).is_ok() {
+    assert!(table.memory_bytes() <= budget);
+    inserted += 1;
+  }
+  // The cap is reached exactly at the fill limit of the largest table that
+  // fits, key 0 counting like any other.
+  assert_eq!(inserted, table.cap);
+  assert!(table.heap_bytes(table.data_cap * 2).unwrap() > budget);
+  // Pre-hashed entries fit the budget too.
+  table.delete(1);
+  table.insert_hashed(u64::MAX >> 1, 1);
+  assert!(table.memory_bytes() <= budget);
+  table.delete_hashed(u64::MAX >> 1);
+  table.insert(1, 1);
+  let before = table.clone();
+  let limit = table.cap;
+  assert_eq!(
+    table.insert_checked(u64::MAX, 0),
+    Err(CapacityExceeded {
+      requested: limit + 1,
+      limit
+    })
+  );
+  assert!(table == before);
+  assert_eq!(table.memory_bytes(), before.memory_bytes());
+  table.reserve(1 << 20);
+  assert_eq!(table.data_cap, before.data_cap);
+  assert_eq!(table.delete(0), Some(0));
+  assert_eq!(table.insert_checked(u64::MAX, 0), Ok(()));
+  // The lower of the two caps applies.
+  let capped = OpenIndexTable::new()
+    .with_max_capacity(10)
+    .with_max_memory(budget);
+  assert_eq!(capped.size_limit(), Some(10));
+  let padded = OpenIndexTable::new()
+    .alloc_hint(AllocHint::Aligned(4096))
+    .with_max_memory(budget);
+  assert!(padded.size_limit() < Some(limit));
+}
+
+#[test]
+#[should_panic(expected = "maximum capacity of 3 entries")]
+fn test_max_capacity_insert_panics() {
+  let mut table = OpenIndexTable::new().with_max_capacity(3);
+  for key in 0..4 {
+    table.insert(key, key);
+  }
+}
+
+// Regression test: delete once left size alone, so churning over a bounded
+// set of keys grew the table forever.
+#[test]
+fn test_churn_does_not_grow() {
+  let mut table = OpenIndexTable::new();
+  for key in 0..1000 {
+    table.insert(key, key);
+  }
+  let capacity = table.capacity();
+  assert_eq!(table.data_cap, capacity_for(1000) as u64);
+  // Every key deleted and reinserted 100k times.
+  for round in 0..100_000 {
+    for key in 0..1000 {
+      assert_eq!(table.delete(key), Some(key + round * 1000));
+    }
+    assert!(table.is_empty());
+    for key in 0..1000 {
+      table.insert(key, key + (round + 1) * 1000);
+    }
+    assert_eq!(table.len(), 1000);
+    assert_eq!(table.capacity(), capacity);
+  }
+}
+
+// Regression test: cap was once doubled along with the table instead of
+// recomputed, drifting away from the fill limit.
+#[test]
+fn test_cap_tracks_fill_limit_across_doublings() {
+  let mut table = OpenIndexTable::new();
+  let mut key = 0;
+  for _ in 0..10 {
+    while table.is_inline() || table.size < table.cap {
+      table.insert(key, key);
+      key += 1;
+    }
+    let slots = table.data_cap >> 1;
+    assert_eq!(table.cap, slots * 7 / 8);
+    assert_eq!(table.cap_mask, slots - 1);
+    // Full, but growing only once size exceeds cap.
+    assert_eq!(table.size, table.cap);
+    table.insert(key, key);
+    key += 1;
+    assert_eq!(table.data_cap >> 1, slots * 2);
+  }
+  assert_eq!(table.len(), key);
+}
+
+#[test]
+fn test_growth_policies() {
+  let mut double = OpenIndexTable::new().with_growth_policy(GrowthPolicy::Double);
+  for key in 0..cap_for(128) {
+    double.insert(key, key);
+  }
+  assert_eq!(double.data_cap, 128);
+  double.insert(cap_for(128), 0);
+  assert_eq!(double.data_cap, 256);
+
+  // 4096 bytes of keys and values are 256 slots.
+  let mut bounded = OpenIndexTable::new().with_growth_policy(GrowthPolicy::DoubleUntil(4096));
+  for key in 0..cap_for(512) {
+    assert_eq!(bounded.insert_checked(key, key), Ok(()));
+  }
+  assert_eq!(bounded.data_cap, 512);
+  assert_eq!(
+    bounded.insert_checked(1000, 1),
+    Err(CapacityExceeded {
+      requested: cap_for(512) + 1,
+      limit: cap_for(512)
+    })
+  );
+  assert_eq!(bounded.insert_checked(3, 30), Ok(()));
+  bounded.reserve(1000);
+  assert_eq!(bounded.data_cap, 512);
+  // Budgets below the smallest heap table still allow that table.
+  let mut tiny = OpenIndexTable::new().with_growth_policy(GrowthPolicy::DoubleUntil(0));
+  assert_eq!(tiny.insert_checked(0, 0), Ok(()));
+  assert_eq!(tiny.size_limit(), Some(cap_for(MIN_DATA_CAP)));
+
+  let mut chunked =
+    OpenIndexTable::with_capacity(cap_for(128) as usize).with_growth_policy(GrowthPolicy::Chunked);
+  for key in 0..cap_for(128) {
+    chunked.insert(key, key);
+  }
+  assert_eq!((chunked.data_cap, chunked.cap), (128, 56));
+  // 64 slots step the fill limit by one entry at a time up to 60.
+  let resizes = count_resizes(|| {
+    for key in 56..60 {
+      chunked.insert(key, key);
+      assert_eq!(chunked.cap, key + 1);
+    }
+  });
+  assert_eq!(resizes, 0);
+  assert_eq!(chunked.data_cap, 128);
+  chunked.insert(60, 60);
+  assert_eq!((chunked.data_cap, chunked.cap), (256, cap_for(256)));
+  for key in 61..cap_for(1 << 13) + 10 {
+    chunked.insert(key, key);
+  }
+  // Large tables step by 1/64 of their slots.
+  assert_eq!(
+    (chunked.data_cap, chunked.cap),
+    (1 << 13, cap_for(1 << 13) + 64)
+  );
+  assert!((0..cap_for(1 << 13) + 10).all(|key| chunked.get(key) == Some(key)));
+}
+
+#[test]
+fn test_chunked_rebuilds_keep_raised_cap() {
+  let mut table =
+    OpenIndexTable::with_capacity(cap_for(128) as usize).with_growth_policy(GrowthPolicy::Chunked);
+  for key in 0..58 {
+    table.insert(key, key);
+  }
+  assert_eq!((table.data_cap, table.cap), (128, 58));
+  // Below the default fill limit, so the rebuilds refill to no more than it.
+  for key in 0..4 {
+    table.delete(key);
+  }
+  table.compact();
+  assert_eq!((table.data_cap, table.cap), (128, 58));
+  // A quarter of the table, so delete_many rebuilds it.
+  assert_eq!(table.delete_many(&(4..20).collect::<Vec<u64>>()), 16);
+  assert_eq!((table.data_cap, table.cap), (128, 58));
+  table.retain_notify(|key, _| key != 20, |_, _| {});
+  assert_eq!((table.data_cap, table.cap), (128, 58));
+  table.check_invariants();
+  // Refilling does not double the table the raised cap still has room in.
+  for key in 0..58 {
+    table.insert(key, key);
+  }
+  assert_eq!((table.data_cap, table.cap), (128, 58));
+  assert!((0..58).all(|key| table.get(key) == Some(key)));
+}
+
+#[test]
+fn test_resize_hook_reports_each_rehash() {
+  let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+  let mut table = OpenIndexTable::new();
+  let log = events.clone();
+  table.set_resize_hook(Box::new(move |event| log.lock().unwrap().push(event)));
+  let mut expected = Vec::new();
+  for key in 0..10_000 {
+    let cap = table.cap;
+    table.insert(key, key);
+    if table.cap != cap {
+      // The inline arrays are left before the new key is stored, doublings
+      // happen after.
+      let len = if cap == INLINE_CAP { cap } else { cap + 1 };
+      expected.push((cap, table.cap, len));
+    }
+  }
+  // Moving off the inline arrays and then every doubling.
+  assert_eq!(expected.len(), 10);
+  assert_eq!(expected[0], (INLINE_CAP, cap_for(MIN_DATA_CAP), INLINE_CAP));
+  let reported = |events: &[ResizeEvent]| -> Vec<(u64, u64, u64)> {
+    events
+      .iter()
+      .map(|event| (event.old_capacity, event.new_capacity, event.len))
+      .collect()
+  };
+  assert_eq!(reported(&events.lock().unwrap()), expected);
+  table.delete_many(&(0..9_000).collect::<Vec<_>>());
+  table.shrink_to_fit();
+  table.compact();
+  let events = events.lock().unwrap();
+  assert_eq!(events.len(), 13);
+  assert_eq!(events[11].new_capacity, table.cap);
+  assert_eq!(events[11].len, 1000);
+  assert!(events[11].old_capacity > events[11].new_capacity);
+  // The hook is kept across rehashes, and a clone starts without one.
+  assert_eq!(events[12].old_capacity, table.cap);
+  assert!(table.clone().resize_hook.is_none());
+}
+
+#[test]
+fn test_split_off() {
+  let mut table = OpenIndexTable::new().with_insertion_order();
+  let mut expected = HashMap::new();
+  for i in 0..5000u64 {
+    let key = i.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 8;
+    table.insert(key, i);
+    expected.insert(key, i);
+  }
+  table.insert(0, 1);
+  expected.insert(0, 1);
+  let pivot = 1 << 55;
+  let high = table.split_off(pivot);
+  assert!(high.iter().all(|(key, _)| key >= pivot));
+  assert!(table.iter().all(|(key, _)| key < pivot));
+  assert_eq!(table.get(0), Some(1));
+  assert_eq!(table.len() + high.len(), expected.len() as u64);
+  let mut joined: HashMap<u64, u64> = table.iter().collect();
+  joined.extend(high.iter());
+  assert_eq!(joined, expected);
+  // The new table is sized for what it holds and keeps the insertion order.
+  assert_eq!(high.data_cap, capacity_for(high.len() as usize) as u64);
+  let ordered: Vec<u64> = high.iter_ordered().map(|(_, v)| v).collect();
+  assert!(ordered.windows(2).all(|pair| pair[0] < pair[1]));
+  // Everything or nothing can be split off.
+  let all = table.split_off(0);
+  assert!(table.is_empty());
+  assert_eq!(all.len() + high.len(), expected.len() as u64);
+  assert!(table.split_off(0).is_empty());
+}
+
+// Minor page faults taken by the calling thread so far.
+#[cfg(all(test, target_os = "linux"))]
+fn thread_page_faults() -> u64 {
+  let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+  // Fields follow the parenthesized command name; minflt is the tenth.
+  let fields = &stat[stat.rfind(')').unwrap() + 2..];
+  fields.split(' ').nth(7).unwrap().parse().unwrap()
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_prefaulted_table_does_not_fault() {
+  let n = 1 << 20;
+  let mut table = OpenIndexTable::with_capacity_prefaulted(n);
+  let data_cap = table.data_cap;
+  let before = thread_page_faults();
+  let sum: u64 = table.keys.iter().chain(table.values.iter()).sum();
+  let faults = thread_page_faults() - before;
+  assert_eq!(sum, 0);
+  // Reading untouched arrays would fault once per page, thousands of times.
+  assert!(faults < 16, "{faults} page faults");
+  for key in 0..n as u64 {
+    table.insert(key, key + 1);
+  }
+  assert_eq!(table.data_cap, data_cap);
+  assert!((0..n as u64).all(|key| table.get(key) == Some(key + 1)));
+  assert_eq!(table.len(), n as u64);
+  let small = OpenIndexTable::with_capacity_prefaulted(3);
+  assert!(small.is_inline() && small.is_empty());
+}
+
+#[test]
+fn test_manual_resize() {
+  let mut table = OpenIndexTable::with_capacity(100).with_manual_resize();
+  let mut key = 0;
+  while !table.needs_resize() {
+    table.insert(key, key);
+    key += 1;
+  }
+  let full = key;
+  // Past the fill limit inserts keep going without rehashing.
+  assert_eq!(
+    count_resizes(|| {
+      for key in full..full + 5 {
+        table.insert(key, key);
+      }
+    }),
+    0
+  );
+  assert!(table.load_factor() > 1.0);
+  assert_eq!(count_resizes(|| table.resize()), 1);
+  assert!(!table.needs_resize());
+  assert_eq!(
+    count_resizes(|| {
+      while !table.needs_resize() {
+        table.insert(key, key);
+        key += 1;
+      }
+    }),
+    0
+  );
+  assert!((0..key).all(|key| table.get(key) == Some(key)));
+  // Taking the second to last free slot grows the table anyway.
+  let slots = table.data_cap >> 1;
+  while table.size + 2 < slots {
+    table.insert(key, key);
+    key += 1;
+  }
+  assert_eq!(
+    count_resizes(|| {
+      table.insert(key, key);
+    }),
+    1
+  );
+  assert!((0..=key).all(|key| table.get(key) == Some(key)));
+}
+
+// Inverse of scramble, to construct keys with chosen hashes.
+#[cfg(test)]
+fn unscramble(hash: u64) -> u64 {
+  let mut k = hash ^ (hash >> 31) ^ (hash >> 62);
+  k = k.wrapping_mul(0x3196_42b2_d24d_8ec3);
+  k ^= (k >> 27) ^ (k >> 54);
+  k = k.wrapping_mul(0x96de_1b17_3f11_9089);
+  k ^ (k >> 30) ^ (k >> 60)
+}
+
+#[test]
+fn test_scramble_full_width_keys() {
+  // Runs with debug assertions, so an overflowing multiply would panic.
+  let mut keys = alloc::vec![u64::MAX, u64::MAX - 1, 1 << 63, 1 << 32, 0];
+  let mut state = 0x2545_f491_4f6c_dd1d_u64;
+  for _ in 0..10_000 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    keys.push(state);
+  }
+  let mut table = OpenIndexTable::new();
+  for key in &keys {
+    assert_eq!(unscramble(scramble(*key)), *key);
+    table.insert(*key, !*key);
+  }
+  assert_eq!(table.len(), keys.len() as u64);
+  assert!(keys.iter().all(|key| table.get(*key) == Some(!*key)));
+  // High bits reach the low bits of the hash.
+  let low = |key: u64| scramble(key) & 0xffff;
+  assert_ne!(low(1 << 63), low(0));
+  assert_ne!(low(u64::MAX), low(u64::MAX >> 1));
+}
+
+#[test]
+fn test_strided_keys_spread_out() {
+  // Multiples of a page size all collided in a few dozen home slots under
+  // the previous multiply and shift mixer.
+  let mut table = OpenIndexTable::new();
+  for i in 0..100_000 {
+    table.insert(i * 4096, i);
+  }
+  let histogram = table.probe_histogram();
+  let total: usize = histogram
+    .iter()
+    .enumerate()
+    .map(|(distance, count)| distance * count)
+    .sum();
+  // Linear probing at up to 87.5% load has a long tail even with a uniform
+  // hash; the average stays low.
+  assert!(total < 2 * 100_000);
+  assert!(histogram.len() < 200);
+  let homes: std::collections::HashSet<u64> = (0..100_000)
+    .map(|i| MultiplyShift.hash(i * 4096) & table.home_mask())
+    .collect();
+  assert!(homes.len() < 100);
+}
+
+#[test]
+fn test_sip_hash_resists_scramble_collisions() {
+  // Keys whose scrambled hashes have their low 32 bits zero all share one
+  // home slot.
+  let keys: Vec<u64> = (1..=1000).map(|i| unscramble(i << 32)).collect();
+  let mut scrambled = OpenIndexTable::new();
+  let mut sip = OpenIndexTable::with_hasher(SipHash64::new(0x0123_4567, 0x89ab_cdef));
+  for key in &keys {
+    scrambled.insert(*key, *key);
+    sip.insert(*key, *key);
+  }
+  assert_eq!(scrambled.probe_histogram().len(), keys.len());
+  assert!(sip.probe_histogram().len() < 32);
+  assert!(keys.iter().all(|key| sip.get(*key) == Some(*key)));
+  assert_eq!(sip.delete(keys[0]), Some(keys[0]));
+  assert_eq!(sip.len(), 999);
+}
+
+#[test]
+fn test_eq_ignores_layout() {
+  let mut small = OpenIndexTable::new();
+  let mut large = OpenIndexTable::with_capacity(10_000).with_insertion_order();
+  for key in 0..100 {
+    small.insert(key, key * 3);
+  }
+  for key in (0..100).rev() {
+    large.insert(key, key * 3);
+  }
+  assert!(small == large);
+  large.insert(42, 0);
+  assert!(small != large);
+  large.insert(42, 126);
+  large.delete(7);
+  assert!(small != large);
+  assert!(large != small);
+  // Same size, but each side holds a key the other lacks.
+  large.insert(1000, 21);
+  assert!(small != large);
+  // A pre-hashed entry differs from a plain key with the same bits.
+  let mut plain = OpenIndexTable::new();
+  plain.insert(5, 1);
+  let mut hashed = OpenIndexTable::new();
+  hashed.insert_hashed(5, 1);
+  assert!(plain != hashed);
+  assert!(OpenIndexTable::new() == heap_table());
+}
+
+#[test]
+fn test_growth_factor() {
+  let doubling = peak_memory_bytes(OpenIndexTable::new());
+  let one_and_half = peak_memory_bytes(OpenIndexTable::new().with_growth_factor(1.5));
+  let triple = peak_memory_bytes(OpenIndexTable::new().with_growth_factor(3.0));
+  // Power of two sizes round 1.5x up to a doubling.
+  assert_eq!(one_and_half, doubling);
+  assert!(triple > doubling);
+
+  let mut table = OpenIndexTable::new().with_growth_factor(3.0);
+  for i in 0..=28 {
+    table.insert(i, i);
+  }
+  assert_eq!(table.data_cap, 256);
+  assert_eq!(table.cap, cap_for(256));
+  for i in 0..=28 {
+    assert_eq!(table.get(i), Some(i));
+  }
+}
+
+#[test]
+fn test_partition() {
+  let mut table = OpenIndexTable::new();
+  for i in 0..1000 {
+    table.insert(i, i * 2);
+  }
+  let (even, odd) = table.partition(|key, _| key % 2 == 0);
+  assert_eq!(even.size + odd.size, 1000);
+  for i in 0..1000 {
+    let (inside, outside) = if i % 2 == 0 {
+      (&even, &odd)
+    } else {
+      (&odd, &even)
+    };
+    assert_eq!(inside.get(i), Some(i * 2));
+    assert_eq!(outside.get(i), None);
+  }
+  assert_eq!(even.get(0), Some(0));
+
+  let mut table = OpenIndexTable::new().with_insertion_order();
+  for i in (0..100).rev() {
+    table.insert(i, i);
+  }
+  table.insert_hashed(7, 70);
+  let (high, low) = table.split_by_key_bit(3);
+  assert!(high.iter_ordered().all(|(key, _)| key & 8 != 0));
+  assert!(low.iter_ordered().all(|(key, _)| key & 8 == 0));
+  assert_eq!(high.get_hashed(7), None);
+  assert_eq!(low.get_hashed(7), Some(70));
+  let keys: Vec<u64> = low.iter_ordered().map(|(key, _)| key).collect();
+  let mut expected: Vec<u64> = (0..100).rev().filter(|k| k & 8 == 0).collect();
+  expected.push(7);
+  assert_eq!(keys, expected);
+  assert_eq!(high.size + low.size, 101);
+}
+
+#[test]
+fn test_swap_values() {
+  let mut table = OpenIndexTable::new();
+  for i in 0..100 {
+    table.insert(i, i * 10);
+  }
+  assert_eq!(table.swap_values(3, 70), Ok(()));
+  assert_eq!(table.get(3), Some(700));
+  assert_eq!(table.get(70), Some(30));
+  assert_eq!(table.swap_values(0, 5), Ok(()));
+  assert_eq!(table.get(0), Some(50));
+  assert_eq!(table.get(5), Some(0));
+  assert_eq!(table.swap_values(9, 9), Ok(()));
+  assert_eq!(table.get(9), Some(90));
+  assert_eq!(table.swap_values(100, 1), Err(SwapError::Missing(100)));
+  assert_eq!(table.swap_values(1, 100), Err(SwapError::Missing(100)));
+  assert_eq!(table.get(1), Some(10));
+  table.delete(0);
+  assert_eq!(table.swap_values(0, 1), Err(SwapError::Missing(0)));
+  assert_eq!(table.get(1), Some(10));
+}
+
+#[test]
+fn test_get_or_insert_hashes_once() {
+  let mut single = heap_table();
+  let mut double = heap_table();
+  let (mut single_calls, mut double_calls) = (0, 0);
+  let keys = [5, 9, 5, 0, 9, 12, 0];
+  for key in keys {
+    let mut got = 0;
+    single_calls += count_hash_calls(|| got = single.get_or_insert(key, key + 100));
+    let mut expected = 0;
+    double_calls += count_hash_calls(|| {
+      expected = match double.get(key) {
+        Some(v) => v,
+        _ => {
+          double.insert(key, key + 100);
+          key + 100
+        }
+      }
+    });
+    assert_eq!(got, expected);
+  }
+  // The two call pattern hashes every missing key twice.
+  assert_eq!(single_calls, keys.len());
+  assert_eq!(double_calls, keys.len() + 4);
+  assert_eq!(single.size, double.size);
+}
+
+#[test]
+fn test_try_insert_keeps_existing() {
+  let mut table = OpenIndexTable::new();
+  for key in [0, 1, 42] {
+    assert_eq!(table.try_insert(key, key + 10), Ok(()));
+    assert_eq!(table.try_insert(key, 99), Err(key + 10));
+    assert_eq!(table.get(key), Some(key + 10));
+  }
+  assert_eq!(table.len(), 3);
+}
+
+#[test]
+fn test_stored_zero_is_present() {
+  let mut table = OpenIndexTable::new().with_insertion_order();
+  assert_eq!(table.insert(4, 0), None);
+  assert_eq!(table.insert(0, 0), None);
+  // Overwriting hands back the stored 0.
+  assert_eq!(table.insert(0, 0), Some(0));
+  table.insert_hashed(8, 0);
+  for key in [0, 4] {
+    assert_eq!(table.get(key), Some(0));
+    assert_eq!(table.get_key_value(key), Some((key, 0)));
+    assert_eq!(table.get_or(key, 9), 0);
+    assert_eq!(table.get_or_default(key), 0);
+    assert!(matches!(table.entry(key), Entry::Occupied(entry) if entry.get() == 0));
+  }
+  assert_eq!(table.get_hashed(8), Some(0));
+  assert_eq!(table.get(1), None);
+  assert_eq!(table.get_or(1, 9), 9);
+  assert_eq!(table.get_or_default(1), 0);
+  assert_eq!(
+    table.iter_ordered().collect::<Vec<_>>(),
+    [(4, 0), (0, 0), (8, 0)]
+  );
+  let mut cursor = table.cursor();
+  let mut visited = 0;
+  while let Some((_, v)) = cursor.next() {
+    assert_eq!(v, 0);
+    visited += 1;
+  }
+  assert_eq!(visited, 3);
+  assert_eq!(table.remove_entry(4), Some((4, 0)));
+  assert_eq!(table.delete(0), Some(0));
+  assert_eq!(table.delete(0), None);
+  assert_eq!(table.delete_hashed(8), Some(0));
+  let mut table = OpenIndexTable::new();
+  table.insert(3, 0);
+  assert_eq!(table.into_values().collect::<Vec<_>>(), [0]);
+}
+
+#[test]
+fn test_fetch_update_and_iter() {
+  let mut table = OpenIndexTable::new();
+  assert_eq!(table.fetch_update(3, |v| v.map(|v| v + 1)), None);
+  assert_eq!(table.get(3), None);
+  assert_eq!(table.fetch_update(3, |v| Some(v.unwrap_or(0) + 5)), None);
+  assert_eq!(table.fetch_update(3, |v| Some(v.unwrap_or(0) + 5)), Some(5));
+  assert_eq!(table.get(3), Some(10));
+  assert_eq!(table.fetch_update(3, |_| None), Some(10));
+  assert_eq!(table.get(3), None);
+  for i in 0..100 {
+    table.fetch_update(i, |_| Some(i));
+  }
+  let mut entries: Vec<(u64, u64)> = table.iter().collect();
+  entries.sort();
+  assert_eq!(entries, (0..100).map(|i| (i, i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_entry_combinators() {
+  let mut table = OpenIndexTable::new();
+  assert_eq!(*table.entry(1).or_insert(10), 10);
+  assert_eq!(*table.entry(1).or_insert(20), 10);
+  assert_eq!(*table.entry(2).or_insert_with(|| 30), 30);
+  assert_eq!(*table.entry(2).or_insert_with(|| unreachable!()), 30);
+  *table.entry(3).or_default() += 5;
+  *table.entry(3).or_default() += 5;
+  table.entry(1).and_modify(|v| *v += 1).or_insert(0);
+  table.entry(4).and_modify(|v| *v += 1).or_insert(40);
+  assert_eq!(table.get(1), Some(11));
+  assert_eq!(table.get(2), Some(30));
+  assert_eq!(table.get(3), Some(10));
+  assert_eq!(table.get(4), Some(40));
+  match table.entry(2) {
+    Entry::Occupied(entry) => {
+      assert_eq!(entry.key(), 2);
+      assert_eq!(entry.get(), 30);
+      assert_eq!(entry.remove(), 30);
+    }
+    Entry::Vacant(_) => panic!("key 2 is present"),
+  }
+  assert_eq!(table.get(2), None);
+  assert!(matches!(table.entry(2), Entry::Vacant(entry) if entry.key() == 2));
+}
+
+#[test]
+fn test_entry_key_zero() {
+  let mut table = OpenIndexTable::new();
+  assert!(matches!(table.entry(0), Entry::Vacant(_)));
+  table.entry(0).and_modify(|v| *v = 9);
+  assert!(table.get(0).is_none());
+  *table.entry(0).or_default() += 3;
+  table.entry(0).and_modify(|v| *v *= 2).or_insert(100);
+  assert_eq!(*table.entry(0).or_insert(100), 6);
+  assert_eq!(table.get(0), Some(6));
+  match table.entry(0) {
+    Entry::Occupied(entry) => assert_eq!(entry.remove(), 6),
+    Entry::Vacant(_) => panic!("key 0 is present"),
+  }
+  assert!(table.get(0).is_none());
+}
+
+#[test]
+fn test_entry_insert_grows_table() {
+  let mut table = OpenIndexTable::new();
+  for i in 1..=5000 {
+    let v = table.entry(i).or_insert(i);
+    *v *= 2;
+  }
+  for i in 1..=5000 {
+    assert_eq!(table.get(i), Some(i * 2));
+  }
+}
+
+#[test]
+fn test_upsert_modify_accumulates() {
+  let mut table = OpenIndexTable::new();
+  for _ in 0..10 {
+    table.upsert_modify(0, 100, |v| *v += 1);
+  }
+  assert_eq!(table.get(0), Some(110));
+  // New keys keep growing the table while being modified.
+  for key in 1..10_000 {
+    table.upsert_modify(key, key, |v| *v *= 2);
+    table.upsert_modify(key, 0, |v| *v += 1);
   }
+  assert_eq!(table.len(), 10_000);
+  assert!((1..10_000).all(|key| table.get(key) == Some(key * 2 + 1)));
+}
 
-  fn next(&self, index: u64) -> u64 {
-    (index + 2) & self.data_mask
+#[test]
+fn test_insert_with_is_lazy() {
+  let mut table = OpenIndexTable::new();
+  let mut calls = 0;
+  for key in 0..1_000 {
+    let outcome = table.insert_with(key, || {
+      calls += 1;
+      key * 2
+    });
+    assert_eq!(outcome, InsertOutcome::Inserted);
   }
-
-  pub fn get(&self, key: u64) -> (u64, bool) {
-    if key == FREE_KEY {
-      return (self.free_value, self.free_set);
-    }
-    let mut index = self.index(key);
-    loop {
-      let data = &self.data;
-      let assigned_key = data[index as usize];
-      if assigned_key == FREE_KEY {
-        return (0, false);
-      }
-      if assigned_key == key {
-        return (data[index as usize + 1], true);
-      }
-      index = self.next(index);
-    }
+  for key in 0..1_000 {
+    let outcome = table.insert_with(key, || panic!("called for an occupied key"));
+    assert_eq!(outcome, InsertOutcome::Existing(key * 2));
   }
+  assert_eq!(calls, 1_000);
+  assert_eq!(table.len(), 1_000);
+}
 
-  pub fn insert(&mut self, new_key: u64, v: u64) {
-    if new_key == FREE_KEY {
-      self.free_value = v;
-      self.free_set = true;
-      return;
-    }
-    let mut index = self.index(new_key);
-    loop {
-      let assigned_key = self.data[index as usize];
-      if assigned_key == new_key || assigned_key == FREE_KEY {
-        if assigned_key == FREE_KEY {
-          self.size += 1;
-          self.data[index as usize] = new_key;
-        }
-        self.data[index as usize + 1] = v;
-        break;
-      }
-      index = self.next(index);
-    }
-    self.expand();
+#[test]
+fn test_remove_if() {
+  let mut table = OpenIndexTable::new();
+  for key in 0..100 {
+    table.insert(key, key + 1);
   }
-
-  pub fn delete(&mut self, key: u64) -> (u64, bool) {
-    if key == FREE_KEY {
-      self.free_set = false;
-      return (self.free_value, true);
-    }
-    let mut index = self.index(key);
-    let v;
-    let found;
-    loop {
-      let assigned_key = self.data[index as usize];
-      if assigned_key == key || assigned_key == FREE_KEY {
-        if assigned_key == FREE_KEY {
-          return (0, false);
-        }
-        found = true;
-        self.data[index as usize] = 0;
-        v = self.data[index as usize + 1];
-        break;
-      }
-      index = self.next(index);
-    }
-    self.unshift(index);
-    return (v, found);
+  for key in [0, 7] {
+    assert_eq!(table.remove_if(key, |v| v == key + 2), None);
+    assert_eq!(table.get(key), Some(key + 1));
+    assert_eq!(table.remove_if(key, |v| v == key + 1), Some(key + 1));
+    assert_eq!(table.get(key), None);
+    assert_eq!(
+      table.remove_if(key, |_| panic!("called for a missing key")),
+      None
+    );
   }
+  assert_eq!(table.len(), 98);
+  assert!((1..100)
+    .filter(|k| *k != 7)
+    .all(|k| table.get(k) == Some(k + 1)));
+}
 
-  fn unshift(&mut self, current: u64) {
-    let mut current = current;
-    let mut key;
-    loop {
-      let last = current;
-      current = self.next(current);
-      loop {
-        key = self.data[current as usize];
-        if key == FREE_KEY {
-          self.data[key as usize] = FREE_KEY;
-          return;
-        }
-        let slot = self.index(key);
-        if last < current {
-          if last >= slot || slot > current {
-            break;
-          }
-        } else if last >= slot && slot > current {
-          break;
-        }
-        current = self.next(current);
-      }
-      self.data[last as usize] = key;
-      self.data[last as usize + 1] = self.data[current as usize + 1];
-    }
+#[test]
+fn test_delete_many() {
+  let mut table = OpenIndexTable::new();
+  for key in 0..1_000 {
+    table.insert(key, key);
+  }
+  table.insert_hashed(7u64.wrapping_mul(0x9E37_79B9_7F4A_7C15), 1);
+  // A small batch is deleted key by key.
+  let batch = [5, 5, 10, 2_000, 999];
+  assert_eq!(
+    count_resizes(|| assert_eq!(table.delete_many(&batch), 3)),
+    0
+  );
+  assert_eq!(table.len(), 998);
+  // A large batch rebuilds the table once and leaves hashed entries alone.
+  let mut batch: Vec<u64> = (0..1_000).filter(|k| k % 3 == 0).collect();
+  batch.extend([3, 6, 5_000, 7u64.wrapping_mul(0x9E37_79B9_7F4A_7C15)]);
+  let removed = (0..1_000).filter(|k| k % 3 == 0 && *k != 999).count() as u64;
+  assert_eq!(
+    count_resizes(|| assert_eq!(table.delete_many(&batch), removed)),
+    1
+  );
+  assert_eq!(table.len(), 998 - removed);
+  for key in 0..1_000 {
+    let present = key % 3 != 0 && !matches!(key, 5 | 10);
+    assert_eq!(table.get(key), present.then_some(key));
   }
+  assert_eq!(
+    table.get_hashed(7u64.wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+    Some(1)
+  );
+}
 
-  fn expand(&mut self) {
-    if self.size <= self.cap {
-      return;
-    }
+#[test]
+fn test_contains_value() {
+  let mut table = OpenIndexTable::new();
+  assert!(!table.contains_value(0));
+  table.insert(0, 7);
+  assert!(table.contains_value(7));
+  for i in 1..=1000 {
+    table.insert(i, i * 10);
+  }
+  assert!(table.contains_value(7));
+  assert!(table.contains_value(5000));
+  assert!(!table.contains_value(5001));
+  // Key 0 is an ordinary entry and deleted entries leave no trace.
+  table.delete(0);
+  table.delete(500);
+  assert!(!table.contains_value(7));
+  assert!(!table.contains_value(5000));
+  table.insert_hashed(u64::MAX, 3);
+  assert!(table.contains_value(3));
+}
 
-    let data_cap = self.data_cap * 2;
-    let cap = self.cap * 2;
-    let mut new = OpenIndexTable {
-      data: std::vec::from_elem(0, data_cap as usize),
-      data_cap: data_cap,
-      data_mask: data_cap - 1,
-      cap_mask: (data_cap >> 1) - 1,
-      cap: cap,
-      size: self.size,
-      free_value: self.free_value,
-      free_set: self.free_set,
-    };
-    let mut n = 0;
-    while n < self.data_cap {
-      new.insert(self.data[n as usize], self.data[n as usize + 1]);
-      n += 2;
-    }
-    *self = new;
+#[test]
+fn test_min_max_key() {
+  let mut table = OpenIndexTable::new();
+  assert_eq!((table.min_key(), table.max_key()), (None, None));
+  table.insert(42, 1);
+  assert_eq!((table.min_key(), table.max_key()), (Some(42), Some(42)));
+  for key in (100..10_000).step_by(7) {
+    table.insert(key, key);
   }
+  assert_eq!((table.min_key(), table.max_key()), (Some(42), Some(9998)));
+  table.insert(0, 0);
+  table.insert(u64::MAX, 0);
+  assert_eq!(
+    (table.min_key(), table.max_key()),
+    (Some(0), Some(u64::MAX))
+  );
+  table.delete(0);
+  table.delete(u64::MAX);
+  table.delete(42);
+  assert_eq!((table.min_key(), table.max_key()), (Some(100), Some(9998)));
 }
 
-extern crate test;
-use std::collections::HashMap;
-use test::Bencher;
+#[test]
+fn test_iter_with_slots() {
+  let mut table = OpenIndexTable::new();
+  for key in [5, 0, 9] {
+    table.insert(key, key + 1);
+  }
+  let slots: Vec<_> = table.iter_with_slots().collect();
+  assert_eq!(slots, [(0, 5, 6), (1, 0, 1), (2, 9, 10)]);
+  for key in 1..5000 {
+    table.insert(key * 3, key);
+  }
+  for key in (1..5000).step_by(4) {
+    table.delete(key * 3);
+  }
+  let mut seen = 0;
+  for (slot, key, v) in table.iter_with_slots() {
+    assert_eq!(table.find(key), ((slot as u64) << 1, true));
+    assert_eq!(table.value_at((slot as u64) << 1), v);
+    seen += 1;
+  }
+  assert_eq!(seen, table.len());
+  assert!(table
+    .iter_with_slots()
+    .map(|(_, key, v)| (key, v))
+    .eq(table.iter()));
+}
 
 #[test]
-fn test_table_insert() {
+fn test_get_key_value_and_remove_entry() {
   let mut table = OpenIndexTable::new();
-  table.insert(1, 2);
-  table.insert(2, 3);
-  table.insert(3, 4);
-  table.insert(4, 5);
-  assert_eq!(table.get(1), (2, true));
-  assert_eq!(table.get(2), (3, true));
-  assert_eq!(table.get(3), (4, true));
-  assert_eq!(table.get(4), (5, true));
+  assert_eq!(table.get_key_value(0), None);
+  assert_eq!(table.remove_entry(0), None);
+  for i in 1..=100 {
+    table.insert(i, i * 10);
+  }
+  table.insert(0, 5);
+  assert_eq!(table.get_key_value(0), Some((0, 5)));
+  assert_eq!(table.get_key_value(42), Some((42, 420)));
+  assert_eq!(table.get_key_value(101), None);
+  assert_eq!(table.remove_entry(0), Some((0, 5)));
+  assert_eq!(table.remove_entry(0), None);
+  for i in 1..=100 {
+    assert_eq!(table.remove_entry(i), Some((i, i * 10)));
+    assert_eq!(table.remove_entry(i), None);
+    assert_eq!(table.get_key_value(i), None);
+  }
 }
 
+#[cfg(feature = "rayon")]
 #[test]
-fn test_table_delete() {
+fn test_par_iter_sum() {
   let mut table = OpenIndexTable::new();
-  table.insert(1, 2);
-  table.insert(2, 3);
-  table.insert(3, 4);
-  table.insert(4, 5);
-  assert_eq!(table.get(1), (2, true));
-  assert_eq!(table.get(2), (3, true));
-  assert_eq!(table.get(3), (4, true));
-  assert_eq!(table.get(4), (5, true));
-  assert_eq!(table.delete(1), (2, true));
-  assert_eq!(table.delete(2), (3, true));
-  assert_eq!(table.delete(3), (4, true));
-  assert_eq!(table.delete(4), (5, true));
-  assert_eq!(table.get(1), (0, false));
-  assert_eq!(table.get(2), (0, false));
-  assert_eq!(table.get(3), (0, false));
-  assert_eq!(table.get(4), (0, false));
+  for i in 1..=100_000 {
+    table.insert(i, i * 3);
+  }
+  table.insert(0, 7);
+  let mut sequential = 0;
+  let mut count = 0;
+  let mut cursor = table.cursor();
+  while let Some((_, v)) = cursor.next() {
+    sequential += v;
+    count += 1;
+  }
+  assert_eq!(table.par_values().sum::<u64>(), sequential);
+  assert_eq!(table.par_iter().count(), count);
+  assert_eq!(
+    table
+      .par_iter()
+      .filter(|(k, _)| *k == 0)
+      .collect::<Vec<_>>(),
+    [(0, 7)]
+  );
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_from_par_iter_matches_sequential_build() {
+  for n in [0u64, 5, 100, 200_000] {
+    // Repeated keys, including key 0, whose last value has to win.
+    let pairs: Vec<(u64, u64)> = (0..n)
+      .map(|i| (scramble(i) % (n / 2 + 1), i))
+      .chain([(0, u64::MAX)])
+      .collect();
+    let parallel: OpenIndexTable = pairs.par_iter().copied().collect();
+    let mut sequential = OpenIndexTable::new();
+    sequential.insert_sorted(&pairs);
+    parallel.check_invariants();
+    assert_eq!(parallel.len(), sequential.len());
+    assert!(parallel == sequential);
+    assert_eq!(parallel.get(0), Some(u64::MAX));
+  }
+  // Filled right up to the fill limit, so many chains run past the end of
+  // their range.
+  let n = 1 << 17;
+  let full =
+    OpenIndexTable::<Scramble>::from_par_iter((0..n / 16 * 14).into_par_iter().map(|i| (i, i + 1)));
+  assert_eq!(full.data_cap, n * 2);
+  full.check_invariants();
+  assert!((0..n / 16 * 14).all(|i| full.get(i) == Some(i + 1)));
+}
+
+#[cfg(all(test, feature = "rayon"))]
+fn bulk_load_pairs() -> Vec<(u64, u64)> {
+  (0..1 << 20).map(|i| (i * 7, i)).collect()
+}
+
+#[cfg(all(test, feature = "rayon"))]
+#[bench]
+fn bench_table_bulk_load_sequential(b: &mut Bencher) {
+  let pairs = bulk_load_pairs();
+  b.iter(|| {
+    let mut table = OpenIndexTable::new();
+    table.insert_sorted(&pairs);
+    table
+  });
+}
+
+#[cfg(all(test, feature = "rayon"))]
+#[bench]
+fn bench_table_bulk_load_parallel(b: &mut Bencher) {
+  let pairs = bulk_load_pairs();
+  b.iter(|| pairs.par_iter().copied().collect::<OpenIndexTable>());
 }
 
+#[cfg(test)]
 #[bench]
 fn bench_std_map_insert(b: &mut Bencher) {
   let mut map = HashMap::new();
@@ -203,6 +4630,7 @@ fn bench_std_map_insert(b: &mut Bencher) {
   });
 }
 
+#[cfg(test)]
 #[bench]
 fn bench_table_insert(b: &mut Bencher) {
   let mut table = OpenIndexTable::new();
@@ -213,6 +4641,49 @@ fn bench_table_insert(b: &mut Bencher) {
   });
 }
 
+#[cfg(test)]
+#[bench]
+fn bench_std_map_small_construct_insert_3(b: &mut Bencher) {
+  let mut i: u64 = 0;
+  b.iter(|| {
+    let mut map = HashMap::new();
+    for key in i..i + 3 {
+      map.insert(key, key);
+    }
+    i += 3;
+    test::black_box(map);
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_small_construct_insert_3(b: &mut Bencher) {
+  let mut i: u64 = 0;
+  b.iter(|| {
+    let mut table = OpenIndexTable::new();
+    for key in i..i + 3 {
+      table.insert(key, key);
+    }
+    i += 3;
+    test::black_box(table);
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_small_construct_insert_3_heap(b: &mut Bencher) {
+  let mut i: u64 = 0;
+  b.iter(|| {
+    let mut table = heap_table();
+    for key in i..i + 3 {
+      table.insert(key, key);
+    }
+    i += 3;
+    test::black_box(table);
+  });
+}
+
+#[cfg(test)]
 #[bench]
 fn bench_std_map_get(b: &mut Bencher) {
   let mut map: HashMap<u64, u64> = HashMap::new();
@@ -228,6 +4699,7 @@ fn bench_std_map_get(b: &mut Bencher) {
   });
 }
 
+#[cfg(test)]
 #[bench]
 fn bench_table_get(b: &mut Bencher) {
   let mut table = OpenIndexTable::new();
@@ -243,6 +4715,135 @@ fn bench_table_get(b: &mut Bencher) {
   });
 }
 
+#[cfg(test)]
+#[bench]
+fn bench_table_get_miss(b: &mut Bencher) {
+  let mut table = OpenIndexTable::new();
+  let max = 2 << 24;
+  for i in 0..max {
+    table.insert(i, i);
+  }
+  let mut i: u64 = 0;
+  b.iter(|| {
+    test::black_box(table.get(max + i));
+    i += 1;
+    i %= max;
+  });
+}
+
+// The multiply and shift mixer scramble used before the splitmix64
+// finalizer, kept to compare against on strided keys.
+#[cfg(test)]
+#[derive(Clone)]
+struct MultiplyShift;
+
+#[cfg(test)]
+impl Hasher64 for MultiplyShift {
+  fn hash(&self, key: u64) -> u64 {
+    let hash = key.wrapping_mul(0x9E3779B9);
+    hash.wrapping_mul(hash >> 16)
+  }
+}
+
+// Looks up keys that are multiples of a page size, like offsets or pointers.
+#[cfg(test)]
+fn bench_strided_get<H: Hasher64>(b: &mut Bencher, hasher: H) {
+  let mut table = OpenIndexTable::with_hasher(hasher);
+  let max = 1 << 14;
+  for i in 0..max {
+    table.insert(i * 4096, i);
+  }
+  let mut i: u64 = 0;
+  b.iter(|| {
+    test::black_box(table.get(i * 4096));
+    i += 1;
+    i %= max;
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_strided_get(b: &mut Bencher) {
+  bench_strided_get(b, Scramble);
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_strided_get_multiply_shift(b: &mut Bencher) {
+  bench_strided_get(b, MultiplyShift);
+}
+
+// Looks up present keys, or absent ones if miss is set, in a table of 2^22
+// slots filled to load times its fill limit.
+#[cfg(test)]
+fn bench_get_at_load(b: &mut Bencher, table: OpenIndexTable, load: f64, miss: bool) {
+  let mut table = table;
+  table.reserve(cap_for(1 << 23));
+  let max = (table.cap as f64 * load) as u64;
+  for i in 0..max {
+    table.insert(i, i);
+  }
+  let offset = if miss { max } else { 0 };
+  let mut i: u64 = 0;
+  b.iter(|| {
+    test::black_box(table.get(offset + i));
+    i += 1;
+    i %= max;
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_get_load_half(b: &mut Bencher) {
+  bench_get_at_load(b, OpenIndexTable::new(), 0.5, false);
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_get_load_full(b: &mut Bencher) {
+  bench_get_at_load(b, OpenIndexTable::new(), 1.0, false);
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_get_miss_load_full(b: &mut Bencher) {
+  bench_get_at_load(b, OpenIndexTable::new(), 1.0, true);
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_bucketed_get_load_half(b: &mut Bencher) {
+  bench_get_at_load(
+    b,
+    OpenIndexTable::new().with_cache_line_buckets(),
+    0.5,
+    false,
+  );
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_bucketed_get_load_full(b: &mut Bencher) {
+  bench_get_at_load(
+    b,
+    OpenIndexTable::new().with_cache_line_buckets(),
+    1.0,
+    false,
+  );
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_table_bucketed_get_miss_load_full(b: &mut Bencher) {
+  bench_get_at_load(
+    b,
+    OpenIndexTable::new().with_cache_line_buckets(),
+    1.0,
+    true,
+  );
+}
+
+#[cfg(test)]
 #[bench]
 fn bench_std_map_delete(b: &mut Bencher) {
   let mut map: HashMap<u64, u64> = HashMap::new();
@@ -254,12 +4855,13 @@ fn bench_std_map_delete(b: &mut Bencher) {
   b.iter(|| {
     test::black_box(map.remove(&i));
     i += 1;
-    if i % max == 0 {
+    if i.is_multiple_of(max) {
       panic!("Benchmark to big")
     }
   });
 }
 
+#[cfg(test)]
 #[bench]
 fn bench_table_delete(b: &mut Bencher) {
   let mut table = OpenIndexTable::new();
@@ -271,8 +4873,339 @@ fn bench_table_delete(b: &mut Bencher) {
   b.iter(|| {
     test::black_box(table.delete(i));
     i += 1;
-    if i % max == 0 {
+    if i.is_multiple_of(max) {
       panic!("Benchmark to big")
     }
   });
 }
+
+// Sums a table several times the size of the last level cache on a pool of
+// the given size.
+#[cfg(all(test, feature = "rayon"))]
+fn bench_par_sum(b: &mut Bencher, threads: usize) {
+  let mut table = OpenIndexTable::new();
+  for i in 1..=1 << 23 {
+    table.insert(i, i);
+  }
+  let pool = rayon::ThreadPoolBuilder::new()
+    .num_threads(threads)
+    .build()
+    .unwrap();
+  b.iter(|| pool.install(|| test::black_box(table.par_values().sum::<u64>())));
+}
+
+#[cfg(all(test, feature = "rayon"))]
+#[bench]
+fn bench_table_par_sum_1_thread(b: &mut Bencher) {
+  bench_par_sum(b, 1);
+}
+
+#[cfg(all(test, feature = "rayon"))]
+#[bench]
+fn bench_table_par_sum_2_threads(b: &mut Bencher) {
+  bench_par_sum(b, 2);
+}
+
+#[cfg(all(test, feature = "rayon"))]
+#[bench]
+fn bench_table_par_sum_4_threads(b: &mut Bencher) {
+  bench_par_sum(b, 4);
+}
+
+#[cfg(all(test, feature = "rayon"))]
+#[bench]
+fn bench_table_par_sum_8_threads(b: &mut Bencher) {
+  bench_par_sum(b, 8);
+}
+
+#[test]
+fn test_clone_into_reuses_allocation() {
+  let mut src = OpenIndexTable::new();
+  for key in 0..1000 {
+    src.insert(key * 7, key);
+  }
+  src.insert_hashed(u64::MAX, 3);
+
+  // A larger destination is refilled in place.
+  let mut dest = OpenIndexTable::with_capacity(10_000);
+  for key in 0..5000 {
+    dest.insert(key, key);
+  }
+  dest.insert_hashed(1, 1);
+  let (keys, bytes) = (dest.keys.as_ptr(), dest.memory_bytes());
+  src.clone_into(&mut dest);
+  assert_eq!(dest.keys.as_ptr(), keys);
+  assert_eq!(dest.memory_bytes(), bytes);
+  assert!(dest == src);
+  assert_eq!(dest.get(5), None);
+  assert_eq!(dest.get(14), Some(2));
+  assert_eq!(dest.get_hashed(u64::MAX), Some(3));
+
+  // A destination of the same size gets a slot for slot copy.
+  let mut dest = OpenIndexTable::with_capacity(src.len() as usize);
+  assert_eq!(dest.data_cap, src.data_cap);
+  let keys = dest.keys.as_ptr();
+  src.clone_into(&mut dest);
+  assert_eq!(dest.keys.as_ptr(), keys);
+  assert!(dest == src);
+  assert_eq!(
+    dest.iter_with_slots().collect::<Vec<_>>(),
+    src.iter_with_slots().collect::<Vec<_>>()
+  );
+
+  // Inline sources fit any heap destination, and a smaller one is replaced.
+  let mut small = OpenIndexTable::new();
+  small.insert(1, 2);
+  small.clone_into(&mut dest);
+  assert_eq!(dest.keys.as_ptr(), keys);
+  assert!(dest == small);
+  src.clone_into(&mut small);
+  assert!(small == src);
+  small.insert(7_000_000, 1);
+  assert_eq!(src.get(7_000_000), None);
+}
+
+#[test]
+fn test_precomputed_home_matches_plain_api() {
+  let mut plain = OpenIndexTable::new();
+  let mut precomputed = OpenIndexTable::new();
+  for key in 0..5000u64 {
+    let key = key * 4099;
+    plain.insert(key, key + 1);
+    let home = Scramble.hash(key) & precomputed.home_mask();
+    precomputed.insert_precomputed(home, key, key + 1);
+  }
+  // Overwrites go to the existing slot.
+  let home = Scramble.hash(4099) & precomputed.home_mask();
+  precomputed.insert_precomputed(home, 4099, 7);
+  plain.insert(4099, 7);
+  assert!(plain == precomputed);
+  for key in 0..10_000u64 {
+    let key = key * 4099;
+    let home = Scramble.hash(key) & precomputed.home_mask();
+    assert_eq!(precomputed.get_precomputed(home, key), plain.get(key));
+  }
+  let bucketed = OpenIndexTable::with_capacity(1000).with_cache_line_buckets();
+  assert_eq!(bucketed.home_mask() % BUCKET_SLOTS, 0);
+}
+
+#[test]
+#[should_panic(expected = "outside the home mask")]
+fn test_precomputed_home_out_of_range() {
+  let table = OpenIndexTable::with_capacity(100);
+  table.get_precomputed(table.home_mask() + 1, 0);
+}
+
+#[test]
+fn test_scan_across_writes() {
+  let walk = |table: &mut OpenIndexTable, write: &mut dyn FnMut(&mut OpenIndexTable, u64)| {
+    let (mut cursor, mut seen, mut round) = (0, Vec::new(), 0);
+    loop {
+      cursor = table.scan(cursor, 10, &mut seen);
+      if cursor == 0 {
+        return seen;
+      }
+      write(table, round);
+      round += 1;
+    }
+  };
+  // Without growing, entries there all along come out exactly once.
+  let mut table = OpenIndexTable::with_capacity(4000);
+  for key in 0..1000 {
+    table.insert(key, key);
+  }
+  let mut seen = Vec::new();
+  let resizes = count_resizes(|| {
+    seen = walk(&mut table, &mut |table, round| {
+      table.insert(2000 + round, round);
+      table.delete(2000 + round / 2);
+      table.delete(round * 3 % 500);
+    })
+  });
+  assert_eq!(resizes, 0);
+  seen.retain(|(key, _)| *key >= 500 && *key < 1000);
+  seen.sort();
+  assert!(seen.into_iter().eq((500..1000).map(|key| (key, key))));
+  // Growing may repeat entries but misses none.
+  let mut table = heap_table();
+  for key in 0..1000 {
+    table.insert(key, key);
+  }
+  let mut seen = Vec::new();
+  let resizes = count_resizes(|| {
+    seen = walk(&mut table, &mut |table, round| {
+      for key in 0..20 {
+        table.insert(10_000 + round * 20 + key, 0);
+      }
+    })
+  });
+  assert!(resizes > 0);
+  let mut keys: Vec<u64> = seen.into_iter().map(|(key, _)| key).collect();
+  keys.sort();
+  keys.dedup();
+  assert!((0..1000).all(|key| keys.binary_search(&key).is_ok()));
+}
+
+#[test]
+fn test_fold_values() {
+  let sum_and_max =
+    |table: &OpenIndexTable| table.fold_values((0, 0), |(sum, max), v| (sum + v, max.max(v)));
+  let mut table = OpenIndexTable::new();
+  assert_eq!(sum_and_max(&table), (0, 0));
+  table.insert(0, 40);
+  table.insert(7, 2);
+  assert_eq!(sum_and_max(&table), (42, 40));
+  let values: Vec<u64> = (1..3000).map(|key| key * 7 % 1009).collect();
+  for (key, v) in (1..3000).zip(&values) {
+    table.insert(key * 11, *v);
+  }
+  table.delete(7);
+  let sum = 40 + values.iter().sum::<u64>();
+  let max = values.iter().copied().fold(40, u64::max);
+  assert_eq!(sum_and_max(&table), (sum, max));
+  assert_eq!(table.fold_values(0, |count, _| count + 1), table.len());
+}
+
+#[test]
+fn test_for_each_matches_iter() {
+  let visit = |table: &OpenIndexTable| {
+    let mut entries = Vec::new();
+    table.for_each(|key, v| entries.push((key, v)));
+    assert_eq!(entries, table.iter().collect::<Vec<_>>());
+    let mut sum = 0u64;
+    table.for_each(|_, v| sum = sum.wrapping_add(v));
+    assert_eq!(
+      sum,
+      table.iter().fold(0u64, |sum, (_, v)| sum.wrapping_add(v))
+    );
+  };
+  let mut table = OpenIndexTable::new();
+  visit(&table);
+  table.insert(0, 5);
+  table.insert(3, 6);
+  visit(&table);
+  for key in 1..5000 {
+    table.insert(key * 13, key);
+  }
+  for key in (1..5000).step_by(3) {
+    table.delete(key * 13);
+  }
+  table.insert_hashed(u64::MAX, 1);
+  visit(&table);
+}
+
+#[test]
+fn test_get_disjoint_mut_swaps() {
+  for mut table in [OpenIndexTable::new(), heap_table()] {
+    table.insert(1, 10);
+    table.insert(2, 20);
+    let (a, b) = table.get_disjoint_mut(1, 2);
+    core::mem::swap(a.unwrap(), b.unwrap());
+    assert_eq!(table.get(1), Some(20));
+    assert_eq!(table.get(2), Some(10));
+    let (a, b) = table.get_disjoint_mut(3, 2);
+    assert!(a.is_none());
+    *b.unwrap() += 1;
+    assert_eq!(table.get(2), Some(11));
+    assert_eq!(table.get_disjoint_mut(3, 4), (None, None));
+  }
+}
+
+#[test]
+#[should_panic(expected = "keys must be distinct")]
+fn test_get_disjoint_mut_same_key() {
+  let mut table = OpenIndexTable::new();
+  table.insert(1, 1);
+  table.get_disjoint_mut(1, 1);
+}
+
+#[test]
+fn test_capacity_overflow_is_reported() {
+  for n in [usize::MAX, MAX_SLOTS as usize + 1] {
+    assert!(OpenIndexTable::try_with_capacity(n).is_err());
+  }
+  let table = OpenIndexTable::try_with_capacity(1000).unwrap();
+  assert_eq!(table.data_cap, capacity_for(1000) as u64);
+  assert!(OpenIndexTable::try_with_capacity(3).unwrap().is_inline());
+
+  let mut table = heap_table();
+  table.insert(1, 2);
+  let data_cap = table.data_cap;
+  assert!(table.try_reserve(u64::MAX).is_err());
+  assert!(table.try_reserve(MAX_SLOTS).is_err());
+  assert_eq!(table.data_cap, data_cap);
+  assert_eq!(table.get(1), Some(2));
+}
+
+#[test]
+#[should_panic(expected = "capacity overflow")]
+fn test_with_capacity_overflow_panics() {
+  OpenIndexTable::with_capacity(usize::MAX);
+}
+
+#[test]
+#[should_panic(expected = "capacity overflow")]
+fn test_growth_factor_overflow_panics() {
+  // Growing by this much would wrap to a tiny table without the check.
+  // Leaving the inline slots goes to the smallest heap table; the first
+  // resize after that applies the factor.
+  let mut table = OpenIndexTable::new().with_growth_factor(1e30);
+  for key in 0..MIN_DATA_CAP {
+    table.insert(key, key);
+  }
+}
+
+#[test]
+fn test_random_operations_keep_invariants() {
+  let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+  let mut next = move |n: u64| {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state % n
+  };
+  let tables = [
+    OpenIndexTable::new(),
+    OpenIndexTable::new().with_cache_line_buckets(),
+    OpenIndexTable::new().with_insertion_order(),
+    OpenIndexTable::new().with_growth_policy(GrowthPolicy::Chunked),
+  ];
+  for mut table in tables {
+    let mut model = HashMap::new();
+    for _ in 0..5000 {
+      // A small key range keeps chains long and deletes hitting them.
+      let key = next(600);
+      match next(16) {
+        0..=6 => {
+          table.insert(key, key + 1);
+          model.insert(key, key + 1);
+        }
+        7..=12 => assert_eq!(table.delete(key).is_some(), model.remove(&key).is_some()),
+        13 => {
+          table.insert_hashed(key << 40, key);
+          table.delete_hashed(key << 40);
+        }
+        14 => table.shrink_to_fit(),
+        _ => {
+          if next(8) == 0 {
+            table.compact();
+          }
+        }
+      }
+      table.check_invariants();
+      assert_eq!(table.len(), model.len() as u64);
+    }
+    assert!(model.iter().all(|(key, v)| table.get(*key) == Some(*v)));
+  }
+}
+
+// Regression test: expand once started the grown table off with the old
+// size and then counted every reinserted entry again.
+#[test]
+fn test_expand_keeps_size() {
+  let mut table = OpenIndexTable::new();
+  for key in 1..=1000 {
+    table.insert(key, key);
+    assert_eq!(table.size, key);
+  }
+}
@@ -0,0 +1,268 @@
+use crate::hasher::{Hasher64, Scramble};
+#[cfg(test)]
+use crate::open_index_table::CapacityExceeded;
+use crate::open_index_table::{GrowthPolicy, OpenIndexTable};
+use crate::slot_array::AllocHint;
+#[cfg(test)]
+use alloc::vec::Vec;
+
+// Why build rejected a combination of options.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildError {
+  // The growth factor is not above 1.0.
+  GrowthFactor(f64),
+  // An Aligned alloc hint that is not a power of two.
+  Alignment(usize),
+  // The memory budget is below the smallest heap table.
+  MemoryBudget(usize),
+  // The initial capacity is above the entries the table may hold, from
+  // max_capacity, max_memory or the growth policy.
+  CapacityAboveLimit { capacity: u64, limit: u64 },
+}
+
+// Collects the options of an OpenIndexTable and checks them together, where
+// the with_ methods on the table panic one at a time. Options apply in a
+// fixed order, so e.g. max_memory accounts for the alloc hint whichever was
+// set first.
+pub struct OpenIndexTableBuilder<H: Hasher64 = Scramble> {
+  hasher: H,
+  capacity: u64,
+  growth_factor: Option<f64>,
+  growth_policy: Option<GrowthPolicy>,
+  max_capacity: Option<u64>,
+  max_memory: Option<usize>,
+  alloc_hint: Option<AllocHint>,
+  insertion_order: bool,
+  manual_resize: bool,
+  cache_line_buckets: bool,
+}
+
+impl OpenIndexTableBuilder {
+  pub fn new() -> OpenIndexTableBuilder {
+    OpenIndexTableBuilder::with_hasher(Scramble)
+  }
+}
+
+impl Default for OpenIndexTableBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<H: Hasher64> OpenIndexTableBuilder<H> {
+  pub fn with_hasher(hasher: H) -> OpenIndexTableBuilder<H> {
+    OpenIndexTableBuilder {
+      hasher,
+      capacity: 0,
+      growth_factor: None,
+      growth_policy: None,
+      max_capacity: None,
+      max_memory: None,
+      alloc_hint: None,
+      insertion_order: false,
+      manual_resize: false,
+      cache_line_buckets: false,
+    }
+  }
+
+  // Switches to hashing keys with hasher, keeping the other options.
+  pub fn hasher<H2: Hasher64>(self, hasher: H2) -> OpenIndexTableBuilder<H2> {
+    OpenIndexTableBuilder {
+      hasher,
+      capacity: self.capacity,
+      growth_factor: self.growth_factor,
+      growth_policy: self.growth_policy,
+      max_capacity: self.max_capacity,
+      max_memory: self.max_memory,
+      alloc_hint: self.alloc_hint,
+      insertion_order: self.insertion_order,
+      manual_resize: self.manual_resize,
+      cache_line_buckets: self.cache_line_buckets,
+    }
+  }
+
+  // Entries the table holds before it first has to grow.
+  pub fn capacity(mut self, entries: u64) -> OpenIndexTableBuilder<H> {
+    self.capacity = entries;
+    self
+  }
+
+  pub fn growth_factor(mut self, factor: f64) -> OpenIndexTableBuilder<H> {
+    self.growth_factor = Some(factor);
+    self
+  }
+
+  pub fn growth_policy(mut self, policy: GrowthPolicy) -> OpenIndexTableBuilder<H> {
+    self.growth_policy = Some(policy);
+    self
+  }
+
+  pub fn max_capacity(mut self, entries: u64) -> OpenIndexTableBuilder<H> {
+    self.max_capacity = Some(entries);
+    self
+  }
+
+  pub fn max_memory(mut self, bytes: usize) -> OpenIndexTableBuilder<H> {
+    self.max_memory = Some(bytes);
+    self
+  }
+
+  pub fn alloc_hint(mut self, hint: AllocHint) -> OpenIndexTableBuilder<H> {
+    self.alloc_hint = Some(hint);
+    self
+  }
+
+  pub fn insertion_order(mut self) -> OpenIndexTableBuilder<H> {
+    self.insertion_order = true;
+    self
+  }
+
+  pub fn manual_resize(mut self) -> OpenIndexTableBuilder<H> {
+    self.manual_resize = true;
+    self
+  }
+
+  pub fn cache_line_buckets(mut self) -> OpenIndexTableBuilder<H> {
+    self.cache_line_buckets = true;
+    self
+  }
+
+  // Checks the options and creates the table, allocating once for the
+  // initial capacity.
+  pub fn build(self) -> Result<OpenIndexTable<H>, BuildError> {
+    // Settings go on while the table is still inline, so none of them
+    // rebuilds it.
+    let mut table = OpenIndexTable::with_hasher(self.hasher);
+    if let Some(factor) = self.growth_factor {
+      if factor.is_nan() || factor <= 1.0 {
+        return Err(BuildError::GrowthFactor(factor));
+      }
+      table = table.with_growth_factor(factor);
+    }
+    if let Some(hint) = self.alloc_hint {
+      if let AllocHint::Aligned(align) = hint {
+        if !align.is_power_of_two() {
+          return Err(BuildError::Alignment(align));
+        }
+      }
+      table = table.alloc_hint(hint);
+    }
+    if let Some(policy) = self.growth_policy {
+      table = table.with_growth_policy(policy);
+    }
+    if let Some(entries) = self.max_capacity {
+      table = table.with_max_capacity(entries);
+    }
+    if let Some(bytes) = self.max_memory {
+      match table.entries_within(bytes) {
+        Some(entries) => table = table.with_max_capacity(entries),
+        None => return Err(BuildError::MemoryBudget(bytes)),
+      }
+    }
+    if let Some(limit) = table.size_limit() {
+      if self.capacity > limit {
+        return Err(BuildError::CapacityAboveLimit {
+          capacity: self.capacity,
+          limit,
+        });
+      }
+    }
+    if self.cache_line_buckets {
+      table = table.with_cache_line_buckets();
+    }
+    if self.manual_resize {
+      table = table.with_manual_resize();
+    }
+    if self.insertion_order {
+      table = table.with_insertion_order();
+    }
+    table.reserve(self.capacity);
+    Ok(table)
+  }
+}
+
+#[test]
+fn test_builder_applies_options() {
+  let mut table = OpenIndexTableBuilder::new()
+    .capacity(1000)
+    .max_capacity(1000)
+    .growth_factor(4.0)
+    .insertion_order()
+    .manual_resize()
+    .cache_line_buckets()
+    .alloc_hint(AllocHint::Aligned(256))
+    .build()
+    .unwrap();
+  let allocated = table.memory_bytes();
+  assert_eq!(table.load_factor(), 0.0);
+  for key in (0..1000).rev() {
+    assert_eq!(table.insert_checked(key, key * 2), Ok(()));
+  }
+  assert_eq!(
+    table.insert_checked(1000, 0),
+    Err(CapacityExceeded {
+      requested: 1001,
+      limit: 1000
+    })
+  );
+  // Sized once up front, in insertion order, and the insertion order log is
+  // the only thing that grew.
+  let keys: Vec<u64> = table.iter_ordered().map(|(key, _)| key).collect();
+  assert!(keys.iter().copied().eq((0..1000).rev()));
+  assert!(table.memory_bytes() > allocated);
+  assert!(table.memory_bytes() - allocated < 64 << 10);
+
+  let mut manual = OpenIndexTableBuilder::with_hasher(Scramble)
+    .capacity(100)
+    .manual_resize()
+    .build()
+    .unwrap();
+  let allocated = manual.memory_bytes();
+  let mut key = 0;
+  while !manual.needs_resize() {
+    manual.insert(key, key);
+    key += 1;
+  }
+  assert!(key >= 100);
+  manual.insert(key, key);
+  assert!(manual.load_factor() > 1.0);
+  assert_eq!(manual.memory_bytes(), allocated);
+}
+
+#[test]
+fn test_builder_rejects_bad_combinations() {
+  let build = |builder: OpenIndexTableBuilder| builder.build().err();
+  assert_eq!(
+    build(OpenIndexTableBuilder::new().growth_factor(1.0)),
+    Some(BuildError::GrowthFactor(1.0))
+  );
+  assert_eq!(
+    build(OpenIndexTableBuilder::new().alloc_hint(AllocHint::Aligned(48))),
+    Some(BuildError::Alignment(48))
+  );
+  assert_eq!(
+    build(OpenIndexTableBuilder::new().max_memory(100)),
+    Some(BuildError::MemoryBudget(100))
+  );
+  assert_eq!(
+    build(OpenIndexTableBuilder::new().capacity(11).max_capacity(10)),
+    Some(BuildError::CapacityAboveLimit {
+      capacity: 11,
+      limit: 10
+    })
+  );
+  let budget = OpenIndexTableBuilder::new()
+    .capacity(1 << 20)
+    .growth_policy(GrowthPolicy::DoubleUntil(1 << 16));
+  assert!(matches!(
+    build(budget),
+    Some(BuildError::CapacityAboveLimit { .. })
+  ));
+  assert!(OpenIndexTableBuilder::new()
+    .capacity(100)
+    .max_capacity(100)
+    .max_memory(1 << 20)
+    .hasher(crate::hasher::SipHash64::new(1, 2))
+    .build()
+    .is_ok());
+}
@@ -0,0 +1,115 @@
+// Linear probing shared by the tables storing one key and one value per slot.
+// Slots are addressed by index = 2 * slot, their word offset if keys and
+// values were interleaved, however the implementor lays them out.
+pub(crate) trait LinearProbe {
+  // Twice the slot count minus one; the slot count is a power of two.
+  fn data_mask(&self) -> u64;
+  fn slot_occupied(&self, index: u64) -> bool;
+  // Slot the entry stored at index hashes to.
+  fn home_of(&self, index: u64) -> u64;
+  // Marks the slot at index free.
+  fn vacate(&mut self, index: u64);
+  // Copies the entry stored at from into the slot at to.
+  fn move_slot(&mut self, from: u64, to: u64);
+
+  fn next_slot(&self, index: u64) -> u64 {
+    (index + 2) & self.data_mask()
+  }
+
+  // Walks the probe chain starting at home, returning the first slot
+  // matches accepts and true, or the free slot ending the chain and false.
+  // Panics if every slot is occupied and none matches, which the fill limit
+  // of the implementor rules out unless its size accounting is broken.
+  #[inline]
+  fn probe(&self, home: u64, matches: impl Fn(u64) -> bool) -> (u64, bool) {
+    match self.try_probe(home, matches) {
+      Some(found) => found,
+      None => panic!(
+        "probed all {} slots of a full table without a match",
+        (self.data_mask() + 1) >> 1
+      ),
+    }
+  }
+
+  // Like probe, but returns None once the walk wraps around to home instead
+  // of panicking.
+  #[inline]
+  fn try_probe(&self, home: u64, matches: impl Fn(u64) -> bool) -> Option<(u64, bool)> {
+    let mut index = home;
+    loop {
+      if !self.slot_occupied(index) {
+        return Some((index, false));
+      }
+      if matches(index) {
+        return Some((index, true));
+      }
+      index = self.next_slot(index);
+      if index == home {
+        return None;
+      }
+    }
+  }
+
+  // Frees the slot at index, moving the entries after it back so no probe
+  // chain is broken, then frees the slot left over at the end of the chain.
+  fn unshift(&mut self, index: u64) {
+    let mut current = index;
+    loop {
+      let last = current;
+      current = self.next_slot(current);
+      loop {
+        if !self.slot_occupied(current) {
+          self.vacate(last);
+          return;
+        }
+        let slot = self.home_of(current);
+        if last < current {
+          if last >= slot || slot > current {
+            break;
+          }
+        } else if last >= slot && slot > current {
+          break;
+        }
+        current = self.next_slot(current);
+      }
+      self.move_slot(current, last);
+    }
+  }
+}
+
+// Every slot occupied by key index / 2, as a table whose size accounting
+// went wrong could end up.
+#[cfg(test)]
+struct FullSlots;
+
+#[cfg(test)]
+impl LinearProbe for FullSlots {
+  fn data_mask(&self) -> u64 {
+    15
+  }
+
+  fn slot_occupied(&self, _index: u64) -> bool {
+    true
+  }
+
+  fn home_of(&self, index: u64) -> u64 {
+    index
+  }
+
+  fn vacate(&mut self, _index: u64) {}
+
+  fn move_slot(&mut self, _from: u64, _to: u64) {}
+}
+
+#[test]
+fn test_probe_stops_on_full_table() {
+  assert_eq!(FullSlots.try_probe(6, |index| index == 2), Some((2, true)));
+  assert_eq!(FullSlots.try_probe(6, |_| false), None);
+  assert_eq!(FullSlots.probe(14, |index| index == 0), (0, true));
+}
+
+#[test]
+#[should_panic(expected = "probed all 8 slots of a full table")]
+fn test_probe_panics_on_full_table() {
+  FullSlots.probe(4, |_| false);
+}
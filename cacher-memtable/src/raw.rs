@@ -0,0 +1,139 @@
+// Read and write access to the slot arrays of an OpenIndexTable, for layers
+// that move entries in bulk, e.g. rehashing into files, without going through
+// get and insert. OpenIndexTable::raw_slots and raw_slots_mut hand out the
+// views; slot_count and slot_index_of map keys to slots.
+//
+// Layout contract:
+// - A table has slot_count slots, a power of two. Slot i holds keys()[i] and
+//   values()[i]; a slot only holds an entry if is_occupied(i). Words of free
+//   slots are leftovers and carry no meaning.
+// - Every u64 including 0 is an ordinary key; there is no reserved free key.
+// - A pre-hashed entry, stored through insert_hashed, has is_hashed(i) set and
+//   its hash as key. It never matches the plain API for the same u64.
+// - A heap table keeps each entry between its home slot and the first free
+//   slot after it, wrapping around at the end, and at least one slot free.
+// - An inline table, one that has not allocated yet, has INLINE_CAP slots
+//   whose first len() are occupied, in no particular order.
+#[cfg(test)]
+use crate::open_index_table::OpenIndexTable;
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use std::collections::HashMap;
+
+// Read only view of the slots.
+pub struct RawSlots<'a> {
+  pub(crate) keys: &'a [u64],
+  pub(crate) values: &'a [u64],
+  pub(crate) occupancy: Occupancy<'a>,
+  pub(crate) hashed: &'a [u64],
+}
+
+// Slots that may be rewritten, see OpenIndexTable::raw_slots_mut.
+pub struct RawSlotsMut<'a> {
+  pub(crate) keys: &'a mut [u64],
+  pub(crate) values: &'a mut [u64],
+  pub(crate) occupancy: Occupancy<'a>,
+  pub(crate) hashed: &'a [u64],
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Occupancy<'a> {
+  // Bitset with one bit per slot.
+  Bits(&'a [u64]),
+  // The first n slots are occupied.
+  Prefix(usize),
+}
+
+impl Occupancy<'_> {
+  fn contains(&self, slot: usize) -> bool {
+    match self {
+      Occupancy::Bits(bits) => bits[slot >> 6] & (1 << (slot & 63)) != 0,
+      Occupancy::Prefix(n) => slot < *n,
+    }
+  }
+}
+
+fn bit(bits: &[u64], slot: usize) -> bool {
+  !bits.is_empty() && bits[slot >> 6] & (1 << (slot & 63)) != 0
+}
+
+impl<'a> RawSlots<'a> {
+  pub fn keys(&self) -> &'a [u64] {
+    self.keys
+  }
+
+  pub fn values(&self) -> &'a [u64] {
+    self.values
+  }
+
+  pub fn is_occupied(&self, slot: usize) -> bool {
+    self.occupancy.contains(slot)
+  }
+
+  pub fn is_hashed(&self, slot: usize) -> bool {
+    bit(self.hashed, slot)
+  }
+}
+
+impl RawSlotsMut<'_> {
+  pub fn keys(&mut self) -> &mut [u64] {
+    self.keys
+  }
+
+  pub fn values(&mut self) -> &mut [u64] {
+    self.values
+  }
+
+  pub fn is_occupied(&self, slot: usize) -> bool {
+    self.occupancy.contains(slot)
+  }
+
+  pub fn is_hashed(&self, slot: usize) -> bool {
+    bit(self.hashed, slot)
+  }
+}
+
+#[cfg(test)]
+fn raw_entries(table: &OpenIndexTable) -> HashMap<u64, u64> {
+  let raw = table.raw_slots();
+  assert_eq!(raw.keys().len(), table.slot_count());
+  (0..table.slot_count())
+    .filter(|slot| raw.is_occupied(*slot))
+    .map(|slot| (raw.keys()[slot], raw.values()[slot]))
+    .collect()
+}
+
+#[test]
+fn test_raw_slots_match_iter() {
+  let mut table = OpenIndexTable::new();
+  table.insert(0, 5);
+  table.insert(3, 6);
+  assert_eq!(raw_entries(&table), table.iter().collect());
+  assert_eq!(table.slot_index_of(3), Some(1));
+  for key in 1..2000 {
+    table.insert(key * 31, key);
+  }
+  for key in (1..2000).step_by(3) {
+    table.delete(key * 31);
+  }
+  table.insert_hashed(u64::MAX, 9);
+  assert_eq!(raw_entries(&table), table.iter().collect());
+  let raw = table.raw_slots();
+  let hashed: Vec<usize> = (0..table.slot_count())
+    .filter(|slot| raw.is_occupied(*slot) && raw.is_hashed(*slot))
+    .collect();
+  assert_eq!(hashed.len(), 1);
+  assert_eq!(raw.keys()[hashed[0]], u64::MAX);
+  for key in [0, 31, 62, 93] {
+    let slot = table.slot_index_of(key);
+    assert_eq!(slot.is_some(), table.get(key).is_some());
+    assert!(slot.is_none_or(|slot| raw.keys()[slot] == key && !raw.is_hashed(slot)));
+  }
+  assert_eq!(table.slot_index_of(u64::MAX), None);
+  // Values can be rewritten in place.
+  let slot = table.slot_index_of(62).unwrap();
+  // SAFETY: only a value changes, every key stays in its slot.
+  unsafe { table.raw_slots_mut() }.values()[slot] = 100;
+  assert_eq!(table.get(62), Some(100));
+}
@@ -0,0 +1,362 @@
+use crate::open_index_table::{heap_capacity_for, scramble};
+use alloc::boxed::Box;
+#[cfg(feature = "epoch")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+const EMPTY: u8 = 0;
+const READY: u8 = 1;
+
+// One generation of the table. Only the writer holding the lock stores into
+// it, and only into the generation that is current.
+struct Slots {
+  states: Vec<AtomicU8>,
+  keys: Vec<AtomicU64>,
+  values: Vec<AtomicU64>,
+  mask: u64,
+  cap: u64,
+}
+
+impl Slots {
+  fn new(capacity: u64) -> Slots {
+    let slots = heap_capacity_for(capacity as usize) as u64 >> 1;
+    Slots {
+      states: (0..slots).map(|_| AtomicU8::new(EMPTY)).collect(),
+      keys: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      values: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      mask: slots - 1,
+      cap: capacity,
+    }
+  }
+
+  // Returns the slot holding key and true, or the free slot ending its
+  // probe chain and false.
+  fn find(&self, key: u64) -> (usize, bool) {
+    let mut index = scramble(key) & self.mask;
+    loop {
+      let slot = index as usize;
+      if self.states[slot].load(Ordering::Acquire) == EMPTY {
+        return (slot, false);
+      }
+      if self.keys[slot].load(Ordering::Relaxed) == key {
+        return (slot, true);
+      }
+      index = (index + 1) & self.mask;
+    }
+  }
+
+  // Fills the free slot with key and v, publishing it to readers last.
+  fn occupy(&self, slot: usize, key: u64, v: u64) {
+    self.keys[slot].store(key, Ordering::Relaxed);
+    self.values[slot].store(v, Ordering::Relaxed);
+    self.states[slot].store(READY, Ordering::Release);
+  }
+}
+
+// Table shared between threads that grows without blocking readers. Reads
+// are lock free; writers take a lock and so run one at a time. A writer that
+// has to grow builds the next generation of slots beside the current one,
+// swaps it in, and frees the old one once every reader that may still be
+// walking it has finished. Readers never see a partially moved table, only
+// the old generation or the new one. Keys are never removed.
+//
+// Reclamation follows RCU with two reader counters: a reader registers in
+// the counter of the current epoch's parity, and a writer that swapped
+// generations advances the epoch and waits for the previous parity to
+// drain. Readers arriving meanwhile register in the other counter, so a
+// steady stream of them cannot hold the writer up.
+//
+// With the epoch feature, readers pin a crossbeam-epoch epoch instead and
+// the writer hands the old generation to the epoch collector, so growing
+// never waits for readers at all. Replaced generations are then freed some
+// time later, once the collector has advanced past every reader; try_advance
+// and flush let callers bound the memory they hold.
+pub struct RcuIndexTable {
+  slots: AtomicPtr<Slots>,
+  #[cfg(not(feature = "epoch"))]
+  epoch: AtomicU64,
+  #[cfg(not(feature = "epoch"))]
+  readers: [AtomicUsize; 2],
+  // Generations handed to the epoch collector and not yet freed.
+  #[cfg(feature = "epoch")]
+  retired: Arc<AtomicUsize>,
+  writer: Mutex<()>,
+  size: AtomicU64,
+}
+
+// Keeps the generation loaded during a read alive until dropped.
+#[cfg(not(feature = "epoch"))]
+struct ReadGuard<'a> {
+  readers: &'a AtomicUsize,
+}
+
+#[cfg(not(feature = "epoch"))]
+impl Drop for ReadGuard<'_> {
+  fn drop(&mut self) {
+    self.readers.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl RcuIndexTable {
+  pub fn new() -> RcuIndexTable {
+    RcuIndexTable::with_capacity(8)
+  }
+
+  // Creates a table holding capacity keys before it first grows.
+  pub fn with_capacity(capacity: u64) -> RcuIndexTable {
+    RcuIndexTable {
+      slots: AtomicPtr::new(Box::into_raw(Box::new(Slots::new(capacity.max(1))))),
+      #[cfg(not(feature = "epoch"))]
+      epoch: AtomicU64::new(0),
+      #[cfg(not(feature = "epoch"))]
+      readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+      #[cfg(feature = "epoch")]
+      retired: Arc::new(AtomicUsize::new(0)),
+      writer: Mutex::new(()),
+      size: AtomicU64::new(0),
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.size.load(Ordering::Acquire)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Keys the current generation holds before the table grows.
+  pub fn capacity(&self) -> u64 {
+    let _guard = self.pin();
+    self.current().cap
+  }
+
+  #[cfg(feature = "epoch")]
+  fn pin(&self) -> crossbeam_epoch::Guard {
+    crossbeam_epoch::pin()
+  }
+
+  #[cfg(not(feature = "epoch"))]
+  fn pin(&self) -> ReadGuard<'_> {
+    loop {
+      let epoch = self.epoch.load(Ordering::SeqCst);
+      let readers = &self.readers[(epoch & 1) as usize];
+      readers.fetch_add(1, Ordering::SeqCst);
+      // Registered under an epoch a writer has already moved past, the
+      // writer waiting on that parity may have missed this reader.
+      if self.epoch.load(Ordering::SeqCst) == epoch {
+        return ReadGuard { readers };
+      }
+      readers.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+
+  // The current generation. Callers either hold the guard of pin or the
+  // writer lock, either of which keeps it from being freed.
+  fn current(&self) -> &Slots {
+    // SAFETY: the pointer always comes from Box::into_raw and is only freed
+    // after every reader pinned while it was current has left.
+    unsafe { &*self.slots.load(Ordering::SeqCst) }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    let _guard = self.pin();
+    let slots = self.current();
+    match slots.find(key) {
+      (slot, true) => Some(slots.values[slot].load(Ordering::Acquire)),
+      _ => None,
+    }
+  }
+
+  // Stores v for key, growing the table first if key is new and the table
+  // is full.
+  pub fn insert(&self, key: u64, v: u64) {
+    let _writer = self.writer.lock().unwrap();
+    let mut slots = self.current();
+    let (mut slot, found) = slots.find(key);
+    if found {
+      slots.values[slot].store(v, Ordering::Release);
+      return;
+    }
+    if self.size.load(Ordering::Relaxed) >= slots.cap {
+      slots = self.grow(slots);
+      slot = slots.find(key).0;
+    }
+    slots.occupy(slot, key, v);
+    self.size.fetch_add(1, Ordering::Release);
+  }
+
+  // Replaces the current generation with one twice its capacity. Needs the
+  // writer lock.
+  fn grow(&self, old: &Slots) -> &Slots {
+    let new = Slots::new(old.cap * 2);
+    for slot in 0..old.states.len() {
+      if old.states[slot].load(Ordering::Relaxed) == READY {
+        let key = old.keys[slot].load(Ordering::Relaxed);
+        let index = new.find(key).0;
+        new.occupy(index, key, old.values[slot].load(Ordering::Relaxed));
+      }
+    }
+    let new = Box::into_raw(Box::new(new));
+    let old = self.slots.swap(new, Ordering::SeqCst);
+    self.retire(old);
+    // SAFETY: new is current and only this writer could replace it.
+    unsafe { &*new }
+  }
+
+  // Frees a replaced generation once no reader can be walking it. Readers
+  // pinned from here on load the new generation, so the collector only has
+  // to wait out the ones pinned already.
+  #[cfg(feature = "epoch")]
+  fn retire(&self, old: *mut Slots) {
+    let retired = self.retired.clone();
+    retired.fetch_add(1, Ordering::SeqCst);
+    let guard = crossbeam_epoch::pin();
+    // SAFETY: old came from Box::into_raw and is unreachable for readers
+    // pinned after the swap; the closure owns everything it touches.
+    unsafe {
+      guard.defer_unchecked(move || {
+        drop(Box::from_raw(old));
+        retired.fetch_sub(1, Ordering::SeqCst);
+      });
+    }
+    // Moves the deferred free to the global queue, where any thread's
+    // collection can run it, not only this writer's.
+    guard.flush();
+  }
+
+  #[cfg(not(feature = "epoch"))]
+  fn retire(&self, old: *mut Slots) {
+    // Readers registering from here on load the new generation; wait out
+    // the ones that may still hold the old.
+    let epoch = self.epoch.fetch_add(1, Ordering::SeqCst);
+    while self.readers[(epoch & 1) as usize].load(Ordering::SeqCst) != 0 {
+      std::thread::yield_now();
+    }
+    // SAFETY: old came from Box::into_raw and no reader can reach it anymore.
+    drop(unsafe { Box::from_raw(old) });
+  }
+
+  // Tries once to advance the epoch and free the replaced generations no
+  // reader can reach anymore. Returns how many are still waiting.
+  #[cfg(feature = "epoch")]
+  pub fn try_advance(&self) -> usize {
+    crossbeam_epoch::pin().flush();
+    self.retired.load(Ordering::SeqCst)
+  }
+
+  // Blocks until every replaced generation has been freed, which takes as
+  // long as the readers pinned before the last grow.
+  #[cfg(feature = "epoch")]
+  pub fn flush(&self) {
+    while self.try_advance() > 0 {
+      std::thread::yield_now();
+    }
+  }
+}
+
+impl Default for RcuIndexTable {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Drop for RcuIndexTable {
+  fn drop(&mut self) {
+    // SAFETY: the table is exclusively borrowed, so no reader holds it.
+    drop(unsafe { Box::from_raw(*self.slots.get_mut()) });
+  }
+}
+
+#[test]
+fn test_rcu_table_grows() {
+  let table = RcuIndexTable::new();
+  assert!(table.is_empty());
+  for key in 0..1000 {
+    table.insert(key * 7, key);
+  }
+  table.insert(7, 100);
+  assert_eq!(table.len(), 1000);
+  assert!(table.capacity() >= 1000);
+  for key in 0..1000 {
+    assert_eq!(table.get(key * 7), Some(if key == 1 { 100 } else { key }));
+  }
+  assert_eq!(table.get(1), None);
+}
+
+#[test]
+fn test_rcu_table_readers_during_resizes() {
+  use std::sync::atomic::AtomicBool;
+
+  let table = RcuIndexTable::new();
+  // Keys below published are in the table with value key * 3 + 1.
+  let published = AtomicU64::new(0);
+  let done = AtomicBool::new(false);
+  let value = |key: u64| key * 3 + 1;
+  std::thread::scope(|scope| {
+    for reader in 0..4u64 {
+      let (table, published, done) = (&table, &published, &done);
+      scope.spawn(move || {
+        let mut state = reader + 1;
+        while !done.load(Ordering::Acquire) {
+          let limit = published.load(Ordering::Acquire);
+          if limit == 0 {
+            continue;
+          }
+          state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+          for key in [0, limit - 1, (state >> 33) % limit] {
+            assert_eq!(table.get(key), Some(value(key)));
+          }
+          assert!(table.len() >= limit);
+          assert_eq!(table.get(limit + 1_000_000), None);
+        }
+      });
+    }
+    let capacity = table.capacity();
+    for key in 0..200_000 {
+      table.insert(key, value(key));
+      published.store(key + 1, Ordering::Release);
+    }
+    assert!(table.capacity() >= capacity << 10);
+    done.store(true, Ordering::Release);
+  });
+  assert_eq!(table.len(), 200_000);
+  assert!((0..200_000).all(|key| table.get(key) == Some(value(key))));
+}
+
+#[cfg(feature = "epoch")]
+#[test]
+fn test_rcu_table_epoch_reclamation() {
+  use std::sync::atomic::AtomicBool;
+
+  let table = RcuIndexTable::new();
+  table.insert(0, 0);
+  let pinned = AtomicBool::new(false);
+  let done = AtomicBool::new(false);
+  std::thread::scope(|scope| {
+    // A reader pinned throughout keeps every generation replaced meanwhile
+    // alive, without holding the writer up.
+    let (table, pinned, done) = (&table, &pinned, &done);
+    scope.spawn(move || {
+      let _guard = table.pin();
+      let first = table.current();
+      pinned.store(true, Ordering::Release);
+      while !done.load(Ordering::Acquire) {
+        assert!(first.find(0).1);
+      }
+    });
+    while !pinned.load(Ordering::Acquire) {
+      std::thread::yield_now();
+    }
+    for key in 1..10_000 {
+      table.insert(key, key);
+    }
+    assert!(table.try_advance() > 0);
+    done.store(true, Ordering::Release);
+  });
+  table.flush();
+  assert_eq!(table.try_advance(), 0);
+  assert!((0..10_000).all(|key| table.get(key) == Some(key)));
+}
@@ -0,0 +1,186 @@
+use crate::open_index_table::OpenIndexTable;
+use alloc::vec::Vec;
+
+struct SampledEntry {
+  key: u64,
+  value: u64,
+  last_access: u64,
+}
+
+// Bounded cache approximating LRU the way Redis does: when full, a few random
+// entries are sampled and the least recently accessed of them is evicted.
+// Entries are kept dense so every sampled index is a live entry.
+pub struct SampledEvictionTable {
+  table: OpenIndexTable,
+  entries: Vec<SampledEntry>,
+  capacity: u64,
+  samples: u64,
+  tick: u64,
+  rng: u64,
+}
+
+impl SampledEvictionTable {
+  pub fn new(capacity: u64, samples: u64) -> SampledEvictionTable {
+    assert!(
+      capacity > 0 && samples > 0,
+      "capacity and samples must be non zero"
+    );
+    SampledEvictionTable {
+      table: OpenIndexTable::new(),
+      entries: Vec::new(),
+      capacity,
+      samples,
+      tick: 0,
+      rng: 0x2545F4914F6CDD1D,
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.entries.len() as u64
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn capacity(&self) -> u64 {
+    self.capacity
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.table.get(key).is_some()
+  }
+
+  // Reads the value of key without updating its last access.
+  pub fn peek(&self, key: u64) -> Option<u64> {
+    let index = self.table.get(key)?;
+    Some(self.entries[index as usize].value)
+  }
+
+  fn touch(&mut self, index: u64) {
+    self.tick += 1;
+    self.entries[index as usize].last_access = self.tick;
+  }
+
+  pub fn get(&mut self, key: u64) -> Option<u64> {
+    let index = self.table.get(key)?;
+    self.touch(index);
+    Some(self.entries[index as usize].value)
+  }
+
+  // Inserts or updates key, returning the entry evicted to make room.
+  pub fn insert(&mut self, key: u64, value: u64) -> Option<(u64, u64)> {
+    if let Some(index) = self.table.get(key) {
+      self.entries[index as usize].value = value;
+      self.touch(index);
+      return None;
+    }
+    let evicted = if self.len() >= self.capacity {
+      let victim = self.sample_victim();
+      let entry = &self.entries[victim as usize];
+      let (key, value) = (entry.key, entry.value);
+      self.remove_at(victim);
+      Some((key, value))
+    } else {
+      None
+    };
+    self.entries.push(SampledEntry {
+      key,
+      value,
+      last_access: 0,
+    });
+    let index = self.entries.len() as u64 - 1;
+    self.table.insert(key, index);
+    self.touch(index);
+    evicted
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    let index = self.table.get(key)?;
+    let value = self.entries[index as usize].value;
+    self.remove_at(index);
+    Some(value)
+  }
+
+  fn next_random(&mut self) -> u64 {
+    self.rng ^= self.rng << 13;
+    self.rng ^= self.rng >> 7;
+    self.rng ^= self.rng << 17;
+    self.rng
+  }
+
+  fn sample_victim(&mut self) -> u64 {
+    let len = self.len();
+    let mut victim = self.next_random() % len;
+    for _ in 1..self.samples {
+      let candidate = self.next_random() % len;
+      if self.entries[candidate as usize].last_access < self.entries[victim as usize].last_access {
+        victim = candidate;
+      }
+    }
+    victim
+  }
+
+  fn remove_at(&mut self, index: u64) {
+    let removed = self.entries.swap_remove(index as usize);
+    self.table.delete(removed.key);
+    if let Some(moved) = self.entries.get(index as usize) {
+      self.table.insert(moved.key, index);
+    }
+  }
+}
+
+#[cfg(test)]
+fn hot_set_hits(samples: u64) -> u64 {
+  let mut table = SampledEvictionTable::new(100, samples);
+  let mut hits = 0;
+  let mut cold = 1_000;
+  for round in 0..20_000u64 {
+    let hot = round % 20;
+    if table.get(hot).is_some() {
+      hits += 1;
+    } else {
+      table.insert(hot, hot);
+    }
+    for _ in 0..2 {
+      cold += 1;
+      table.insert(cold, cold);
+    }
+  }
+  assert_eq!(table.len(), 100);
+  hits
+}
+
+#[test]
+fn test_sampled_eviction_keeps_hot_set() {
+  let random = hot_set_hits(1);
+  let sampled = hot_set_hits(5);
+  assert!(
+    sampled > random,
+    "sampled {} hits, random {} hits",
+    sampled,
+    random
+  );
+}
+
+#[test]
+fn test_sampled_eviction_table() {
+  let mut table = SampledEvictionTable::new(3, 3);
+  assert_eq!(table.insert(1, 10), None);
+  assert_eq!(table.insert(2, 20), None);
+  assert_eq!(table.insert(3, 30), None);
+  assert_eq!(table.insert(2, 21), None);
+  assert_eq!(table.get(2), Some(21));
+  assert_eq!(table.delete(1), Some(10));
+  assert_eq!(table.delete(1), None);
+  assert_eq!(table.get(3), Some(30));
+  assert_eq!(table.insert(4, 40), None);
+  let evicted = table.insert(5, 50).unwrap();
+  assert!(!table.contains_key(evicted.0));
+  assert_eq!(table.len(), 3);
+  for key in [2, 3, 4, 5] {
+    if key != evicted.0 {
+      assert_eq!(table.get(key), Some(key * 10 + (key == 2) as u64));
+    }
+  }
+}
@@ -0,0 +1,133 @@
+use crate::lru_table::LruTable;
+
+// Segmented LRU: new entries go to a probationary segment and are promoted to
+// the protected segment on their first hit. Eviction always takes the
+// probation tail, so keys touched only once (e.g. by a scan) never push out
+// the protected hot set. Entries falling out of the protected segment get
+// another chance at the head of probation.
+pub struct SegmentedLruTable {
+  probation: LruTable,
+  protected: LruTable,
+}
+
+impl SegmentedLruTable {
+  pub fn new(probation_capacity: u64, protected_capacity: u64) -> SegmentedLruTable {
+    SegmentedLruTable {
+      probation: LruTable::new(probation_capacity),
+      protected: LruTable::new(protected_capacity),
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.probation.len() + self.protected.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> u64 {
+    self.probation.capacity() + self.protected.capacity()
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.protected.contains_key(key) || self.probation.contains_key(key)
+  }
+
+  // Reads the value of key without promoting it or refreshing its recency.
+  pub fn peek(&self, key: u64) -> Option<u64> {
+    self
+      .protected
+      .peek(key)
+      .or_else(|| self.probation.peek(key))
+  }
+
+  pub fn is_protected(&self, key: u64) -> bool {
+    self.protected.contains_key(key)
+  }
+
+  pub fn get(&mut self, key: u64) -> Option<u64> {
+    if let Some(value) = self.protected.get(key) {
+      return Some(value);
+    }
+    let value = self.probation.delete(key)?;
+    self.promote(key, value);
+    Some(value)
+  }
+
+  // Inserts or updates key, returning the entry evicted to make room.
+  pub fn insert(&mut self, key: u64, value: u64) -> Option<(u64, u64)> {
+    if self.protected.contains_key(key) {
+      return self.protected.insert(key, value);
+    }
+    self.probation.insert(key, value)
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    self
+      .protected
+      .delete(key)
+      .or_else(|| self.probation.delete(key))
+  }
+
+  fn promote(&mut self, key: u64, value: u64) {
+    if let Some((demoted, demoted_value)) = self.protected.insert(key, value) {
+      // Probation just lost the promoted entry, so the demoted one fits.
+      self.probation.insert(demoted, demoted_value);
+    }
+  }
+}
+
+#[test]
+fn test_segmented_lru_promotes_and_demotes() {
+  let mut table = SegmentedLruTable::new(2, 2);
+  assert_eq!(table.insert(1, 10), None);
+  assert_eq!(table.insert(2, 20), None);
+  assert_eq!(table.insert(3, 30), Some((1, 10)));
+  assert_eq!(table.get(2), Some(20));
+  assert!(table.is_protected(2));
+  assert_eq!(table.get(3), Some(30));
+  assert_eq!(table.insert(4, 40), None);
+  // Promoting 4 pushes the least recent protected entry back to probation.
+  assert_eq!(table.get(4), Some(40));
+  assert!(!table.is_protected(2));
+  assert!(table.is_protected(3) && table.is_protected(4));
+  assert_eq!(table.insert(3, 31), None);
+  assert_eq!(table.get(3), Some(31));
+  assert_eq!(table.delete(2), Some(20));
+  assert_eq!(table.delete(2), None);
+  assert_eq!(table.len(), 2);
+}
+
+#[test]
+fn test_segmented_lru_peek_does_not_promote() {
+  let mut table = SegmentedLruTable::new(2, 2);
+  table.insert(1, 10);
+  table.insert(2, 20);
+  for _ in 0..3 {
+    assert_eq!(table.peek(1), Some(10));
+  }
+  assert!(!table.is_protected(1));
+  assert_eq!(table.insert(3, 30), Some((1, 10)));
+  assert_eq!(table.get(2), Some(20));
+  assert_eq!(table.peek(2), Some(20));
+}
+
+#[test]
+fn test_segmented_lru_survives_scan() {
+  let mut table = SegmentedLruTable::new(20, 80);
+  let mut lru = LruTable::new(100);
+  // The hot set is read right after it is written.
+  for key in 0..50 {
+    table.insert(key, key);
+    assert_eq!(table.get(key), Some(key));
+    lru.insert(key, key);
+    assert_eq!(lru.get(key), Some(key));
+  }
+  for key in 1000..11_000 {
+    table.insert(key, key);
+    lru.insert(key, key);
+  }
+  assert!((0..50).all(|key| table.get(key).is_some()));
+  assert!((0..50).all(|key| lru.get(key).is_none()));
+}
@@ -0,0 +1,272 @@
+use crate::op_counters::{OpCounters, ShardCounters};
+use crate::open_index_table::{scramble, OpenIndexTable};
+use alloc::vec::Vec;
+use std::sync::{Mutex, MutexGuard};
+#[cfg(test)]
+use test::Bencher;
+
+// A shard on a cache line of its own, so threads locking neighbouring
+// shards do not contend on the line.
+#[repr(align(64))]
+struct Shard {
+  table: Mutex<OpenIndexTable>,
+  counters: ShardCounters,
+}
+
+// Shard of key among 1 << (64 - shift) shards, taken from the high bits of
+// its hash.
+pub(crate) fn shard_of(key: u64, shift: u32) -> usize {
+  // A shift by 64 is out of range; with one shard every key goes to it.
+  scramble(key).checked_shr(shift).unwrap_or(0) as usize
+}
+
+// Table shared between threads, split into a power of two of OpenIndexTables
+// each behind its own lock. A key's shard comes from the high bits of its
+// hash, while the shard tables pick slots from the low bits, so both spread
+// keys evenly. Operations on keys in different shards run in parallel.
+pub struct ShardedIndexTable {
+  shards: Vec<Shard>,
+  shift: u32,
+}
+
+impl ShardedIndexTable {
+  // Creates a table of shards shards. Panics unless shards is a power of
+  // two.
+  pub fn new(shards: usize) -> ShardedIndexTable {
+    assert!(
+      shards.is_power_of_two(),
+      "shard count must be a power of two"
+    );
+    ShardedIndexTable {
+      shards: (0..shards)
+        .map(|_| {
+          let counters = ShardCounters::default();
+          Shard {
+            table: Mutex::new(counters.table()),
+            counters,
+          }
+        })
+        .collect(),
+      shift: 64 - shards.trailing_zeros(),
+    }
+  }
+
+  pub fn shard_count(&self) -> usize {
+    self.shards.len()
+  }
+
+  fn shard(&self, key: u64) -> (MutexGuard<'_, OpenIndexTable>, &ShardCounters) {
+    let shard = &self.shards[shard_of(key, self.shift)];
+    (shard.table.lock().unwrap(), &shard.counters)
+  }
+
+  // Sum of the shard lengths. Each shard is locked in turn, so with writers
+  // running the total need not match any single moment.
+  pub fn len(&self) -> u64 {
+    self
+      .shards
+      .iter()
+      .map(|shard| shard.table.lock().unwrap().len())
+      .sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    let (table, counters) = self.shard(key);
+    let found = table.get(key);
+    counters.get(found.is_some());
+    found
+  }
+
+  pub fn insert(&self, key: u64, v: u64) {
+    let (mut table, counters) = self.shard(key);
+    counters.insert(&mut table, key, v);
+  }
+
+  pub fn delete(&self, key: u64) -> Option<u64> {
+    let (mut table, counters) = self.shard(key);
+    let deleted = table.delete(key);
+    counters.delete(deleted.is_some());
+    deleted
+  }
+
+  // Walks the entries shard by shard, copying out each shard's entries while
+  // holding only its lock. Writes to shards not yet reached show up, writes
+  // to shards already walked do not.
+  pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+    self
+      .shards
+      .iter()
+      .flat_map(|shard| shard.table.lock().unwrap().iter().collect::<Vec<_>>())
+  }
+
+  // Totals of the operations served so far, summed over the shards. The
+  // counters are read one at a time, so with operations in flight the
+  // snapshot need not match any single moment.
+  pub fn counters(&self) -> OpCounters {
+    let mut total = OpCounters::default();
+    for shard in &self.shards {
+      shard.counters.add_to(&mut total);
+    }
+    total
+  }
+
+  pub fn reset_counters(&self) {
+    for shard in &self.shards {
+      shard.counters.reset();
+    }
+  }
+}
+
+impl Default for ShardedIndexTable {
+  fn default() -> Self {
+    Self::new(16)
+  }
+}
+
+#[test]
+fn test_sharded_table_single_threaded() {
+  for shards in [1, 2, 16] {
+    let table = ShardedIndexTable::new(shards);
+    assert!(table.is_empty());
+    for key in 0..1000 {
+      table.insert(key, key * 2);
+    }
+    assert_eq!(table.delete(3), Some(6));
+    assert_eq!(table.delete(3), None);
+    assert_eq!(table.len(), 999);
+    assert_eq!(table.get(4), Some(8));
+    let mut entries: Vec<(u64, u64)> = table.iter().collect();
+    entries.sort();
+    assert!(entries
+      .into_iter()
+      .eq((0..1000).filter(|key| *key != 3).map(|key| (key, key * 2))));
+  }
+  // Keys do not all land in one shard.
+  let table = ShardedIndexTable::new(16);
+  for key in 0..1000 {
+    table.insert(key, key);
+  }
+  assert!(table
+    .shards
+    .iter()
+    .all(|shard| shard.table.lock().unwrap().len() > 20));
+}
+
+#[test]
+fn test_sharded_table_concurrent_writers() {
+  let table = ShardedIndexTable::new(8);
+  std::thread::scope(|scope| {
+    for thread in 0..8u64 {
+      let table = &table;
+      scope.spawn(move || {
+        // Neighbouring threads overlap on half their keys.
+        let keys = thread * 5000..thread * 5000 + 10_000;
+        for key in keys.clone() {
+          table.insert(key, key + 1);
+        }
+        for key in keys.clone().step_by(3) {
+          table.delete(key);
+        }
+        for key in keys.step_by(3) {
+          table.insert(key, key + 1);
+        }
+      });
+    }
+  });
+  // Every key was last written by an insert of key + 1, whichever thread
+  // ran last.
+  assert_eq!(table.len(), 45_000);
+  assert!((0..45_000).all(|key| table.get(key) == Some(key + 1)));
+  assert_eq!(table.get(45_000), None);
+}
+
+#[test]
+fn test_sharded_table_counters() {
+  let table = ShardedIndexTable::new(4);
+  std::thread::scope(|scope| {
+    for thread in 0..4u64 {
+      let table = &table;
+      scope.spawn(move || {
+        let keys = thread * 1000..(thread + 1) * 1000;
+        for key in keys.clone() {
+          table.insert(key, key);
+        }
+        for key in keys.clone().take(500) {
+          table.insert(key, key + 1);
+        }
+        // 1000 hits, then 500 misses on keys no thread writes.
+        for key in keys.clone().chain(1_000_000..1_000_500) {
+          table.get(key);
+        }
+        // Only the first round of deletes removes anything.
+        for _ in 0..2 {
+          for key in keys.clone().take(250) {
+            table.delete(key);
+          }
+        }
+      });
+    }
+  });
+  let counters = table.counters();
+  assert_eq!(
+    counters,
+    OpCounters {
+      gets: 6000,
+      hits: 4000,
+      misses: 2000,
+      inserts: 6000,
+      overwrites: 2000,
+      deletes: 1000,
+      resizes: counters.resizes,
+    }
+  );
+  // 1000 keys per shard outgrow the initial capacity.
+  assert!(counters.resizes >= 4);
+  table.reset_counters();
+  assert_eq!(table.counters(), OpCounters::default());
+}
+
+// Inserts and reads 2^16 keys split across threads, each thread working on
+// its own range.
+#[cfg(test)]
+fn bench_sharded_insert_get(b: &mut Bencher, threads: u64) {
+  let table = ShardedIndexTable::new(64);
+  let per_thread = (1 << 16) / threads;
+  let mut round = 0;
+  b.iter(|| {
+    round += 1;
+    std::thread::scope(|scope| {
+      for thread in 0..threads {
+        let table = &table;
+        scope.spawn(move || {
+          for key in thread * per_thread..(thread + 1) * per_thread {
+            table.insert(key, round);
+            test::black_box(table.get(key ^ 1));
+          }
+        });
+      }
+    });
+  });
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_sharded_insert_get_1_thread(b: &mut Bencher) {
+  bench_sharded_insert_get(b, 1);
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_sharded_insert_get_4_threads(b: &mut Bencher) {
+  bench_sharded_insert_get(b, 4);
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_sharded_insert_get_16_threads(b: &mut Bencher) {
+  bench_sharded_insert_get(b, 16);
+}
@@ -0,0 +1,212 @@
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "allocator_api")]
+pub use alloc::alloc::Global;
+
+// Transparent huge page size on x86-64 and aarch64 Linux.
+const HUGE_PAGE: usize = 2 << 20;
+// Smallest common page size, in u64s.
+const PAGE_WORDS: usize = 4096 / 8;
+
+// How a table allocates its key and value arrays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocHint {
+  // Aligns arrays spanning at least one huge page to the huge page size and,
+  // on Linux with the libc feature, asks the kernel to back them with huge
+  // pages. Smaller arrays are allocated as usual.
+  HugePages,
+  // Aligns the arrays to this many bytes, a power of two.
+  Aligned(usize),
+}
+
+// Allocators a table can keep its arrays in. With the allocator_api feature,
+// which needs a nightly compiler, that is any cloneable Allocator; without
+// it only Global.
+#[cfg(feature = "allocator_api")]
+pub trait SlotAllocator: alloc::alloc::Allocator + Clone {}
+
+#[cfg(feature = "allocator_api")]
+impl<A: alloc::alloc::Allocator + Clone> SlotAllocator for A {}
+
+#[cfg(not(feature = "allocator_api"))]
+pub trait SlotAllocator: Clone + sealed::Sealed {}
+
+#[cfg(not(feature = "allocator_api"))]
+mod sealed {
+  pub trait Sealed {}
+}
+
+// The global allocator, standing in for alloc::alloc::Global without the
+// allocator_api feature.
+#[cfg(not(feature = "allocator_api"))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Global;
+
+#[cfg(not(feature = "allocator_api"))]
+impl sealed::Sealed for Global {}
+
+#[cfg(not(feature = "allocator_api"))]
+impl SlotAllocator for Global {}
+
+fn alignment(len: usize, hint: Option<AllocHint>) -> usize {
+  let align = match hint {
+    Some(AllocHint::HugePages) if len.saturating_mul(8) >= HUGE_PAGE => HUGE_PAGE,
+    Some(AllocHint::Aligned(align)) => align,
+    _ => 0,
+  };
+  align.max(core::mem::align_of::<u64>())
+}
+
+// The error try_zeroed returns for an array no allocation can hold.
+pub(crate) fn capacity_overflow() -> TryReserveError {
+  Vec::<u64>::new().try_reserve_exact(usize::MAX).unwrap_err()
+}
+
+// Number of u64s allocated for an array of len, including the padding.
+pub(crate) fn allocated_len(len: usize, hint: Option<AllocHint>) -> usize {
+  len.saturating_add(alignment(len, hint) / core::mem::size_of::<u64>() - 1)
+}
+
+// Zeroed u64 array starting at the alignment an AllocHint asks for. The Vec
+// is over-allocated by up to one alignment and the array starts at its first
+// aligned element, so allocating and freeing stay plain Vec operations.
+pub(crate) struct SlotArray<A: SlotAllocator = Global> {
+  #[cfg(feature = "allocator_api")]
+  buf: Vec<u64, A>,
+  #[cfg(not(feature = "allocator_api"))]
+  buf: Vec<u64>,
+  #[cfg(not(feature = "allocator_api"))]
+  alloc: A,
+  offset: usize,
+}
+
+impl<A: SlotAllocator> SlotArray<A> {
+  #[cfg(feature = "allocator_api")]
+  pub(crate) fn new_in(alloc: A) -> SlotArray<A> {
+    SlotArray {
+      buf: Vec::new_in(alloc),
+      offset: 0,
+    }
+  }
+
+  #[cfg(not(feature = "allocator_api"))]
+  pub(crate) fn new_in(alloc: A) -> SlotArray<A> {
+    SlotArray {
+      buf: Vec::new(),
+      alloc,
+      offset: 0,
+    }
+  }
+
+  // Aborts if the allocation fails, like vec!.
+  pub(crate) fn zeroed(len: usize, hint: Option<AllocHint>, alloc: A) -> SlotArray<A> {
+    // vec! gets zeroed memory straight from the global allocator. Vec has no
+    // such constructor for other allocators, which zero the array instead.
+    #[cfg(not(feature = "allocator_api"))]
+    if alignment(len, hint) == core::mem::align_of::<u64>() {
+      return SlotArray {
+        buf: alloc::vec::from_elem(0, len),
+        alloc,
+        offset: 0,
+      };
+    }
+    SlotArray::try_zeroed(len, hint, alloc).expect("failed to allocate the slot array")
+  }
+
+  pub(crate) fn try_zeroed(
+    len: usize,
+    hint: Option<AllocHint>,
+    alloc: A,
+  ) -> Result<SlotArray<A>, TryReserveError> {
+    let align = alignment(len, hint);
+    let mut array = SlotArray::new_in(alloc);
+    array.buf.try_reserve_exact(allocated_len(len, hint))?;
+    array.offset = array.buf.as_ptr().align_offset(align);
+    if align == HUGE_PAGE {
+      // Before zeroing, so the pages are faulted in huge.
+      advise_huge_pages(array.buf.as_ptr().wrapping_add(array.offset), len * 8);
+    }
+    array.buf.resize(array.offset + len, 0);
+    Ok(array)
+  }
+
+  pub(crate) fn from_slice(words: &[u64], hint: Option<AllocHint>, alloc: A) -> SlotArray<A> {
+    let mut array = SlotArray::zeroed(words.len(), hint, alloc);
+    array.copy_from_slice(words);
+    array
+  }
+
+  #[cfg(feature = "allocator_api")]
+  pub(crate) fn allocator(&self) -> &A {
+    self.buf.allocator()
+  }
+
+  #[cfg(not(feature = "allocator_api"))]
+  pub(crate) fn allocator(&self) -> &A {
+    &self.alloc
+  }
+
+  // Writes one word per page, so the whole array is backed by memory now
+  // instead of page faulting on first access.
+  pub(crate) fn prefault(&mut self) {
+    for word in self.iter_mut().step_by(PAGE_WORDS) {
+      // A plain store of the zero already there may be optimized away.
+      // SAFETY: word is a valid reference into the array.
+      unsafe { core::ptr::write_volatile(word, 0) };
+    }
+  }
+
+  // Allocated size in u64s, including the alignment padding.
+  pub(crate) fn capacity(&self) -> usize {
+    self.buf.capacity()
+  }
+}
+
+impl<A: SlotAllocator> Deref for SlotArray<A> {
+  type Target = [u64];
+
+  fn deref(&self) -> &[u64] {
+    &self.buf[self.offset..]
+  }
+}
+
+impl<A: SlotAllocator> DerefMut for SlotArray<A> {
+  fn deref_mut(&mut self) -> &mut [u64] {
+    &mut self.buf[self.offset..]
+  }
+}
+
+#[cfg(all(feature = "libc", target_os = "linux"))]
+fn advise_huge_pages(start: *const u64, bytes: usize) {
+  // The advice is only a hint; without it the array just uses small pages.
+  // SAFETY: start is huge page aligned and the range lies within the
+  // allocation; MADV_HUGEPAGE changes no memory contents.
+  unsafe {
+    libc::madvise(start as *mut libc::c_void, bytes, libc::MADV_HUGEPAGE);
+  }
+}
+
+#[cfg(not(all(feature = "libc", target_os = "linux")))]
+fn advise_huge_pages(_start: *const u64, _bytes: usize) {}
+
+#[test]
+fn test_slot_array_alignment() {
+  for (len, hint, align) in [
+    (100, Some(AllocHint::Aligned(64)), 64),
+    (3, Some(AllocHint::Aligned(4096)), 4096),
+    (1 << 18, Some(AllocHint::HugePages), HUGE_PAGE),
+    (100, None, 8),
+  ] {
+    let mut array = SlotArray::zeroed(len, hint, Global);
+    assert!((array.as_ptr() as usize).is_multiple_of(align));
+    assert_eq!(array.len(), len);
+    assert!(array.iter().all(|word| *word == 0));
+    array[len - 1] = 7;
+    let copy = SlotArray::from_slice(&array, hint, Global);
+    assert!((copy.as_ptr() as usize).is_multiple_of(align));
+    assert_eq!(copy.len(), len);
+    assert_eq!(copy[len - 1], 7);
+  }
+}
@@ -0,0 +1,567 @@
+use crate::open_index_table::{heap_capacity_for, scramble};
+use crate::sync::{fence, yield_now, Arc, AtomicPtr, AtomicU64, Lifetime, Mutex};
+#[cfg(test)]
+use crate::sync_index_table::SyncIndexTable;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+use core::sync::atomic::Ordering;
+#[cfg(test)]
+use test::Bencher;
+
+// Keys marking a slot that never held an entry and one whose entry was
+// deleted. Neither can be inserted.
+const EMPTY: u64 = u64::MAX;
+const TOMBSTONE: u64 = u64::MAX - 1;
+// A reader not inside a get.
+const QUIESCENT: u64 = u64::MAX;
+
+// One generation of the table. Readers probe it while the writer stores
+// into it, so every word is atomic.
+struct Slots {
+  keys: Vec<AtomicU64>,
+  values: Vec<AtomicU64>,
+  mask: u64,
+  // Ends once the slots are retired and no reader can be probing them.
+  lifetime: Lifetime,
+}
+
+impl Slots {
+  fn new(slots: u64) -> Slots {
+    Slots {
+      keys: (0..slots).map(|_| AtomicU64::new(EMPTY)).collect(),
+      values: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      mask: slots - 1,
+      lifetime: Lifetime::new(),
+    }
+  }
+
+  // Keys, live or deleted, the generation holds before it is rebuilt. Below
+  // the slot count, so every probe ends at an empty slot.
+  fn cap(&self) -> u64 {
+    (self.mask + 1) / 8 * 7
+  }
+
+  // Returns the slot holding key and true, or the empty slot ending its
+  // probe chain and false.
+  fn find(&self, key: u64) -> (usize, bool) {
+    let mut index = scramble(key) & self.mask;
+    loop {
+      match self.keys[index as usize].load(Ordering::Acquire) {
+        EMPTY => return (index as usize, false),
+        stored if stored == key => return (index as usize, true),
+        _ => index = (index + 1) & self.mask,
+      }
+    }
+  }
+
+  fn get(&self, key: u64) -> Option<u64> {
+    if key >= TOMBSTONE {
+      return None;
+    }
+    match self.find(key) {
+      // The key was stored with release after the value, so the value is
+      // at least as new as the key.
+      (slot, true) => Some(self.values[slot].load(Ordering::Acquire)),
+      _ => None,
+    }
+  }
+
+  // Fills the empty slot, the value first so no reader finds the key
+  // without it.
+  fn occupy(&self, slot: usize, key: u64, v: u64) {
+    self.values[slot].store(v, Ordering::Relaxed);
+    self.keys[slot].store(key, Ordering::Release);
+  }
+}
+
+// State the writer and every reader share.
+struct Shared {
+  slots: AtomicPtr<Slots>,
+  epoch: AtomicU64,
+  // The epoch each reader announced for the get it is in, or QUIESCENT.
+  readers: Mutex<Vec<Arc<AtomicU64>>>,
+}
+
+impl Drop for Shared {
+  fn drop(&mut self) {
+    // SAFETY: the pointer came from Box::into_raw and no handle is left.
+    drop(unsafe { Box::from_raw(self.slots.load(Ordering::Relaxed)) });
+  }
+}
+
+// Table with exactly one writer and any number of readers that probe the
+// live slots directly, without locks or retries. The writer is this type,
+// which is not Clone and writes through &mut self, so a second writer does
+// not type check; reader hands out SpmcReader handles for other threads.
+//
+// Entries are published value first, key last, with release ordering, so a
+// reader that finds a key also sees its value. Deletes only overwrite the
+// key with a tombstone: moving entries back as OpenIndexTable does could
+// carry an entry past a reader probing for it. Tombstones are never reused
+// either, or a reader that matched a deleted key could read the value of
+// the entry that replaced it; they are dropped when the writer rebuilds the
+// slots, which is also how the table grows. A rebuild publishes the new
+// slots through an atomic pointer and frees the old ones once every reader
+// that may still be probing them has finished its get.
+//
+// Keys u64::MAX - 1 and u64::MAX are reserved and cannot be inserted.
+pub struct SpmcIndexTable {
+  shared: Arc<Shared>,
+  len: u64,
+  // Live entries plus tombstones in the current slots.
+  used: u64,
+  // Replaced generations with the epoch they were retired in.
+  retired: Vec<(u64, NonNull<Slots>)>,
+}
+
+// SAFETY: the retired pointers are owned by the writer alone, and Slots is
+// made of atomics.
+unsafe impl Send for SpmcIndexTable {}
+
+// Handle for looking up entries from another thread. Cheap to clone; each
+// clone registers a reader of its own. Not Sync, since a reader announces
+// one get at a time: share clones between threads, not references.
+pub struct SpmcReader {
+  shared: Arc<Shared>,
+  active: Arc<AtomicU64>,
+  _not_sync: PhantomData<Cell<()>>,
+}
+
+impl SpmcIndexTable {
+  // Creates a table holding capacity entries before its first rebuild.
+  pub fn new(capacity: u64) -> SpmcIndexTable {
+    let slots = heap_capacity_for(capacity.max(1) as usize) as u64 >> 1;
+    SpmcIndexTable {
+      shared: Arc::new(Shared {
+        slots: AtomicPtr::new(Box::into_raw(Box::new(Slots::new(slots)))),
+        epoch: AtomicU64::new(0),
+        readers: Mutex::new(Vec::new()),
+      }),
+      len: 0,
+      used: 0,
+      retired: Vec::new(),
+    }
+  }
+
+  pub fn reader(&self) -> SpmcReader {
+    SpmcReader::register(self.shared.clone())
+  }
+
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  // Entries the current slots hold before a rebuild.
+  pub fn capacity(&self) -> u64 {
+    self.current().cap()
+  }
+
+  fn current(&self) -> &Slots {
+    // SAFETY: only the writer replaces or frees the current slots, and it
+    // is borrowed here.
+    unsafe { &*self.shared.slots.load(Ordering::Relaxed) }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    self.current().get(key)
+  }
+
+  // Stores v for key. Panics if key is u64::MAX - 1 or u64::MAX.
+  pub fn insert(&mut self, key: u64, v: u64) {
+    assert!(
+      key < TOMBSTONE,
+      "keys u64::MAX - 1 and u64::MAX are reserved"
+    );
+    self.reclaim();
+    let (slot, found) = self.current().find(key);
+    if found {
+      self.current().values[slot].store(v, Ordering::Release);
+      return;
+    }
+    let slot = if self.used >= self.current().cap() {
+      self.rebuild();
+      self.current().find(key).0
+    } else {
+      slot
+    };
+    self.current().occupy(slot, key, v);
+    self.len += 1;
+    self.used += 1;
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    if key >= TOMBSTONE {
+      return None;
+    }
+    self.reclaim();
+    match self.current().find(key) {
+      (slot, true) => {
+        let slots = self.current();
+        let v = slots.values[slot].load(Ordering::Relaxed);
+        slots.keys[slot].store(TOMBSTONE, Ordering::Release);
+        self.len -= 1;
+        Some(v)
+      }
+      _ => None,
+    }
+  }
+
+  // Copies the live entries into new slots, twice as many unless
+  // tombstones took up at least half of the old ones, publishes them and
+  // retires the old ones.
+  fn rebuild(&mut self) {
+    let old = self.current();
+    let slots = if self.len >= old.cap() / 2 {
+      (old.mask + 1) * 2
+    } else {
+      old.mask + 1
+    };
+    let new = Slots::new(slots);
+    for (key, value) in old.keys.iter().zip(&old.values) {
+      let key = key.load(Ordering::Relaxed);
+      if key < TOMBSTONE {
+        let slot = new.find(key).0;
+        new.occupy(slot, key, value.load(Ordering::Relaxed));
+      }
+    }
+    self.used = self.len;
+    let new = Box::into_raw(Box::new(new));
+    let old = self.shared.slots.swap(new, Ordering::AcqRel);
+    let epoch = self.shared.epoch.fetch_add(1, Ordering::Release);
+    // SAFETY: old came from Box::into_raw, so it is not null.
+    self
+      .retired
+      .push((epoch, unsafe { NonNull::new_unchecked(old) }));
+  }
+
+  // Frees the retired slots no reader can still be probing. A reader that
+  // announced an epoch after the one a generation was retired in loaded the
+  // slots pointer after the swap; one that announced the same epoch or an
+  // earlier one may hold the old pointer. The fence pairs with the one in
+  // SpmcReader::get: a reader whose announcement this scan misses loads the
+  // pointer after the swap, so it gets the new slots.
+  fn reclaim(&mut self) {
+    if self.retired.is_empty() {
+      return;
+    }
+    fence(Ordering::SeqCst);
+    let oldest_active = {
+      let mut readers = self.shared.readers.lock().unwrap();
+      // Handles only this list still holds have been dropped.
+      readers.retain(|active| Arc::strong_count(active) > 1);
+      readers
+        .iter()
+        .map(|active| active.load(Ordering::Acquire))
+        .min()
+        .unwrap_or(QUIESCENT)
+    };
+    self.retired.retain(|(epoch, slots)| {
+      if *epoch < oldest_active {
+        // SAFETY: from Box::into_raw and unreachable for every reader.
+        unsafe { free(*slots) };
+        false
+      } else {
+        true
+      }
+    });
+  }
+
+  // Waits for every reader still inside a get on retired slots and frees
+  // them.
+  fn synchronize(&mut self) {
+    while !self.retired.is_empty() {
+      self.reclaim();
+      yield_now();
+    }
+  }
+}
+
+// Frees retired slots. Under loom they are only marked freed, which fails
+// the model if a reader may still be probing them, and leaked, so a failing
+// run reads no freed memory.
+unsafe fn free(slots: NonNull<Slots>) {
+  slots.as_ref().lifetime.end();
+  #[cfg(not(all(loom, test)))]
+  drop(Box::from_raw(slots.as_ptr()));
+}
+
+impl Drop for SpmcIndexTable {
+  fn drop(&mut self) {
+    self.synchronize();
+  }
+}
+
+impl SpmcReader {
+  fn register(shared: Arc<Shared>) -> SpmcReader {
+    let active = Arc::new(AtomicU64::new(QUIESCENT));
+    shared.readers.lock().unwrap().push(active.clone());
+    SpmcReader {
+      shared,
+      active,
+      _not_sync: PhantomData,
+    }
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    let epoch = self.shared.epoch.load(Ordering::Acquire);
+    self.active.store(epoch, Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+    // SAFETY: slots retired at epoch or later are kept until this reader
+    // goes quiescent again, see SpmcIndexTable::reclaim.
+    let slots = unsafe { &*self.shared.slots.load(Ordering::Acquire) };
+    let found = slots.lifetime.probe(|| slots.get(key));
+    self.active.store(QUIESCENT, Ordering::Release);
+    found
+  }
+}
+
+impl Clone for SpmcReader {
+  fn clone(&self) -> Self {
+    SpmcReader::register(self.shared.clone())
+  }
+}
+
+impl Drop for SpmcReader {
+  fn drop(&mut self) {
+    self.active.store(QUIESCENT, Ordering::Release);
+  }
+}
+
+#[test]
+#[cfg_attr(loom, ignore)]
+fn test_spmc_table_single_threaded() {
+  let mut table = SpmcIndexTable::new(16);
+  let reader = table.reader();
+  assert!(table.is_empty());
+  for key in 0..1000 {
+    table.insert(key, key + 1);
+  }
+  assert!(table.capacity() >= 1000);
+  table.insert(3, 7);
+  assert_eq!(table.delete(4), Some(5));
+  assert_eq!(table.delete(4), None);
+  assert_eq!(table.len(), 999);
+  for key in 0..1000 {
+    let expected = match key {
+      3 => Some(7),
+      4 => None,
+      _ => Some(key + 1),
+    };
+    assert_eq!(reader.get(key), expected);
+    assert_eq!(table.get(key), expected);
+  }
+  assert_eq!(reader.get(TOMBSTONE), None);
+  assert_eq!(reader.get(EMPTY), None);
+  // Churn fills the slots with tombstones. The first rebuild doubles them,
+  // since the live entries are over half the capacity; after that,
+  // rebuilds only drop tombstones.
+  let churn = |table: &mut SpmcIndexTable, rounds: u64| {
+    for round in 0..rounds {
+      table.insert(10_000 + round, round);
+      table.delete(10_000 + round);
+    }
+  };
+  let capacity = table.capacity();
+  churn(&mut table, capacity);
+  assert_eq!(table.capacity(), capacity * 2);
+  churn(&mut table, capacity * 8);
+  assert_eq!(table.capacity(), capacity * 2);
+  assert_eq!(table.len(), 999);
+  assert_eq!(reader.clone().get(999), Some(1000));
+  // No reader is inside a get, so nothing retired is kept.
+  table.insert(1, 1);
+  assert!(table.retired.is_empty());
+}
+
+#[test]
+fn test_spmc_handles_are_send() {
+  fn assert_send<T: Send>() {}
+  assert_send::<SpmcIndexTable>();
+  assert_send::<SpmcReader>();
+}
+
+#[test]
+#[cfg_attr(loom, ignore)]
+#[should_panic(expected = "keys u64::MAX - 1 and u64::MAX are reserved")]
+fn test_spmc_table_reserved_key() {
+  SpmcIndexTable::new(1).insert(u64::MAX - 1, 0);
+}
+
+#[test]
+#[cfg_attr(loom, ignore)]
+fn test_spmc_table_publication() {
+  use std::sync::atomic::AtomicBool;
+
+  // Values carry their key in the high half, so a reader finding a key
+  // without its value, or with another key's, fails.
+  let value = |key: u64, round: u64| key << 32 | round;
+  const KEYS: u64 = 20_000;
+  let mut table = SpmcIndexTable::new(8);
+  // Keys below published are inserted and multiples of 3 among them are
+  // never deleted.
+  let published = AtomicU64::new(0);
+  let done = AtomicBool::new(false);
+  std::thread::scope(|scope| {
+    for thread in 0..4u64 {
+      let (reader, published, done) = (table.reader(), &published, &done);
+      scope.spawn(move || {
+        let mut key = thread;
+        while !done.load(Ordering::Acquire) {
+          let limit = published.load(Ordering::Acquire);
+          key = (key + 7) % KEYS;
+          match reader.get(key) {
+            Some(v) => assert_eq!(v >> 32, key),
+            None => assert!(key >= limit || key % 3 != 0),
+          }
+        }
+      });
+    }
+    for key in 0..KEYS {
+      table.insert(key, value(key, 0));
+      if key % 3 != 0 {
+        table.insert(key, value(key, 1));
+        if key % 3 == 1 {
+          table.delete(key);
+        }
+      }
+      published.store(key + 1, Ordering::Release);
+    }
+    done.store(true, Ordering::Release);
+  });
+  assert_eq!(
+    table.len(),
+    (0..KEYS).filter(|key| key % 3 != 1).count() as u64
+  );
+  let reader = table.reader();
+  for key in 0..KEYS {
+    let expected = match key % 3 {
+      0 => Some(value(key, 0)),
+      1 => None,
+      _ => Some(value(key, 1)),
+    };
+    assert_eq!(reader.get(key), expected);
+  }
+  table.insert(KEYS, 0);
+  assert!(table.retired.is_empty());
+}
+
+// The loom tests run the writer on the model's main thread against one
+// reader, see the sync module for how to run them.
+#[cfg(loom)]
+#[test]
+fn test_spmc_loom_publication() {
+  loom::model(|| {
+    let mut table = SpmcIndexTable::new(1);
+    table.insert(1, 10);
+    let reader = table.reader();
+    let read = loom::thread::spawn(move || (reader.get(1), reader.get(2)));
+    table.insert(2, 20);
+    table.insert(1, 11);
+    // A key is never found without its value.
+    let (one, two) = read.join().unwrap();
+    assert!(matches!(one, Some(10 | 11)), "{one:?}");
+    assert!(matches!(two, None | Some(20)), "{two:?}");
+  });
+}
+
+#[cfg(loom)]
+#[test]
+fn test_spmc_loom_reclaim() {
+  loom::model(|| {
+    let mut table = SpmcIndexTable::new(1);
+    let capacity = table.capacity();
+    for key in 0..capacity {
+      table.insert(key, key + 1);
+    }
+    let reader = table.reader();
+    let read = loom::thread::spawn(move || reader.get(0));
+    // The first insert rebuilds, retiring the slots the reader may be
+    // probing, and the second frees them unless the reader announced an
+    // epoch that may still see them.
+    table.insert(capacity, 0);
+    table.insert(capacity + 1, 0);
+    assert_eq!(read.join().unwrap(), Some(1));
+    table.insert(capacity + 2, 0);
+    assert!(table.retired.is_empty());
+  });
+}
+
+#[cfg(loom)]
+#[test]
+fn test_spmc_loom_tombstone() {
+  loom::model(|| {
+    let mut table = SpmcIndexTable::new(1);
+    let mask = table.current().mask;
+    let collision = (2..)
+      .find(|key| scramble(*key) & mask == scramble(1) & mask)
+      .unwrap();
+    table.insert(1, 10);
+    let reader = table.reader();
+    let read = loom::thread::spawn(move || reader.get(1));
+    // A reader that matched the deleted key must not read the value of an
+    // entry reusing its slot.
+    table.delete(1);
+    table.insert(collision, 20);
+    table.insert(1, 11);
+    let one = read.join().unwrap();
+    assert!(matches!(one, None | Some(10 | 11)), "{one:?}");
+  });
+}
+
+// Reads from one thread while another keeps overwriting entries, through
+// the lock free readers or through SyncIndexTable's shared locks.
+#[cfg(test)]
+fn bench_get_with_writer(
+  b: &mut Bencher,
+  get: impl Fn(u64) -> Option<u64>,
+  write: impl Fn(u64) + Sync,
+) {
+  use std::sync::atomic::AtomicBool;
+
+  let done = AtomicBool::new(false);
+  std::thread::scope(|scope| {
+    scope.spawn(|| {
+      let mut key = 0;
+      while !done.load(Ordering::Relaxed) {
+        write(key);
+        key = (key + 1) & 0xFFFF;
+      }
+    });
+    b.iter(|| (0..1 << 14).filter_map(&get).sum::<u64>());
+    done.store(true, Ordering::Relaxed);
+  });
+}
+
+#[cfg(test)]
+#[bench]
+#[cfg_attr(loom, ignore)]
+fn bench_spmc_get_with_writer(b: &mut Bencher) {
+  let mut table = SpmcIndexTable::new(1 << 16);
+  for key in 0..1 << 16 {
+    table.insert(key, key);
+  }
+  let reader = table.reader();
+  let writer = Mutex::new(table);
+  bench_get_with_writer(
+    b,
+    |key| reader.get(key),
+    |key| writer.lock().unwrap().insert(key, key),
+  );
+}
+
+#[cfg(test)]
+#[bench]
+fn bench_sync_get_with_writer(b: &mut Bencher) {
+  let table = SyncIndexTable::new(16);
+  for key in 0..1 << 16 {
+    table.insert(key, key);
+  }
+  bench_get_with_writer(b, |key| table.get(key), |key| table.insert(key, key));
+}
@@ -0,0 +1,53 @@
+// The atomics and locks SpmcIndexTable is built on. They come from loom when
+// the tests are compiled with --cfg loom, so the loom tests can check every
+// interleaving of the writer with its readers, and from core and std
+// otherwise. Run the model with
+//
+//   RUSTFLAGS="--cfg loom" cargo test -p cacher-memtable --release spmc_loom
+#[cfg(all(loom, test))]
+pub(crate) use loom::sync::atomic::{fence, AtomicPtr, AtomicU64};
+#[cfg(all(loom, test))]
+pub(crate) use loom::sync::{Arc, Mutex};
+#[cfg(all(loom, test))]
+pub(crate) use loom::thread::yield_now;
+
+#[cfg(not(all(loom, test)))]
+pub(crate) use alloc::sync::Arc;
+#[cfg(not(all(loom, test)))]
+pub(crate) use core::sync::atomic::{fence, AtomicPtr, AtomicU64};
+#[cfg(not(all(loom, test)))]
+pub(crate) use std::sync::Mutex;
+#[cfg(not(all(loom, test)))]
+pub(crate) use std::thread::yield_now;
+
+// Tracks reads of memory that is freed while other threads may still reach
+// it. Under loom, ending the lifetime fails the model while a probe is
+// running or when a probe is not ordered before it; otherwise it compiles
+// to nothing.
+pub(crate) struct Lifetime {
+  #[cfg(all(loom, test))]
+  cell: loom::cell::UnsafeCell<()>,
+}
+
+impl Lifetime {
+  pub(crate) fn new() -> Lifetime {
+    Lifetime {
+      #[cfg(all(loom, test))]
+      cell: loom::cell::UnsafeCell::new(()),
+    }
+  }
+
+  // Runs probe as a read of the memory.
+  pub(crate) fn probe<R>(&self, probe: impl FnOnce() -> R) -> R {
+    #[cfg(all(loom, test))]
+    return self.cell.with(|_| probe());
+    #[cfg(not(all(loom, test)))]
+    probe()
+  }
+
+  // Marks the memory freed.
+  pub(crate) fn end(&self) {
+    #[cfg(all(loom, test))]
+    self.cell.with_mut(|_| ());
+  }
+}
@@ -0,0 +1,771 @@
+use crate::op_counters::{OpCounters, ShardCounters};
+use crate::open_index_table::{scramble, OpenIndexTable};
+use crate::probing::LinearProbe;
+use crate::sharded_index_table::shard_of;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(not(feature = "epoch"))]
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockWriteGuard};
+
+// Slots of the first mirror generation of a shard.
+const MIRROR_SLOTS: u64 = 16;
+// Optimistic reads tried before get_lockfree falls back to the shard lock.
+const OPTIMISTIC_TRIES: usize = 8;
+
+// One generation of a shard's mirror: a linear probing table of atomics,
+// addressed like OpenIndexTable with index = 2 * slot. Readers may load any
+// of it at any time, so every word is atomic, even though only a writer
+// holding the shard's write lock stores into it.
+struct Mirror {
+  keys: Vec<AtomicU64>,
+  values: Vec<AtomicU64>,
+  occupied: Vec<AtomicBool>,
+  len: AtomicU64,
+}
+
+impl Mirror {
+  fn new(slots: u64) -> Mirror {
+    Mirror {
+      keys: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      values: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+      occupied: (0..slots).map(|_| AtomicBool::new(false)).collect(),
+      len: AtomicU64::new(0),
+    }
+  }
+
+  fn slots(&self) -> u64 {
+    self.keys.len() as u64
+  }
+
+  fn home(&self, key: u64) -> u64 {
+    (scramble(key) & (self.slots() - 1)) << 1
+  }
+
+  fn key(&self, index: u64) -> u64 {
+    self.keys[(index >> 1) as usize].load(Ordering::Relaxed)
+  }
+
+  // Probes for key without any lock. Under a concurrent write the result
+  // may be garbage, which the seqlock catches; the walk is bounded because a
+  // torn view need not have a free slot to end it.
+  fn read(&self, key: u64) -> Option<u64> {
+    let mut index = self.home(key);
+    for _ in 0..self.slots() {
+      if !self.slot_occupied(index) {
+        return None;
+      }
+      if self.key(index) == key {
+        return Some(self.values[(index >> 1) as usize].load(Ordering::Relaxed));
+      }
+      index = self.next_slot(index);
+    }
+    None
+  }
+
+  // Stores into the slot at index, which must be free.
+  fn occupy(&self, index: u64, key: u64, v: u64) {
+    let slot = (index >> 1) as usize;
+    self.keys[slot].store(key, Ordering::Relaxed);
+    self.values[slot].store(v, Ordering::Relaxed);
+    self.occupied[slot].store(true, Ordering::Relaxed);
+  }
+}
+
+// On &Mirror rather than Mirror: unshift moves slots through &mut self,
+// which readers holding &Mirror rule out for the Mirror itself, and every
+// store goes through atomics, so a shared reference is all a writer needs.
+impl LinearProbe for &Mirror {
+  fn data_mask(&self) -> u64 {
+    self.slots() * 2 - 1
+  }
+
+  fn slot_occupied(&self, index: u64) -> bool {
+    self.occupied[(index >> 1) as usize].load(Ordering::Relaxed)
+  }
+
+  fn home_of(&self, index: u64) -> u64 {
+    self.home(self.key(index))
+  }
+
+  fn vacate(&mut self, index: u64) {
+    self.occupied[(index >> 1) as usize].store(false, Ordering::Relaxed);
+  }
+
+  fn move_slot(&mut self, from: u64, to: u64) {
+    let value = self.values[(from >> 1) as usize].load(Ordering::Relaxed);
+    self.occupy(to, self.key(from), value);
+  }
+}
+
+// Copy of a shard's entries that readers probe without taking any lock.
+// Writers make the sequence odd for the length of a write and even again
+// after it; a reader that saw the same even sequence before and after its
+// probe knows no write overlapped it. Growing swaps in a new generation
+// and frees the old one once no reader can still be walking it, which
+// readers announce by pinning around each probe.
+//
+// Pinning and reclamation work as in RcuIndexTable: a reader registers in
+// the counter of the current epoch's parity, and a writer that swapped
+// generations advances the epoch and waits for the previous parity to
+// drain, which takes a single probe. With the epoch feature, readers pin a
+// crossbeam-epoch epoch instead and the old generation goes to the epoch
+// collector, so growing never waits for readers.
+struct Seqlock {
+  seq: AtomicU64,
+  current: AtomicPtr<Mirror>,
+  #[cfg(not(feature = "epoch"))]
+  epoch: AtomicU64,
+  #[cfg(not(feature = "epoch"))]
+  readers: [AtomicUsize; 2],
+}
+
+// Keeps the generation loaded during a probe alive until dropped.
+#[cfg(not(feature = "epoch"))]
+struct ReadGuard<'a> {
+  readers: &'a AtomicUsize,
+}
+
+#[cfg(not(feature = "epoch"))]
+impl Drop for ReadGuard<'_> {
+  fn drop(&mut self) {
+    self.readers.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl Seqlock {
+  fn new() -> Seqlock {
+    Seqlock {
+      seq: AtomicU64::new(0),
+      current: AtomicPtr::new(Box::into_raw(Box::new(Mirror::new(MIRROR_SLOTS)))),
+      #[cfg(not(feature = "epoch"))]
+      epoch: AtomicU64::new(0),
+      #[cfg(not(feature = "epoch"))]
+      readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+    }
+  }
+
+  // The current generation. Callers either hold the guard of pin or the
+  // shard's write lock, either of which keeps it from being freed.
+  fn mirror(&self) -> &Mirror {
+    // SAFETY: current always comes from Box::into_raw and is only freed
+    // after every reader pinned while it was current has left.
+    unsafe { &*self.current.load(Ordering::SeqCst) }
+  }
+
+  #[cfg(feature = "epoch")]
+  fn pin(&self) -> crossbeam_epoch::Guard {
+    crossbeam_epoch::pin()
+  }
+
+  #[cfg(not(feature = "epoch"))]
+  fn pin(&self) -> ReadGuard<'_> {
+    loop {
+      let epoch = self.epoch.load(Ordering::SeqCst);
+      let readers = &self.readers[(epoch & 1) as usize];
+      readers.fetch_add(1, Ordering::SeqCst);
+      // Registered under an epoch a writer has already moved past, the
+      // writer waiting on that parity may have missed this reader.
+      if self.epoch.load(Ordering::SeqCst) == epoch {
+        return ReadGuard { readers };
+      }
+      readers.fetch_sub(1, Ordering::SeqCst);
+    }
+  }
+
+  // Opens a write, returning the sequence to pass to end_write. Callers
+  // hold the shard's write lock, so writes never overlap.
+  fn begin_write(&self) -> u64 {
+    let seq = self.seq.load(Ordering::Relaxed);
+    self.seq.store(seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    seq
+  }
+
+  fn end_write(&self, seq: u64) {
+    self.seq.store(seq + 2, Ordering::Release);
+  }
+
+  // Inserts or updates key. Needs an open write.
+  fn insert(&self, key: u64, v: u64) {
+    let mut mirror = self.mirror();
+    let home = mirror.home(key);
+    let index = match mirror.probe(home, |index| mirror.key(index) == key) {
+      (index, true) => {
+        mirror.values[(index >> 1) as usize].store(v, Ordering::Relaxed);
+        return;
+      }
+      (index, false) => index,
+    };
+    let len = mirror.len.load(Ordering::Relaxed);
+    let index = if len >= mirror.slots() / 8 * 7 {
+      mirror = self.grow(mirror);
+      mirror.probe(mirror.home(key), |_| false).0
+    } else {
+      index
+    };
+    mirror.occupy(index, key, v);
+    mirror.len.store(len + 1, Ordering::Relaxed);
+  }
+
+  // Removes key, if present. Needs an open write.
+  fn delete(&self, key: u64) {
+    let mut mirror = self.mirror();
+    if let (index, true) = mirror.probe(mirror.home(key), |index| mirror.key(index) == key) {
+      mirror.unshift(index);
+      let len = mirror.len.load(Ordering::Relaxed);
+      mirror.len.store(len - 1, Ordering::Relaxed);
+    }
+  }
+
+  // Swaps in a generation twice the size of old holding its entries, and
+  // retires old.
+  fn grow(&self, old: &Mirror) -> &Mirror {
+    let new = Mirror::new(old.slots() * 2);
+    for index in (0..old.slots() * 2).step_by(2) {
+      if old.slot_occupied(index) {
+        let key = old.key(index);
+        let free = (&new).probe(new.home(key), |_| false).0;
+        let value = old.values[(index >> 1) as usize].load(Ordering::Relaxed);
+        new.occupy(free, key, value);
+      }
+    }
+    new
+      .len
+      .store(old.len.load(Ordering::Relaxed), Ordering::Relaxed);
+    let new = Box::into_raw(Box::new(new));
+    let old = self.current.swap(new, Ordering::SeqCst);
+    self.retire(old);
+    // SAFETY: new is current and only this writer could replace it.
+    unsafe { &*new }
+  }
+
+  // Frees a replaced generation once no reader can be walking it. Readers
+  // pinned from here on load the new generation, so the collector only has
+  // to wait out the ones pinned already.
+  #[cfg(feature = "epoch")]
+  fn retire(&self, old: *mut Mirror) {
+    let guard = crossbeam_epoch::pin();
+    // SAFETY: old came from Box::into_raw and is unreachable for readers
+    // pinned after the swap; the closure owns everything it touches.
+    unsafe { guard.defer_unchecked(move || drop(Box::from_raw(old))) };
+    guard.flush();
+  }
+
+  #[cfg(not(feature = "epoch"))]
+  fn retire(&self, old: *mut Mirror) {
+    // Readers registering from here on load the new generation; wait out
+    // the ones that may still hold the old.
+    let epoch = self.epoch.fetch_add(1, Ordering::SeqCst);
+    while self.readers[(epoch & 1) as usize].load(Ordering::SeqCst) != 0 {
+      std::thread::yield_now();
+    }
+    // SAFETY: old came from Box::into_raw and no reader can reach it anymore.
+    drop(unsafe { Box::from_raw(old) });
+  }
+
+  // Looks key up optimistically. Returns None if every try overlapped a
+  // write, Some of the lookup otherwise.
+  fn read(&self, key: u64) -> Option<Option<u64>> {
+    for _ in 0..OPTIMISTIC_TRIES {
+      let seq = self.seq.load(Ordering::Acquire);
+      if seq & 1 == 1 {
+        core::hint::spin_loop();
+        continue;
+      }
+      let found = {
+        let _guard = self.pin();
+        self.mirror().read(key)
+      };
+      fence(Ordering::Acquire);
+      if self.seq.load(Ordering::Relaxed) == seq {
+        return Some(found);
+      }
+    }
+    None
+  }
+}
+
+impl Drop for Seqlock {
+  fn drop(&mut self) {
+    // SAFETY: the seqlock is exclusively borrowed, so no reader holds it.
+    drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+  }
+}
+
+// A shard on a cache line of its own, so readers locking neighbouring shards
+// do not contend on the line.
+#[repr(align(64))]
+struct Shard {
+  table: RwLock<OpenIndexTable>,
+  seqlock: Option<Seqlock>,
+  counters: ShardCounters,
+}
+
+// The parts of a shard a write needs, with its table locked.
+type Locked<'a> = (
+  RwLockWriteGuard<'a, OpenIndexTable>,
+  Option<&'a Seqlock>,
+  &'a ShardCounters,
+);
+
+impl Shard {
+  fn lock(&self) -> Locked<'_> {
+    (
+      self.table.write().unwrap(),
+      self.seqlock.as_ref(),
+      &self.counters,
+    )
+  }
+
+  fn insert((table, seqlock, counters): &mut Locked<'_>, key: u64, v: u64) {
+    counters.insert(table, key, v);
+    if let Some(seqlock) = seqlock {
+      seqlock.insert(key, v);
+    }
+  }
+
+  fn delete((table, seqlock, counters): &mut Locked<'_>, key: u64) -> Option<u64> {
+    if let Some(seqlock) = seqlock {
+      seqlock.delete(key);
+    }
+    let deleted = table.delete(key);
+    counters.delete(deleted.is_some());
+    deleted
+  }
+}
+
+// Read mostly table shared between threads. Keys are split over a power of
+// two of shards, each an OpenIndexTable behind its own RwLock: gets take a
+// shard's lock shared, so any number of them run at once, and writes take it
+// exclusively, holding up only readers of that one shard.
+//
+// with_lockfree_reads adds a seqlocked copy of every shard for
+// get_lockfree, which reads without touching the lock at all, at the price
+// of keeping every entry twice and writes updating both copies.
+pub struct SyncIndexTable {
+  shards: Vec<Shard>,
+  shift: u32,
+}
+
+// Every shard of a SyncIndexTable locked for writing, handed to the closure
+// of write_batch.
+pub struct WriteBatch<'a> {
+  shards: Vec<Locked<'a>>,
+  shift: u32,
+}
+
+impl WriteBatch<'_> {
+  pub fn get(&self, key: u64) -> Option<u64> {
+    let (table, _, counters) = &self.shards[shard_of(key, self.shift)];
+    let found = table.get(key);
+    counters.get(found.is_some());
+    found
+  }
+
+  pub fn insert(&mut self, key: u64, v: u64) {
+    Shard::insert(&mut self.shards[shard_of(key, self.shift)], key, v);
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    Shard::delete(&mut self.shards[shard_of(key, self.shift)], key)
+  }
+}
+
+impl SyncIndexTable {
+  // Creates a table of shards shards. Panics unless shards is a power of
+  // two.
+  pub fn new(shards: usize) -> SyncIndexTable {
+    assert!(
+      shards.is_power_of_two(),
+      "shard count must be a power of two"
+    );
+    SyncIndexTable {
+      shards: (0..shards)
+        .map(|_| {
+          let counters = ShardCounters::default();
+          Shard {
+            table: RwLock::new(counters.table()),
+            seqlock: None,
+            counters,
+          }
+        })
+        .collect(),
+      shift: 64 - shards.trailing_zeros(),
+    }
+  }
+
+  // Keeps a seqlocked copy of every shard, so get_lockfree can skip the
+  // lock. Entries already in the table are copied over.
+  pub fn with_lockfree_reads(mut self) -> SyncIndexTable {
+    for shard in &mut self.shards {
+      let seqlock = Seqlock::new();
+      for (key, v) in shard.table.get_mut().unwrap().iter() {
+        seqlock.insert(key, v);
+      }
+      shard.seqlock = Some(seqlock);
+    }
+    self
+  }
+
+  pub fn shard_count(&self) -> usize {
+    self.shards.len()
+  }
+
+  fn shard(&self, key: u64) -> &Shard {
+    &self.shards[shard_of(key, self.shift)]
+  }
+
+  // Sum of the shard lengths, each read under its own lock, so with writers
+  // running the total need not match any single moment.
+  pub fn len(&self) -> u64 {
+    self
+      .shards
+      .iter()
+      .map(|shard| shard.table.read().unwrap().len())
+      .sum()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, key: u64) -> Option<u64> {
+    let shard = self.shard(key);
+    let found = shard.table.read().unwrap().get(key);
+    shard.counters.get(found.is_some());
+    found
+  }
+
+  // Like get, but without taking the shard lock when lock free reads are
+  // on: the lookup runs against the shard's copy and is retried if a write
+  // overlapped it. After a few overlapping writes in a row it falls back to
+  // the lock, so a busy writer cannot starve readers. Without
+  // with_lockfree_reads this is get.
+  pub fn get_lockfree(&self, key: u64) -> Option<u64> {
+    let shard = self.shard(key);
+    if let Some(seqlock) = &shard.seqlock {
+      if let Some(found) = seqlock.read(key) {
+        shard.counters.get(found.is_some());
+        return found;
+      }
+    }
+    self.get(key)
+  }
+
+  pub fn insert(&self, key: u64, v: u64) {
+    let mut locked = self.shard(key).lock();
+    let seqlock = locked.1;
+    let seq = seqlock.map(Seqlock::begin_write);
+    Shard::insert(&mut locked, key, v);
+    if let (Some(seqlock), Some(seq)) = (seqlock, seq) {
+      seqlock.end_write(seq);
+    }
+  }
+
+  pub fn delete(&self, key: u64) -> Option<u64> {
+    let mut locked = self.shard(key).lock();
+    let seqlock = locked.1;
+    let seq = seqlock.map(Seqlock::begin_write);
+    let deleted = Shard::delete(&mut locked, key);
+    if let (Some(seqlock), Some(seq)) = (seqlock, seq) {
+      seqlock.end_write(seq);
+    }
+    deleted
+  }
+
+  // Runs f with every shard locked for writing, paying for the locks once
+  // however many writes f makes. Readers see either none or all of the
+  // batch; get_lockfree readers too, since the copies stay mid write until
+  // the batch ends. Shards are locked in order, so concurrent batches cannot
+  // deadlock.
+  pub fn write_batch<R>(&self, f: impl FnOnce(&mut WriteBatch<'_>) -> R) -> R {
+    let mut batch = WriteBatch {
+      shards: self.shards.iter().map(Shard::lock).collect(),
+      shift: self.shift,
+    };
+    let seqs: Vec<Option<u64>> = batch
+      .shards
+      .iter()
+      .map(|(_, seqlock, _)| seqlock.map(Seqlock::begin_write))
+      .collect();
+    let result = f(&mut batch);
+    for ((_, seqlock, _), seq) in batch.shards.iter().zip(seqs) {
+      if let (Some(seqlock), Some(seq)) = (seqlock, seq) {
+        seqlock.end_write(seq);
+      }
+    }
+    result
+  }
+
+  // Totals of the operations served so far, summed over the shards. The
+  // counters are bumped with relaxed increments and read one at a time, so
+  // with operations in flight the snapshot need not match any single
+  // moment.
+  pub fn counters(&self) -> OpCounters {
+    let mut total = OpCounters::default();
+    for shard in &self.shards {
+      shard.counters.add_to(&mut total);
+    }
+    total
+  }
+
+  pub fn reset_counters(&self) {
+    for shard in &self.shards {
+      shard.counters.reset();
+    }
+  }
+}
+
+impl Default for SyncIndexTable {
+  fn default() -> Self {
+    Self::new(16)
+  }
+}
+
+#[test]
+fn test_sync_table_is_send_and_sync() {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<SyncIndexTable>();
+}
+
+#[test]
+fn test_sync_table_single_threaded() {
+  for shards in [1, 4] {
+    let table = SyncIndexTable::new(shards);
+    assert!(table.is_empty());
+    for key in 0..100 {
+      table.insert(key, key + 1);
+    }
+    assert_eq!(table.delete(7), Some(8));
+    assert_eq!(table.delete(7), None);
+    assert_eq!(table.get(7), None);
+    assert_eq!(table.get(8), Some(9));
+    let removed = table.write_batch(|batch| {
+      for key in 100..200 {
+        batch.insert(key, key + 1);
+      }
+      assert_eq!(batch.get(150), Some(151));
+      (0..50).filter_map(|key| batch.delete(key)).count()
+    });
+    assert_eq!(removed, 49);
+    assert_eq!(table.len(), 150);
+    assert!((50..200).all(|key| table.get(key) == Some(key + 1)));
+  }
+}
+
+#[test]
+fn test_sync_table_counters() {
+  let table = SyncIndexTable::new(4).with_lockfree_reads();
+  std::thread::scope(|scope| {
+    for thread in 0..4u64 {
+      let table = &table;
+      scope.spawn(move || {
+        let keys = thread * 1000..(thread + 1) * 1000;
+        for key in keys.clone() {
+          table.insert(key, key);
+        }
+        // 500 overwrites and 250 hits through a batch.
+        table.write_batch(|batch| {
+          for key in keys.clone().take(500) {
+            batch.insert(key, key + 1);
+          }
+          for key in keys.clone().take(250) {
+            batch.get(key);
+          }
+        });
+        // 1000 hits split between both read paths, then 500 misses.
+        for key in keys.clone().chain(1_000_000..1_000_500) {
+          if key % 2 == 0 {
+            table.get(key);
+          } else {
+            table.get_lockfree(key);
+          }
+        }
+        // Only the first round of deletes removes anything.
+        for _ in 0..2 {
+          for key in keys.clone().take(250) {
+            table.delete(key);
+          }
+        }
+      });
+    }
+  });
+  let counters = table.counters();
+  assert_eq!(
+    counters,
+    OpCounters {
+      gets: 7000,
+      hits: 5000,
+      misses: 2000,
+      inserts: 6000,
+      overwrites: 2000,
+      deletes: 1000,
+      resizes: counters.resizes,
+    }
+  );
+  assert!(counters.resizes >= 4);
+  table.reset_counters();
+  assert_eq!(table.counters(), OpCounters::default());
+}
+
+#[test]
+fn test_sync_table_reader_heavy_stress() {
+  // Values pack the key in the high half and a round in the low half, so a
+  // read mixing two writes or landing on another key shows up.
+  let value = |key: u64, round: u64| key << 32 | round;
+  const KEYS: u64 = 512;
+  const ROUNDS: u64 = 200;
+  let table = SyncIndexTable::new(8);
+  let finished = AtomicU64::new(0);
+  let done = AtomicBool::new(false);
+  std::thread::scope(|scope| {
+    for reader in 0..6u64 {
+      let (table, finished, done) = (&table, &finished, &done);
+      scope.spawn(move || {
+        // Rounds only go up, so a key's value never goes back to an older
+        // round once this reader has seen a newer one.
+        let mut seen = [0u64; KEYS as usize];
+        let mut key = reader;
+        while !done.load(Ordering::Acquire) {
+          let floor = finished.load(Ordering::Acquire);
+          key = (key + 7) % KEYS;
+          let v = table.get(key).unwrap_or(0);
+          if floor > 0 || v != 0 {
+            assert_eq!(v >> 32, key);
+            let round = v & u32::MAX as u64;
+            assert!(round >= floor && round >= seen[key as usize]);
+            seen[key as usize] = round;
+          }
+        }
+      });
+    }
+    for round in 1..=ROUNDS {
+      if round % 2 == 0 {
+        table.write_batch(|batch| {
+          for key in 0..KEYS {
+            batch.insert(key, value(key, round));
+          }
+        });
+      } else {
+        for key in 0..KEYS {
+          table.insert(key, value(key, round));
+        }
+      }
+      finished.store(round, Ordering::Release);
+    }
+    done.store(true, Ordering::Release);
+  });
+  assert_eq!(table.len(), KEYS);
+  assert!((0..KEYS).all(|key| table.get(key) == Some(value(key, ROUNDS))));
+}
+
+#[test]
+fn test_sync_table_lockfree_reads_follow_writes() {
+  let table = SyncIndexTable::new(2);
+  table.insert(1, 10);
+  let table = table.with_lockfree_reads();
+  assert_eq!(table.get_lockfree(1), Some(10));
+  // Enough keys for several generations of every shard's copy, and deletes
+  // that shift entries back within them.
+  for key in 0..5000 {
+    table.insert(key, key * 3);
+  }
+  for key in (0..5000).step_by(3) {
+    assert_eq!(table.delete(key), Some(key * 3));
+  }
+  table.write_batch(|batch| {
+    batch.insert(5000, 1);
+    batch.delete(1);
+  });
+  for key in 0..=5000 {
+    assert_eq!(table.get_lockfree(key), table.get(key));
+  }
+  assert_eq!(table.get_lockfree(5001), None);
+  assert!(table.shards.iter().all(|shard| {
+    let seqlock = shard.seqlock.as_ref().unwrap();
+    seqlock.mirror().len.load(Ordering::Relaxed) == shard.table.read().unwrap().len()
+  }));
+}
+
+#[test]
+fn test_sync_table_lockfree_stress() {
+  // As in the reader heavy test, values carry their key and the round that
+  // wrote them.
+  let value = |key: u64, round: u64| key << 32 | round;
+  const KEYS: u64 = 4096;
+  const ROUNDS: u64 = 200;
+  let table = SyncIndexTable::new(4).with_lockfree_reads();
+  let round = AtomicU64::new(0);
+  let done = AtomicBool::new(false);
+  std::thread::scope(|scope| {
+    for reader in 0..6u64 {
+      let (table, round, done) = (&table, &round, &done);
+      scope.spawn(move || {
+        let mut key = reader;
+        while !done.load(Ordering::Acquire) {
+          key = (key + 13) % KEYS;
+          if let Some(v) = table.get_lockfree(key) {
+            // Read after the lookup, since the writer may have moved on
+            // before it; it may also be part way through the next round.
+            let latest = round.load(Ordering::Acquire) + 1;
+            assert_eq!(v >> 32, key);
+            assert!((1..=latest).contains(&(v & u32::MAX as u64)));
+          }
+        }
+      });
+    }
+    for current in 1..=ROUNDS {
+      // Deletes make entries move back, and the key range grows so the
+      // copies keep growing too.
+      let keys = KEYS.min(256 * current);
+      for key in 0..keys {
+        table.insert(key, value(key, current));
+      }
+      for key in (current % 5..keys).step_by(5) {
+        table.delete(key);
+      }
+      round.store(current, Ordering::Release);
+    }
+    done.store(true, Ordering::Release);
+  });
+  for key in 0..KEYS {
+    let expected = (key % 5 != ROUNDS % 5).then(|| value(key, ROUNDS));
+    assert_eq!(table.get_lockfree(key), expected);
+  }
+}
+
+#[test]
+fn test_sync_table_mirror_outlives_readers() {
+  let table = SyncIndexTable::new(1).with_lockfree_reads();
+  table.insert(0, 0);
+  let seqlock = table.shards[0].seqlock.as_ref().unwrap();
+  let pinned = AtomicBool::new(false);
+  let released = AtomicBool::new(false);
+  std::thread::scope(|scope| {
+    // A reader pinned on the first generation can keep walking it while the
+    // writer grows past it.
+    scope.spawn(|| {
+      let guard = seqlock.pin();
+      let first = seqlock.mirror();
+      pinned.store(true, Ordering::Release);
+      for _ in 0..1000 {
+        assert_eq!(first.read(0), Some(0));
+        std::thread::yield_now();
+      }
+      released.store(true, Ordering::Release);
+      drop(guard);
+    });
+    while !pinned.load(Ordering::Acquire) {
+      std::thread::yield_now();
+    }
+    for key in 1..100 {
+      table.insert(key, key);
+    }
+    // Without the epoch feature, growing waits for the reader.
+    #[cfg(not(feature = "epoch"))]
+    assert!(released.load(Ordering::Acquire));
+  });
+  assert!((0..100).all(|key| table.get_lockfree(key) == Some(key)));
+}
@@ -0,0 +1,82 @@
+// Lets the shared tests call insert whether or not it can fail.
+pub(crate) trait Inserted {
+  fn inserted(self);
+}
+
+impl Inserted for () {
+  fn inserted(self) {}
+}
+
+impl Inserted for Option<u64> {
+  fn inserted(self) {}
+}
+
+impl<E: core::fmt::Debug> Inserted for Result<(), E> {
+  fn inserted(self) {
+    self.unwrap()
+  }
+}
+
+// Tests shared by the u64 keyed tables. `$new` builds an empty table and `$key`
+// converts a non zero u64 into the table's key type.
+macro_rules! index_table_tests {
+  ($new:expr, $key:expr) => {
+    #[test]
+    fn test_table_insert() {
+      use crate::table_tests::Inserted;
+      let key = $key;
+      let mut table = $new;
+      table.insert(key(1), 2).inserted();
+      table.insert(key(2), 3).inserted();
+      table.insert(key(3), 4).inserted();
+      table.insert(key(4), 5).inserted();
+      assert_eq!(table.get(key(1)), Some(2));
+      assert_eq!(table.get(key(2)), Some(3));
+      assert_eq!(table.get(key(3)), Some(4));
+      assert_eq!(table.get(key(4)), Some(5));
+    }
+
+    #[test]
+    fn test_table_delete() {
+      use crate::table_tests::Inserted;
+      let key = $key;
+      let mut table = $new;
+      table.insert(key(1), 2).inserted();
+      table.insert(key(2), 3).inserted();
+      table.insert(key(3), 4).inserted();
+      table.insert(key(4), 5).inserted();
+      assert_eq!(table.get(key(1)), Some(2));
+      assert_eq!(table.get(key(2)), Some(3));
+      assert_eq!(table.get(key(3)), Some(4));
+      assert_eq!(table.get(key(4)), Some(5));
+      assert_eq!(table.delete(key(1)), Some(2));
+      assert_eq!(table.delete(key(2)), Some(3));
+      assert_eq!(table.delete(key(3)), Some(4));
+      assert_eq!(table.delete(key(4)), Some(5));
+      assert_eq!(table.get(key(1)), None);
+      assert_eq!(table.get(key(2)), None);
+      assert_eq!(table.get(key(3)), None);
+      assert_eq!(table.get(key(4)), None);
+    }
+
+    #[test]
+    fn test_table_grow_and_delete() {
+      use crate::table_tests::Inserted;
+      let key = $key;
+      let mut table = $new;
+      for i in 1..=5000 {
+        table.insert(key(i), i * 2).inserted();
+      }
+      for i in 1..=5000 {
+        assert_eq!(table.get(key(i)), Some(i * 2));
+      }
+      for i in (2..=5000).step_by(2) {
+        assert_eq!(table.delete(key(i)), Some(i * 2));
+      }
+      for i in 1..=5000 {
+        let expected = (i % 2 == 1).then_some(i * 2);
+        assert_eq!(table.get(key(i)), expected);
+      }
+    }
+  };
+}
@@ -0,0 +1,179 @@
+use crate::count_min_sketch::CountMinSketch;
+use crate::lru_table::LruTable;
+#[cfg(test)]
+use alloc::vec::Vec;
+
+// Window TinyLFU: new entries land in a small LRU window and only move into
+// the main region if the sketch estimates them more popular than the main
+// region's eviction victim.
+pub struct TinyLfuTable {
+  window: LruTable,
+  main: LruTable,
+  sketch: CountMinSketch,
+  samples: u64,
+  sample_size: u64,
+}
+
+impl TinyLfuTable {
+  pub fn new(capacity: u64) -> TinyLfuTable {
+    assert!(
+      capacity > 1,
+      "capacity must hold a window and a main region"
+    );
+    let window = (capacity / 100).max(1);
+    TinyLfuTable {
+      window: LruTable::new(window),
+      main: LruTable::new(capacity - window),
+      sketch: CountMinSketch::new(capacity, 4),
+      samples: 0,
+      sample_size: capacity * 10,
+    }
+  }
+
+  pub fn len(&self) -> u64 {
+    self.window.len() + self.main.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn capacity(&self) -> u64 {
+    self.window.capacity() + self.main.capacity()
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.window.contains_key(key) || self.main.contains_key(key)
+  }
+
+  // Reads the value of key without counting an access in the sketch or
+  // refreshing its recency.
+  pub fn peek(&self, key: u64) -> Option<u64> {
+    self.window.peek(key).or_else(|| self.main.peek(key))
+  }
+
+  fn record(&mut self, key: u64) {
+    self.sketch.add(key);
+    self.samples += 1;
+    if self.samples >= self.sample_size {
+      self.sketch.halve();
+      self.samples /= 2;
+    }
+  }
+
+  pub fn get(&mut self, key: u64) -> Option<u64> {
+    self.record(key);
+    self.window.get(key).or_else(|| self.main.get(key))
+  }
+
+  // Inserts or updates key, returning the entry evicted (or refused
+  // admission) to make room.
+  pub fn insert(&mut self, key: u64, value: u64) -> Option<(u64, u64)> {
+    self.record(key);
+    if self.main.contains_key(key) {
+      return self.main.insert(key, value);
+    }
+    let (candidate, candidate_value) = self.window.insert(key, value)?;
+    if self.main.len() < self.main.capacity() {
+      return self.main.insert(candidate, candidate_value);
+    }
+    let (victim, _) = self.main.lru()?;
+    if self.sketch.estimate(candidate) > self.sketch.estimate(victim) {
+      self.main.insert(candidate, candidate_value)
+    } else {
+      Some((candidate, candidate_value))
+    }
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    self.window.delete(key).or_else(|| self.main.delete(key))
+  }
+}
+
+#[cfg(test)]
+struct ZipfKeys {
+  cumulative: Vec<f64>,
+  state: u64,
+}
+
+#[cfg(test)]
+impl ZipfKeys {
+  fn new(n: u64, exponent: f64) -> ZipfKeys {
+    let mut sum = 0.0;
+    let cumulative = (1..=n)
+      .map(|rank| {
+        sum += 1.0 / (rank as f64).powf(exponent);
+        sum
+      })
+      .collect();
+    ZipfKeys {
+      cumulative,
+      state: 0x2545F4914F6CDD1D,
+    }
+  }
+
+  fn next(&mut self) -> u64 {
+    self.state ^= self.state << 13;
+    self.state ^= self.state >> 7;
+    self.state ^= self.state << 17;
+    let total = self.cumulative[self.cumulative.len() - 1];
+    let target = (self.state >> 11) as f64 / (1u64 << 53) as f64 * total;
+    self.cumulative.partition_point(|sum| *sum < target) as u64 + 1
+  }
+}
+
+#[test]
+fn test_tiny_lfu_beats_lru_on_zipf() {
+  let capacity = 200;
+  let mut keys = ZipfKeys::new(20_000, 0.9);
+  let mut lru = LruTable::new(capacity);
+  let mut lfu = TinyLfuTable::new(capacity);
+  let (mut lru_hits, mut lfu_hits) = (0, 0);
+  for _ in 0..200_000 {
+    let key = keys.next();
+    if lru.get(key).is_some() {
+      lru_hits += 1;
+    } else {
+      lru.insert(key, key);
+    }
+    if lfu.get(key).is_some() {
+      lfu_hits += 1;
+    } else {
+      lfu.insert(key, key);
+    }
+  }
+  assert!(lfu.len() <= capacity);
+  assert!(
+    lfu_hits > lru_hits,
+    "tiny lfu {} hits, lru {} hits",
+    lfu_hits,
+    lru_hits
+  );
+}
+
+#[test]
+fn test_tiny_lfu_admission() {
+  let mut lfu = TinyLfuTable::new(10);
+  for key in 1..=10 {
+    lfu.insert(key, key);
+  }
+  assert_eq!(lfu.len(), 10);
+  for _ in 0..5 {
+    for key in 2..=10 {
+      lfu.get(key);
+    }
+  }
+  // The window's previous entry is popular enough to replace the cold key 1.
+  assert_eq!(lfu.insert(11, 11), Some((1, 1)));
+  // Key 11 was seen once and cannot displace frequently used ones.
+  assert_eq!(lfu.insert(12, 12), Some((11, 11)));
+  assert!(!lfu.contains_key(11));
+  for _ in 0..10 {
+    lfu.get(12);
+  }
+  lfu.insert(13, 13);
+  assert!(lfu.contains_key(12));
+  assert_eq!(lfu.len(), 10);
+  assert_eq!(lfu.delete(12), Some(12));
+  assert_eq!(lfu.len(), 9);
+}
@@ -0,0 +1,528 @@
+use crate::lru_table::EvictFn;
+use crate::open_index_table::OpenIndexTable;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use std::boxed::Box;
+use std::sync::Arc;
+use std::time::Instant;
+use std::vec::Vec;
+
+// Monotonic time source measured from an arbitrary starting point.
+pub trait Clock {
+  fn now(&self) -> Duration;
+}
+
+pub struct SystemClock {
+  start: Instant,
+}
+
+impl SystemClock {
+  pub fn new() -> SystemClock {
+    SystemClock {
+      start: Instant::now(),
+    }
+  }
+}
+
+impl Default for SystemClock {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Clock for SystemClock {
+  fn now(&self) -> Duration {
+    self.start.elapsed()
+  }
+}
+
+// Clock that only moves when told to, for tests and simulations.
+#[derive(Default)]
+pub struct ManualClock {
+  nanos: AtomicU64,
+}
+
+impl ManualClock {
+  pub fn new() -> ManualClock {
+    ManualClock::default()
+  }
+
+  pub fn advance(&self, by: Duration) {
+    self.nanos.fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+  }
+
+  pub fn set(&self, now: Duration) {
+    self.nanos.store(now.as_nanos() as u64, Ordering::SeqCst);
+  }
+}
+
+impl Clock for ManualClock {
+  fn now(&self) -> Duration {
+    Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+  }
+}
+
+impl<C: Clock> Clock for Arc<C> {
+  fn now(&self) -> Duration {
+    (**self).now()
+  }
+}
+
+struct TtlEntry {
+  key: u64,
+  value: u64,
+  expires_at: Duration,
+  last_access: Duration,
+  // Set for entries expiring after being idle this long instead of at
+  // expires_at.
+  idle: Option<Duration>,
+  refreshing: bool,
+  negative: bool,
+}
+
+impl TtlEntry {
+  fn deadline(&self) -> Duration {
+    match self.idle {
+      Some(idle) => self.last_access + idle,
+      None => self.expires_at,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lookup {
+  Hit(u64),
+  // The key is cached as known to be absent from the backing store.
+  Negative,
+  Miss,
+}
+
+impl Lookup {
+  pub fn value(self) -> Option<u64> {
+    match self {
+      Lookup::Hit(value) => Some(value),
+      _ => None,
+    }
+  }
+}
+
+type RefreshFn = Box<dyn FnMut(u64, u64) + Send>;
+
+// Table whose entries expire a fixed time after they were inserted, or for
+// entries inserted with insert_with_tti, after they were last read. Expired
+// entries read as absent and are dropped lazily on access or by
+// purge_expired.
+pub struct TtlTable<C: Clock = SystemClock> {
+  table: OpenIndexTable,
+  entries: Vec<TtlEntry>,
+  free_entries: Vec<u64>,
+  clock: C,
+  refresh_window: Duration,
+  refresh: Option<RefreshFn>,
+  on_evict: Option<EvictFn>,
+  notify_deletes: bool,
+}
+
+impl TtlTable<SystemClock> {
+  pub fn new() -> TtlTable<SystemClock> {
+    TtlTable::with_clock(SystemClock::new())
+  }
+}
+
+impl Default for TtlTable<SystemClock> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<C: Clock> TtlTable<C> {
+  pub fn with_clock(clock: C) -> TtlTable<C> {
+    TtlTable {
+      table: OpenIndexTable::new(),
+      entries: Vec::new(),
+      free_entries: Vec::new(),
+      clock,
+      refresh_window: Duration::ZERO,
+      refresh: None,
+      on_evict: None,
+      notify_deletes: false,
+    }
+  }
+
+  // Calls callback(key, value) once for every entry that leaves the table
+  // after expiring or through clear. Negative entries carry no value and are
+  // skipped.
+  pub fn on_evict(mut self, callback: impl FnMut(u64, u64) + Send + 'static) -> TtlTable<C> {
+    self.on_evict = Some(Box::new(callback));
+    self
+  }
+
+  // Also calls the eviction callback for live entries removed by delete.
+  pub fn notify_deletes(mut self) -> TtlTable<C> {
+    self.notify_deletes = true;
+    self
+  }
+
+  // Enables refresh-ahead: a get that hits an entry expiring within window
+  // calls refresh(key, stale_value) and still returns the stale value. The
+  // callback is expected to start a reload that ends in insert; until then no
+  // further refresh is requested for that key.
+  pub fn with_refresh_ahead(
+    mut self,
+    window: Duration,
+    refresh: impl FnMut(u64, u64) + Send + 'static,
+  ) -> TtlTable<C> {
+    self.refresh_window = window;
+    self.refresh = Some(Box::new(refresh));
+    self
+  }
+
+  pub fn len(&self) -> u64 {
+    self.entries.len() as u64 - self.free_entries.len() as u64
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn insert(&mut self, key: u64, value: u64, ttl: Duration) {
+    self.insert_entry(key, value, ttl, None, false);
+  }
+
+  // Inserts key to expire once it has gone idle long without a get. Every
+  // hit moves the deadline to now + idle. Such entries never trigger
+  // refresh-ahead, since reading them is what keeps them alive.
+  pub fn insert_with_tti(&mut self, key: u64, value: u64, idle: Duration) {
+    self.insert_entry(key, value, idle, Some(idle), false);
+  }
+
+  // Caches key as absent for ttl so repeated lookups of keys missing from the
+  // backing store can be answered without querying it.
+  pub fn insert_negative(&mut self, key: u64, ttl: Duration) {
+    self.insert_entry(key, 0, ttl, None, true);
+  }
+
+  fn insert_entry(
+    &mut self,
+    key: u64,
+    value: u64,
+    ttl: Duration,
+    idle: Option<Duration>,
+    negative: bool,
+  ) {
+    let now = self.clock.now();
+    let entry = TtlEntry {
+      key,
+      value,
+      expires_at: now + ttl,
+      last_access: now,
+      idle,
+      refreshing: false,
+      negative,
+    };
+    match self.table.get(key) {
+      Some(index) => {
+        if self.entries[index as usize].deadline() <= now {
+          self.notify(index);
+        }
+        self.entries[index as usize] = entry;
+      }
+      None => {
+        let index = match self.free_entries.pop() {
+          Some(index) => {
+            self.entries[index as usize] = entry;
+            index
+          }
+          None => {
+            self.entries.push(entry);
+            self.entries.len() as u64 - 1
+          }
+        };
+        self.table.insert(key, index);
+      }
+    }
+  }
+
+  pub fn get(&mut self, key: u64) -> Lookup {
+    let Some(index) = self.table.get(key) else {
+      return Lookup::Miss;
+    };
+    let now = self.clock.now();
+    let entry = &mut self.entries[index as usize];
+    if entry.deadline() <= now {
+      self.notify(index);
+      self.remove(key, index);
+      return Lookup::Miss;
+    }
+    if entry.negative {
+      return Lookup::Negative;
+    }
+    let value = entry.value;
+    if entry.idle.is_some() {
+      entry.last_access = now;
+    } else if let Some(refresh) = &mut self.refresh {
+      if !entry.refreshing && now + self.refresh_window >= entry.expires_at {
+        entry.refreshing = true;
+        refresh(key, value);
+      }
+    }
+    Lookup::Hit(value)
+  }
+
+  // Returns the value of key and moves its expiry to now + ttl, giving it a
+  // fresh lifetime; an entry inserted with insert_with_tti gets ttl as its
+  // idle time instead. Expired and negative entries read as absent, as with
+  // get, and are not extended. Unlike get, never triggers refresh-ahead,
+  // and a refresh already requested may be requested again once the new
+  // expiry comes close.
+  pub fn get_and_touch(&mut self, key: u64, ttl: Duration) -> Option<u64> {
+    let index = self.table.get(key)?;
+    let now = self.clock.now();
+    let entry = &mut self.entries[index as usize];
+    if entry.deadline() <= now {
+      self.notify(index);
+      self.remove(key, index);
+      return None;
+    }
+    if entry.negative {
+      return None;
+    }
+    match &mut entry.idle {
+      Some(idle) => *idle = ttl,
+      None => entry.expires_at = now + ttl,
+    }
+    entry.last_access = now;
+    entry.refreshing = false;
+    Some(entry.value)
+  }
+
+  pub fn delete(&mut self, key: u64) -> Option<u64> {
+    let index = self.table.get(key)?;
+    let entry = &self.entries[index as usize];
+    let live = entry.deadline() > self.clock.now() && !entry.negative;
+    let value = entry.value;
+    if !live || self.notify_deletes {
+      self.notify(index);
+    }
+    self.remove(key, index);
+    if live {
+      Some(value)
+    } else {
+      None
+    }
+  }
+
+  fn remove(&mut self, key: u64, index: u64) {
+    self.table.delete(key);
+    self.free_entries.push(index);
+  }
+
+  fn notify(&mut self, index: u64) {
+    let entry = &self.entries[index as usize];
+    if let (Some(on_evict), false) = (&mut self.on_evict, entry.negative) {
+      on_evict(entry.key, entry.value);
+    }
+  }
+
+  // Removes every entry.
+  pub fn clear(&mut self) {
+    for index in 0..self.entries.len() {
+      let key = self.entries[index].key;
+      if self.table.get(key) == Some(index as u64) {
+        self.notify(index as u64);
+        self.remove(key, index as u64);
+      }
+    }
+  }
+
+  // Drops every expired entry, returning how many were removed.
+  pub fn purge_expired(&mut self) -> u64 {
+    let now = self.clock.now();
+    let mut purged = 0;
+    for index in 0..self.entries.len() {
+      let entry = &self.entries[index];
+      if entry.deadline() <= now && self.table.get(entry.key) == Some(index as u64) {
+        let key = entry.key;
+        self.notify(index as u64);
+        self.remove(key, index as u64);
+        purged += 1;
+      }
+    }
+    purged
+  }
+}
+
+#[cfg(test)]
+use std::sync::Mutex;
+#[cfg(test)]
+use std::vec;
+
+#[test]
+fn test_ttl_expiry() {
+  let clock = Arc::new(ManualClock::new());
+  let mut table = TtlTable::with_clock(clock.clone());
+  table.insert(1, 10, Duration::from_secs(5));
+  table.insert(2, 20, Duration::from_secs(10));
+  assert_eq!(table.get(1), Lookup::Hit(10));
+  clock.advance(Duration::from_secs(5));
+  assert_eq!(table.get(1), Lookup::Miss);
+  assert_eq!(table.get(2), Lookup::Hit(20));
+  assert_eq!(table.len(), 1);
+  table.insert(3, 30, Duration::from_secs(1));
+  clock.advance(Duration::from_secs(5));
+  assert_eq!(table.purge_expired(), 2);
+  assert!(table.is_empty());
+  table.insert(4, 40, Duration::from_secs(1));
+  assert_eq!(table.delete(4), Some(40));
+  assert_eq!(table.delete(4), None);
+}
+
+#[test]
+fn test_get_and_touch_extends_expiry() {
+  let clock = Arc::new(ManualClock::new());
+  let mut table = TtlTable::with_clock(clock.clone());
+  table.insert(1, 10, Duration::from_secs(5));
+  table.insert(2, 20, Duration::from_secs(5));
+  table.insert_negative(3, Duration::from_secs(5));
+  clock.advance(Duration::from_secs(4));
+  assert_eq!(table.get_and_touch(1, Duration::from_secs(10)), Some(10));
+  assert_eq!(table.get_and_touch(3, Duration::from_secs(10)), None);
+  assert_eq!(table.get_and_touch(4, Duration::from_secs(10)), None);
+  // Past the original expiry only the touched entry is left.
+  clock.advance(Duration::from_secs(6));
+  assert_eq!(table.get(1), Lookup::Hit(10));
+  assert_eq!(table.get(2), Lookup::Miss);
+  assert_eq!(table.get(3), Lookup::Miss);
+  // Expired entries are not brought back.
+  clock.advance(Duration::from_secs(4));
+  assert_eq!(table.get_and_touch(1, Duration::from_secs(10)), None);
+  assert!(table.is_empty());
+  // Idle entries take the new time as their idle timeout.
+  table.insert_with_tti(5, 50, Duration::from_secs(2));
+  assert_eq!(table.get_and_touch(5, Duration::from_secs(8)), Some(50));
+  clock.advance(Duration::from_secs(7));
+  assert_eq!(table.get(5), Lookup::Hit(50));
+}
+
+#[test]
+fn test_tti_expires_when_idle() {
+  let clock = Arc::new(ManualClock::new());
+  let refreshed = Arc::new(Mutex::new(Vec::new()));
+  let log = refreshed.clone();
+  let mut table = TtlTable::with_clock(clock.clone())
+    .with_refresh_ahead(Duration::from_secs(5), move |key, value| {
+      log.lock().unwrap().push((key, value))
+    });
+  table.insert_with_tti(1, 10, Duration::from_secs(3));
+  table.insert_with_tti(2, 20, Duration::from_secs(3));
+  // Reading key 1 every two seconds keeps it alive well past its idle window.
+  for _ in 0..10 {
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(table.get(1), Lookup::Hit(10));
+  }
+  assert_eq!(table.get(2), Lookup::Miss);
+  clock.advance(Duration::from_millis(2999));
+  assert_eq!(table.purge_expired(), 0);
+  clock.advance(Duration::from_millis(1));
+  assert_eq!(table.purge_expired(), 1);
+  assert_eq!(table.get(1), Lookup::Miss);
+  // Reinserting with a plain TTL makes reads stop extending the entry.
+  table.insert(1, 11, Duration::from_secs(3));
+  table.insert_with_tti(3, 30, Duration::from_secs(3));
+  clock.advance(Duration::from_secs(2));
+  assert_eq!(table.get(1), Lookup::Hit(11));
+  assert_eq!(table.get(3), Lookup::Hit(30));
+  clock.advance(Duration::from_secs(2));
+  assert_eq!(table.get(1), Lookup::Miss);
+  assert_eq!(table.delete(3), Some(30));
+  // Only the plain TTL entry was ever refreshed.
+  assert_eq!(*refreshed.lock().unwrap(), vec![(1, 11)]);
+}
+
+#[test]
+fn test_refresh_ahead_fires_once_in_window() {
+  let clock = Arc::new(ManualClock::new());
+  let refreshed = Arc::new(Mutex::new(Vec::new()));
+  let log = refreshed.clone();
+  let mut table = TtlTable::with_clock(clock.clone())
+    .with_refresh_ahead(Duration::from_secs(2), move |key, value| {
+      log.lock().unwrap().push((key, value))
+    });
+  table.insert(1, 10, Duration::from_secs(10));
+  clock.set(Duration::from_secs(7));
+  assert_eq!(table.get(1), Lookup::Hit(10));
+  assert!(refreshed.lock().unwrap().is_empty());
+  clock.set(Duration::from_secs(8));
+  assert_eq!(table.get(1), Lookup::Hit(10));
+  clock.set(Duration::from_secs(9));
+  assert_eq!(table.get(1), Lookup::Hit(10));
+  assert_eq!(*refreshed.lock().unwrap(), vec![(1, 10)]);
+  // The refresh completes and the next window triggers a new refresh.
+  table.insert(1, 11, Duration::from_secs(10));
+  clock.set(Duration::from_secs(17));
+  assert_eq!(table.get(1), Lookup::Hit(11));
+  assert_eq!(table.get(1), Lookup::Hit(11));
+  assert_eq!(*refreshed.lock().unwrap(), vec![(1, 10), (1, 11)]);
+  clock.set(Duration::from_secs(19));
+  assert_eq!(table.get(1), Lookup::Miss);
+}
+
+#[test]
+fn test_negative_entry_suppresses_misses() {
+  let clock = Arc::new(ManualClock::new());
+  let mut table = TtlTable::with_clock(clock.clone());
+  let mut backend_queries = 0;
+  let mut lookup = |table: &mut TtlTable<Arc<ManualClock>>, key| match table.get(key) {
+    Lookup::Hit(value) => Some(value),
+    Lookup::Negative => None,
+    Lookup::Miss => {
+      backend_queries += 1;
+      table.insert_negative(key, Duration::from_secs(1));
+      None
+    }
+  };
+  table.insert(1, 10, Duration::from_secs(60));
+  assert_eq!(lookup(&mut table, 1), Some(10));
+  assert_eq!(lookup(&mut table, 2), None);
+  assert_eq!(lookup(&mut table, 2), None);
+  clock.advance(Duration::from_millis(999));
+  assert_eq!(lookup(&mut table, 2), None);
+  assert_eq!(lookup(&mut table, 1), Some(10));
+  clock.advance(Duration::from_millis(1));
+  // The negative entry lapsed while the real one is still live.
+  assert_eq!(lookup(&mut table, 2), None);
+  assert_eq!(lookup(&mut table, 1), Some(10));
+  assert_eq!(backend_queries, 2);
+  assert_eq!(table.get(2), Lookup::Negative);
+  assert_eq!(table.delete(2), None);
+  assert_eq!(table.get(2), Lookup::Miss);
+  assert_eq!(Lookup::Hit(3).value(), Some(3));
+  assert_eq!(Lookup::Negative.value(), None);
+}
+
+#[test]
+fn test_ttl_on_evict() {
+  let clock = Arc::new(ManualClock::new());
+  let evicted = Arc::new(Mutex::new(Vec::new()));
+  let log = evicted.clone();
+  let mut table = TtlTable::with_clock(clock.clone())
+    .on_evict(move |key, value| log.lock().unwrap().push((key, value)));
+  for key in 1..=5 {
+    table.insert(key, key * 10, Duration::from_secs(key));
+  }
+  table.insert_negative(6, Duration::from_secs(1));
+  clock.set(Duration::from_secs(2));
+  // Expired entries are reported whichever way they are dropped.
+  assert_eq!(table.get(1), Lookup::Miss);
+  assert_eq!(table.get(1), Lookup::Miss);
+  table.insert(2, 21, Duration::from_secs(10));
+  assert_eq!(table.delete(4), Some(40));
+  clock.set(Duration::from_secs(3));
+  assert_eq!(table.purge_expired(), 2);
+  assert_eq!(*evicted.lock().unwrap(), vec![(1, 10), (2, 20), (3, 30)]);
+  table.clear();
+  assert!(table.is_empty());
+  let mut evicted = evicted.lock().unwrap().clone();
+  evicted.sort();
+  assert_eq!(evicted, vec![(1, 10), (2, 20), (2, 21), (3, 30), (5, 50)]);
+}
@@ -0,0 +1,198 @@
+use cacher_memtable::open_index_table::OpenIndexTable;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const PUT: u8 = 1;
+const TOMBSTONE: u8 = 2;
+// key, value, tag and checksum.
+const RECORD_LEN: usize = 8 + 8 + 1 + 4;
+
+// Append only log of key value records with an in-memory index from key to
+// the offset of its latest record. Every record carries a checksum, so a
+// record torn by a crash is detected on open and cut off along with anything
+// after it; all records before it are recovered.
+pub struct LogStore {
+  file: File,
+  index: OpenIndexTable,
+  end: u64,
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+  // FNV-1a.
+  bytes.iter().fold(0x811c_9dc5, |hash: u32, byte| {
+    (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+  })
+}
+
+fn encode(key: u64, value: u64, tag: u8) -> [u8; RECORD_LEN] {
+  let mut record = [0; RECORD_LEN];
+  record[..8].copy_from_slice(&key.to_le_bytes());
+  record[8..16].copy_from_slice(&value.to_le_bytes());
+  record[16] = tag;
+  let sum = checksum(&record[..17]);
+  record[17..].copy_from_slice(&sum.to_le_bytes());
+  record
+}
+
+// Returns key, value and tag, or None if the record is corrupt.
+fn decode(record: &[u8; RECORD_LEN]) -> Option<(u64, u64, u8)> {
+  let sum = u32::from_le_bytes(record[17..].try_into().unwrap());
+  if sum != checksum(&record[..17]) || !matches!(record[16], PUT | TOMBSTONE) {
+    return None;
+  }
+  let key = u64::from_le_bytes(record[..8].try_into().unwrap());
+  let value = u64::from_le_bytes(record[8..16].try_into().unwrap());
+  Some((key, value, record[16]))
+}
+
+impl LogStore {
+  // Opens the log at path, creating it if needed, and rebuilds the index by
+  // replaying it.
+  pub fn open(path: impl AsRef<Path>) -> io::Result<LogStore> {
+    let mut file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(path)?;
+    let mut index = OpenIndexTable::new();
+    let mut end = 0;
+    let mut reader = BufReader::new(&mut file);
+    let mut record = [0; RECORD_LEN];
+    loop {
+      match reader.read_exact(&mut record) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+        Err(err) => return Err(err),
+      }
+      match decode(&record) {
+        Some((key, _, PUT)) => {
+          index.insert(key, end);
+        }
+        Some((key, _, _)) => {
+          index.delete(key);
+        }
+        None => break,
+      }
+      end += RECORD_LEN as u64;
+    }
+    drop(reader);
+    file.set_len(end)?;
+    Ok(LogStore { file, index, end })
+  }
+
+  pub fn len(&self) -> u64 {
+    self.index.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.index.is_empty()
+  }
+
+  pub fn contains_key(&self, key: u64) -> bool {
+    self.index.get(key).is_some()
+  }
+
+  pub fn get(&mut self, key: u64) -> io::Result<Option<u64>> {
+    let Some(offset) = self.index.get(key) else {
+      return Ok(None);
+    };
+    let mut record = [0; RECORD_LEN];
+    self.file.seek(SeekFrom::Start(offset))?;
+    self.file.read_exact(&mut record)?;
+    match decode(&record) {
+      Some((stored, value, PUT)) if stored == key => Ok(Some(value)),
+      _ => Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "log record does not match its index entry",
+      )),
+    }
+  }
+
+  pub fn put(&mut self, key: u64, value: u64) -> io::Result<()> {
+    self.append(&encode(key, value, PUT))?;
+    self.index.insert(key, self.end - RECORD_LEN as u64);
+    Ok(())
+  }
+
+  // Removes key, returning its value if it was stored.
+  pub fn remove(&mut self, key: u64) -> io::Result<Option<u64>> {
+    let value = self.get(key)?;
+    if value.is_some() {
+      self.append(&encode(key, 0, TOMBSTONE))?;
+      self.index.delete(key);
+    }
+    Ok(value)
+  }
+
+  // Flushes appended records to the disk.
+  pub fn sync(&self) -> io::Result<()> {
+    self.file.sync_data()
+  }
+
+  fn append(&mut self, record: &[u8; RECORD_LEN]) -> io::Result<()> {
+    self.file.seek(SeekFrom::Start(self.end))?;
+    self.file.write_all(record)?;
+    self.end += RECORD_LEN as u64;
+    Ok(())
+  }
+}
+
+// A file path in the temp directory that is removed on drop.
+#[cfg(test)]
+pub(crate) struct TempPath(pub(crate) std::path::PathBuf);
+
+#[cfg(test)]
+impl TempPath {
+  pub(crate) fn new(name: &str) -> TempPath {
+    let file = format!("cacher-{}-{}", std::process::id(), name);
+    TempPath(std::env::temp_dir().join(file))
+  }
+}
+
+#[cfg(test)]
+impl Drop for TempPath {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.0);
+  }
+}
+
+#[test]
+fn test_log_store_reopen() {
+  let path = TempPath::new("log-store-reopen");
+  let mut store = LogStore::open(&path.0).unwrap();
+  for key in 0..100 {
+    store.put(key, key * 2).unwrap();
+  }
+  store.put(5, 55).unwrap();
+  assert_eq!(store.remove(6).unwrap(), Some(12));
+  assert_eq!(store.remove(6).unwrap(), None);
+  drop(store);
+  let mut store = LogStore::open(&path.0).unwrap();
+  assert_eq!(store.len(), 99);
+  assert_eq!(store.get(5).unwrap(), Some(55));
+  assert_eq!(store.get(6).unwrap(), None);
+  assert_eq!(store.get(99).unwrap(), Some(198));
+}
+
+#[test]
+fn test_log_store_drops_torn_tail() {
+  let path = TempPath::new("log-store-torn");
+  let mut store = LogStore::open(&path.0).unwrap();
+  store.put(1, 10).unwrap();
+  store.put(2, 20).unwrap();
+  drop(store);
+  // Simulate a crash in the middle of appending a third record.
+  let mut file = OpenOptions::new().append(true).open(&path.0).unwrap();
+  file.write_all(&encode(3, 30, PUT)[..10]).unwrap();
+  drop(file);
+  let mut store = LogStore::open(&path.0).unwrap();
+  assert_eq!(store.len(), 2);
+  assert_eq!(store.get(3).unwrap(), None);
+  store.put(3, 31).unwrap();
+  drop(store);
+  let mut store = LogStore::open(&path.0).unwrap();
+  assert_eq!(store.get(2).unwrap(), Some(20));
+  assert_eq!(store.get(3).unwrap(), Some(31));
+}
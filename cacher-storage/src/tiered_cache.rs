@@ -0,0 +1,103 @@
+use crate::log_store::LogStore;
+use cacher_memtable::lru_table::LruTable;
+use std::io;
+use std::path::Path;
+
+// Cache with a bounded in-memory tier in front of an on-disk log. Entries
+// evicted from memory are written to the log, and a memory miss that hits
+// the log moves the entry back into memory. Every key lives in exactly one
+// tier. The log is never compacted, so it grows with every spill.
+pub struct TieredCache {
+  hot: LruTable,
+  cold: LogStore,
+}
+
+impl TieredCache {
+  // Opens a cache keeping up to hot_capacity entries in memory and spilling
+  // the rest to the log at path.
+  pub fn open(hot_capacity: u64, path: impl AsRef<Path>) -> io::Result<TieredCache> {
+    Ok(TieredCache {
+      hot: LruTable::new(hot_capacity),
+      cold: LogStore::open(path)?,
+    })
+  }
+
+  pub fn len(&self) -> u64 {
+    self.hot.len() + self.cold.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Whether key is held by the in-memory tier.
+  pub fn is_hot(&self, key: u64) -> bool {
+    self.hot.contains_key(key)
+  }
+
+  pub fn get(&mut self, key: u64) -> io::Result<Option<u64>> {
+    if let Some(value) = self.hot.get(key) {
+      return Ok(Some(value));
+    }
+    let Some(value) = self.cold.remove(key)? else {
+      return Ok(None);
+    };
+    self.insert_hot(key, value)?;
+    Ok(Some(value))
+  }
+
+  pub fn insert(&mut self, key: u64, value: u64) -> io::Result<()> {
+    if self.cold.contains_key(key) {
+      self.cold.remove(key)?;
+    }
+    self.insert_hot(key, value)
+  }
+
+  pub fn delete(&mut self, key: u64) -> io::Result<Option<u64>> {
+    match self.hot.delete(key) {
+      Some(value) => Ok(Some(value)),
+      None => self.cold.remove(key),
+    }
+  }
+
+  // Flushes spilled entries to the disk.
+  pub fn sync(&self) -> io::Result<()> {
+    self.cold.sync()
+  }
+
+  fn insert_hot(&mut self, key: u64, value: u64) -> io::Result<()> {
+    if let Some((evicted, evicted_value)) = self.hot.insert(key, value) {
+      self.cold.put(evicted, evicted_value)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+use crate::log_store::TempPath;
+
+#[test]
+fn test_tiered_cache_spills_and_promotes() {
+  let path = TempPath::new("tiered-cache");
+  let mut cache = TieredCache::open(10, &path.0).unwrap();
+  for key in 0..100 {
+    cache.insert(key, key + 1).unwrap();
+  }
+  assert_eq!(cache.len(), 100);
+  assert!(!cache.is_hot(0));
+  assert_eq!(cache.get(0).unwrap(), Some(1));
+  assert!(cache.is_hot(0));
+  assert_eq!(cache.len(), 100);
+  for key in 0..100 {
+    assert_eq!(cache.get(key).unwrap(), Some(key + 1));
+  }
+  cache.insert(50, 7).unwrap();
+  assert_eq!(cache.delete(51).unwrap(), Some(52));
+  assert_eq!(cache.get(51).unwrap(), None);
+  drop(cache);
+  // Entries that were on disk survive a restart.
+  let mut cache = TieredCache::open(10, &path.0).unwrap();
+  assert_eq!(cache.len(), 89);
+  assert_eq!(cache.get(1).unwrap(), Some(2));
+  assert_eq!(cache.get(95).unwrap(), None);
+}
@@ -0,0 +1,20 @@
+#!/bin/sh
+# Runs the tests of the tables that free memory behind lock free readers
+# under AddressSanitizer, with and without the epoch feature. A reader
+# walking a generation after it was freed fails as a use after free, and
+# generations that are never freed show up as leaks.
+#
+# Needs a nightly toolchain. The explicit --target keeps the sanitizer off
+# build scripts and proc macros, and the separate target directory keeps
+# the instrumented build from evicting the normal one.
+set -eu
+cd "$(dirname "$0")/.."
+export RUSTFLAGS="-Zsanitizer=address"
+export RUSTDOCFLAGS="-Zsanitizer=address"
+export CARGO_TARGET_DIR=target/asan
+target=$(rustc +nightly -vV | sed -n 's/^host: //p')
+for features in "" "--features epoch"; do
+  # shellcheck disable=SC2086
+  cargo +nightly test -p cacher-memtable --target "$target" $features --lib -- \
+    rcu_index_table spmc_index_table sync_index_table
+done